@@ -0,0 +1,202 @@
+//! Compares storages of `Mask` on highlighting of real source files.
+//!
+//! Run with `cargo bench --bench mask`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use yatui_text::{
+    mask::{MaskBuilder, StoragePolicy},
+    Color, IdxRange, Mask, Modifier, Style,
+};
+
+const SOURCES: [&str; 4] = [
+    include_str!("../src/ansi.rs"),
+    include_str!("../src/mask/mod.rs"),
+    include_str!("../src/style.rs"),
+    include_str!("../src/theme.rs"),
+];
+
+const POLICIES: [StoragePolicy; 3] =
+    [StoragePolicy::Auto, StoragePolicy::Vec, StoragePolicy::BTree];
+
+const KEYWORDS: [&str; 16] = [
+    "fn", "let", "mut", "pub", "impl", "struct", "enum", "match", "if", "else", "for", "in", "use",
+    "return", "self", "Self",
+];
+
+/// Splits `src` into tokens in the way simple syntax highlighter does and returns styled ranges
+/// of chars. Whitespace and punctuation are not styled.
+fn highlight(src: &str) -> Vec<(IdxRange, Style)> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        let start = idx;
+        let c = chars[idx];
+        let style = if c == '/' && chars.get(idx + 1) == Some(&'/') {
+            while idx < chars.len() && chars[idx] != '\n' {
+                idx += 1;
+            }
+            Style::new().fg(Color::BrightBlack).modifier(Modifier::ITALIC)
+        } else if c == '"' {
+            idx += 1;
+            while idx < chars.len() && chars[idx] != '"' {
+                idx += if chars[idx] == '\\' { 2 } else { 1 };
+            }
+            idx += 1;
+            Style::new().fg(Color::Green)
+        } else if c.is_ascii_digit() {
+            while idx < chars.len() && chars[idx].is_ascii_alphanumeric() {
+                idx += 1;
+            }
+            Style::new().fg(Color::Magenta)
+        } else if c.is_alphabetic() || c == '_' {
+            while idx < chars.len() && (chars[idx].is_alphanumeric() || chars[idx] == '_') {
+                idx += 1;
+            }
+            let word: String = chars[start..idx].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) {
+                Style::new().fg(Color::Blue).modifier(Modifier::BOLD)
+            } else if word.starts_with(char::is_uppercase) {
+                Style::new().fg(Color::Yellow)
+            } else {
+                continue;
+            }
+        } else {
+            idx += 1;
+            continue;
+        };
+
+        let end = idx.min(chars.len());
+        tokens.push((IdxRange::new(start, end - 1), style));
+    }
+
+    tokens
+}
+
+/// Runs `f` until it takes at least `budget` and prints average time of one call.
+fn bench(name: &str, mut f: impl FnMut()) {
+    let budget = Duration::from_millis(300);
+    let started = Instant::now();
+    let mut iterations = 0u32;
+    while started.elapsed() < budget {
+        f();
+        iterations += 1;
+    }
+    let average = started.elapsed() / iterations;
+    println!("{name:<40} {:>12.1} us", average.as_secs_f64() * 1e6);
+}
+
+fn build_with_add(policy: StoragePolicy, tokens: &[(IdxRange, Style)]) -> Mask {
+    let mut mask = Mask::with_storage(policy);
+    for (range, style) in tokens {
+        mask.add(*range, *style);
+    }
+    mask
+}
+
+fn build_sorted(policy: StoragePolicy, tokens: &[(IdxRange, Style)]) -> Mask {
+    let mut builder = MaskBuilder::with_storage(policy);
+    for (range, style) in tokens {
+        builder.push(*range, *style).expect("Tokens are sorted");
+    }
+    builder.build()
+}
+
+/// Edits the middle of masks of growing size in place to find count of runs after which B-tree
+/// is faster than vector.
+fn bench_edit_sizes(file: &str) {
+    let selection = Style::new().bg(Color::Blue);
+    let tokens = highlight(&file.repeat(16));
+
+    for count in [64, 256, 1024, 4096, 16384, 65536] {
+        let tokens = &tokens[..count];
+        let len = tokens[count - 1].0.end;
+
+        for policy in [StoragePolicy::Vec, StoragePolicy::BTree] {
+            let mut mask = build_sorted(policy, tokens);
+            let runs = mask.iter().count();
+            bench(&format!("{policy:?}: {runs} runs, 100 edits"), || {
+                for start in (len / 4..len * 3 / 4).step_by(len / 200) {
+                    mask.add(start..start + 4, selection);
+                    mask.remove(start..start + 4);
+                }
+            });
+            bench(&format!("{policy:?}: {runs} runs, 100 splices"), || {
+                for start in (len / 4..len * 3 / 4).step_by(len / 200) {
+                    mask.shift(start, 1);
+                    mask.shift(start + 1, -1);
+                }
+            });
+        }
+    }
+}
+
+fn main() {
+    let file: String = SOURCES.concat();
+    let line = file.lines().find(|line| line.contains("pub fn")).expect("Sources have functions");
+    let tokens = highlight(&file);
+    let line_tokens = highlight(line);
+    let len = file.chars().count();
+
+    println!("{} chars, {} tokens", len, tokens.len());
+    bench_edit_sizes(&file);
+
+    let big_file = file.repeat(8);
+    let big_tokens = highlight(&big_file);
+    let big_len = big_file.chars().count();
+    println!("{} chars, {} tokens", big_len, big_tokens.len());
+
+    for policy in POLICIES {
+        let name = format!("{policy:?}");
+        let mask = build_sorted(policy, &big_tokens);
+
+        bench(&format!("{name}: big file, 1000 lookups"), || {
+            for idx in (0..big_len).step_by(big_len / 1000) {
+                black_box(mask.get(idx));
+            }
+        });
+        bench(&format!("{name}: big file, move selection"), || {
+            let mut mask = mask.clone();
+            for start in (0..big_len).step_by(big_len / 20) {
+                mask.add(start..start + 40, Style::new().bg(Color::Blue));
+            }
+            black_box(mask);
+        });
+    }
+
+    for policy in POLICIES {
+        let name = format!("{policy:?}");
+        let mask = build_sorted(policy, &tokens);
+
+        bench(&format!("{name}: one line, add"), || {
+            black_box(build_with_add(policy, &line_tokens));
+        });
+        bench(&format!("{name}: whole file, add"), || {
+            black_box(build_with_add(policy, &tokens));
+        });
+        bench(&format!("{name}: whole file, from sorted runs"), || {
+            black_box(build_sorted(policy, &tokens));
+        });
+        bench(&format!("{name}: 1000 lookups"), || {
+            for idx in (0..len).step_by((len / 1000).max(1)) {
+                black_box(mask.get(idx));
+            }
+        });
+        bench(&format!("{name}: visible window (80x50) runs"), || {
+            let start = len / 2;
+            black_box(mask.range(start..start + 80 * 50).count());
+        });
+        bench(&format!("{name}: move selection"), || {
+            let mut mask = mask.clone();
+            for start in (0..len).step_by(len / 20) {
+                mask.add(start..start + 40, Style::new().bg(Color::Blue));
+            }
+            black_box(mask);
+        });
+    }
+}
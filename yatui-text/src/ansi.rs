@@ -0,0 +1,889 @@
+//! Import and export of styled [`Text`] with
+//! [ANSI escape sequences](https://en.wikipedia.org/wiki/ANSI_escape_code#SGR).
+//!
+//! Only SGR (Select Graphic Rendition) and OSC 8 (hyperlink) sequences are interpreted, all other
+//! escape sequences are skipped on import.
+
+use std::fmt::{self, Display, Write};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    link::{Hyperlink, Links},
+    style::{ansi_index, named_color, ColorDepth},
+    Color, Mask, Modifier, Style, Text,
+};
+
+const ESC: char = '\x1b';
+const LINK_CLOSE: &str = "\x1b]8;;\x1b\\";
+
+/// SGR code which enables every modifier. Underline kinds use `4` with a sub-parameter.
+const MODIFIER_CODES: [(Modifier, &str); 14] = [
+    (Modifier::BOLD, "1"),
+    (Modifier::DIM, "2"),
+    (Modifier::ITALIC, "3"),
+    (Modifier::UNDERLINED, "4"),
+    (Modifier::DOUBLE_UNDERLINED, "4:2"),
+    (Modifier::CURLY_UNDERLINED, "4:3"),
+    (Modifier::DOTTED_UNDERLINED, "4:4"),
+    (Modifier::DASHED_UNDERLINED, "4:5"),
+    (Modifier::SLOW_BLINK, "5"),
+    (Modifier::RAPID_BLINK, "6"),
+    (Modifier::REVERSED, "7"),
+    (Modifier::HIDDEN, "8"),
+    (Modifier::CROSSED_OUT, "9"),
+    (Modifier::OVERLINED, "53"),
+];
+
+impl Display for Style {
+    /// Writes SGR escape sequence which resets all previous attributes and applies `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style = Style::new().fg(Color::Red).modifier(Modifier::BOLD | Modifier::ITALIC);
+    /// assert_eq!(style.to_string(), "\x1b[0;1;3;31m");
+    /// assert_eq!(Style::new().to_string(), "\x1b[0m");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\x1b[0")?;
+
+        for (modifier, code) in MODIFIER_CODES {
+            if self.modifier.contains(modifier) {
+                write!(f, ";{code}")?;
+            }
+        }
+
+        if let Some(fg) = self.fg {
+            f.write_char(';')?;
+            write_color(f, fg, 30)?;
+        }
+        if let Some(bg) = self.bg {
+            f.write_char(';')?;
+            write_color(f, bg, 40)?;
+        }
+        if let Some(color) = self.underline_color {
+            match (color, ansi_index(color)) {
+                (Color::Rgb(r, g, b), _) => write!(f, ";58;2;{r};{g};{b}")?,
+                (Color::Reset, _) => f.write_str(";59")?,
+                (_, Some(idx)) => write!(f, ";58;5;{idx}")?,
+                (_, None) => {}
+            }
+        }
+
+        f.write_char('m')
+    }
+}
+
+impl Display for Modifier {
+    /// Writes lowercase names of all modifiers separated by space.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Modifier;
+    /// let modifier = Modifier::BOLD | Modifier::CURLY_UNDERLINED;
+    /// assert_eq!(modifier.to_string(), "bold curly_underlined");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, _)) in self.iter_names().enumerate() {
+            if i != 0 {
+                f.write_char(' ')?;
+            }
+            for c in name.chars() {
+                f.write_char(c.to_ascii_lowercase())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Features of a terminal which are used for ANSI export. Styles are adapted to the terminal
+/// before writing, so unsupported features are degraded gracefully.
+///
+/// Default capabilities enable everything.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Capabilities {
+    /// Supported colors.
+    pub colors: ColorDepth,
+    /// Support of colored underline (SGR 58).
+    pub underline_color: bool,
+    /// Support of underline kinds except the plain one (SGR 4:2 - 4:5).
+    pub styled_underlines: bool,
+    /// Support of hyperlinks (OSC 8). Links are written as plain text without it.
+    pub hyperlinks: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            colors: ColorDepth::TrueColor,
+            underline_color: true,
+            styled_underlines: true,
+            hyperlinks: true,
+        }
+    }
+}
+
+/// Terminals which are known to support all underline extensions.
+const EXTENDED_UNDERLINE_TERMS: [&str; 5] =
+    ["xterm-kitty", "wezterm", "foot", "xterm-ghostty", "alacritty"];
+
+/// Terminal programs which are known to support hyperlinks.
+const HYPERLINK_PROGRAMS: [&str; 4] = ["iTerm.app", "WezTerm", "vscode", "ghostty"];
+
+/// The first version of VTE based terminals (e.g. gnome-terminal) with hyperlinks.
+const VTE_HYPERLINK_VERSION: u32 = 5000;
+
+impl Capabilities {
+    /// Detects capabilities of current terminal from environment variables `NO_COLOR`, `TERM`,
+    /// `COLORTERM`, `TERM_PROGRAM` and `VTE_VERSION`.
+    pub fn detect() -> Self {
+        Self::detect_with(|name| std::env::var(name).ok())
+    }
+
+    fn detect_with(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let no_color = var("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+        let colors = if no_color || term == "dumb" {
+            ColorDepth::Monochrome
+        } else if matches!(var("COLORTERM").as_deref(), Some("truecolor" | "24bit")) {
+            ColorDepth::TrueColor
+        } else {
+            ColorDepth::Ansi16
+        };
+
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        let extended_underlines =
+            EXTENDED_UNDERLINE_TERMS.contains(&term.as_str()) || program == "WezTerm";
+
+        let vte = var("VTE_VERSION").and_then(|v| v.parse::<u32>().ok()).unwrap_or_default();
+        let hyperlinks = EXTENDED_UNDERLINE_TERMS.contains(&term.as_str())
+            || HYPERLINK_PROGRAMS.contains(&program.as_str())
+            || vte >= VTE_HYPERLINK_VERSION;
+
+        Self {
+            colors,
+            underline_color: extended_underlines,
+            styled_underlines: extended_underlines,
+            hyperlinks,
+        }
+    }
+
+    /// Converts `style` into a style which can be displayed with `self` capabilities.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{ansi::Capabilities, style::ColorDepth, *};
+    /// let caps = Capabilities {
+    ///     colors: ColorDepth::Ansi16,
+    ///     underline_color: false,
+    ///     styled_underlines: false,
+    ///     hyperlinks: false,
+    /// };
+    /// let style = Style::new()
+    ///     .fg(Color::Rgb(250, 0, 0))
+    ///     .underline_color(Color::Red)
+    ///     .modifier(Modifier::CURLY_UNDERLINED);
+    /// assert_eq!(
+    ///     caps.adapt(style),
+    ///     Style::new().fg(Color::BrightRed).modifier(Modifier::UNDERLINED)
+    /// );
+    /// ```
+    pub fn adapt(&self, style: Style) -> Style {
+        let mut style = style.downsample(self.colors);
+
+        if !self.underline_color {
+            style.underline_color = None;
+        }
+
+        if !self.styled_underlines && style.modifier.intersects(Modifier::ANY_UNDERLINE) {
+            style.modifier.remove(Modifier::ANY_UNDERLINE);
+            style.modifier |= Modifier::UNDERLINED;
+        }
+
+        style
+    }
+}
+
+/// Writes OSC 8 sequence which opens `link`.
+///
+/// OSC 8 allows only printable ASCII inside the sequence, so other bytes of uri and id are
+/// percent-encoded. Otherwise the link could terminate the sequence and inject another one. `:`
+/// and `;` in id are encoded too, because they separate params, and `%` is encoded everywhere, so
+/// [`parse_link`] decodes links back unambiguously.
+fn write_link(out: &mut String, link: &Hyperlink) {
+    out.push_str("\x1b]8;");
+    if let Some(id) = link.id() {
+        out.push_str("id=");
+        write_percent_encoded(out, id, |b| matches!(b, b':' | b';'));
+    }
+    out.push(';');
+    write_percent_encoded(out, link.uri(), |_| false);
+    out.push_str("\x1b\\");
+}
+
+/// Writes `s` with `%`, every byte outside of printable ASCII and bytes matching `reserved` encoded
+/// as `%XX`.
+fn write_percent_encoded(out: &mut String, s: &str, reserved: impl Fn(u8) -> bool) {
+    for b in s.bytes() {
+        if (0x20..=0x7e).contains(&b) && b != b'%' && !reserved(b) {
+            out.push(b as char);
+        } else {
+            write!(out, "%{b:02X}").expect("Writing into String never fails");
+        }
+    }
+}
+
+/// Parses body of OSC 8 sequence (`params;uri`). Empty `uri` closes current link. Uri and id are
+/// percent-decoded.
+fn parse_link(body: &str) -> Option<Hyperlink> {
+    let (params, uri) = body.split_once(';')?;
+    if uri.is_empty() {
+        return None;
+    }
+
+    let link = Hyperlink::new(percent_decode(uri));
+    match params.split(':').find_map(|param| param.strip_prefix("id=")) {
+        Some(id) => Some(link.with_id(percent_decode(id))),
+        None => Some(link),
+    }
+}
+
+/// Replaces `%XX` sequences in `s` with their bytes. Invalid sequences are kept as is, and
+/// decoded bytes which are not UTF-8 are replaced with `U+FFFD`.
+fn percent_decode(s: &str) -> String {
+    let hex = |b: u8| char::from(b).to_digit(16).map(|d| d as u8);
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        let decoded = match rest {
+            [hi, lo, tail @ ..] if b == b'%' => hex(*hi).zip(hex(*lo)).map(|hex| (hex, tail)),
+            _ => None,
+        };
+        match decoded {
+            Some(((hi, lo), tail)) => {
+                bytes.push(hi << 4 | lo);
+                rest = tail;
+            }
+            None => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Writes SGR parameters of `color`. `base` is 30 for foreground and 40 for background colors.
+fn write_color(f: &mut impl Write, color: Color, base: u8) -> fmt::Result {
+    let code = match color {
+        Color::Black => base,
+        Color::Red => base + 1,
+        Color::Green => base + 2,
+        Color::Yellow => base + 3,
+        Color::Blue => base + 4,
+        Color::Magenta => base + 5,
+        Color::Cyan => base + 6,
+        Color::White => base + 7,
+        Color::BrightBlack => base + 60,
+        Color::BrightRed => base + 61,
+        Color::BrightGreen => base + 62,
+        Color::BrightYellow => base + 63,
+        Color::BrightBlue => base + 64,
+        Color::BrightMagenta => base + 65,
+        Color::BrightCyan => base + 66,
+        Color::BrightWhite => base + 67,
+        Color::Rgb(r, g, b) => return write!(f, "{};2;{r};{g};{b}", base + 8),
+        Color::Reset => base + 9,
+    };
+    write!(f, "{code}")
+}
+
+/// Converts 256-color palette index into [`Color`].
+fn indexed_color(idx: u8) -> Color {
+    if let Some(color) = named_color(usize::from(idx)) {
+        return color;
+    }
+
+    if idx >= 232 {
+        let level = 8 + (idx - 232) * 10;
+        return Color::Rgb(level, level, level);
+    }
+
+    let idx = idx - 16;
+    let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+    Color::Rgb(level(idx / 36), level((idx / 6) % 6), level(idx % 6))
+}
+
+/// Parses extended color from SGR parameters after 38/48 code. `sub` contains colon separated
+/// sub-parameters of the same parameter, `rest` contains following semicolon separated
+/// parameters.
+fn parse_extended_color<'a>(
+    sub: &[&str],
+    rest: &mut impl Iterator<Item = &'a str>,
+) -> Option<Color> {
+    let num = |s: &str| s.parse::<u8>().ok();
+
+    if !sub.is_empty() {
+        return match sub {
+            ["5", idx] => num(idx).map(indexed_color),
+            // Color space id is optional
+            ["2", r, g, b] | ["2", _, r, g, b] => Some(Color::Rgb(num(r)?, num(g)?, num(b)?)),
+            _ => None,
+        };
+    }
+
+    match rest.next()? {
+        "5" => rest.next().and_then(num).map(indexed_color),
+        "2" => {
+            let (r, g, b) = (rest.next()?, rest.next()?, rest.next()?);
+            Some(Color::Rgb(num(r)?, num(g)?, num(b)?))
+        }
+        _ => None,
+    }
+}
+
+/// Applies SGR `params` (content between `ESC[` and `m`) to `style`. Unknown parameters are
+/// ignored.
+pub(crate) fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let mut params = params.split(';');
+
+    while let Some(param) = params.next() {
+        let mut sub = param.split(':');
+        let code = sub.next().unwrap_or_default();
+        let sub: Vec<&str> = sub.collect();
+
+        // Empty parameter is the same as 0
+        let code = match code {
+            "" => 0,
+            code => match code.parse::<u8>() {
+                Ok(code) => code,
+                Err(_) => continue,
+            },
+        };
+
+        match code {
+            0 => style = Style::default(),
+            1 => style.modifier |= Modifier::BOLD,
+            2 => style.modifier |= Modifier::DIM,
+            3 => style.modifier |= Modifier::ITALIC,
+            4 => {
+                let kind = match sub.first().copied() {
+                    None | Some("1") => Modifier::UNDERLINED,
+                    Some("2") => Modifier::DOUBLE_UNDERLINED,
+                    Some("3") => Modifier::CURLY_UNDERLINED,
+                    Some("4") => Modifier::DOTTED_UNDERLINED,
+                    Some("5") => Modifier::DASHED_UNDERLINED,
+                    _ => Modifier::empty(),
+                };
+                style.modifier.remove(Modifier::ANY_UNDERLINE);
+                style.modifier |= kind;
+            }
+            5 => {
+                style.modifier.remove(Modifier::ANY_BLINK);
+                style.modifier |= Modifier::SLOW_BLINK;
+            }
+            6 => {
+                style.modifier.remove(Modifier::ANY_BLINK);
+                style.modifier |= Modifier::RAPID_BLINK;
+            }
+            7 => style.modifier |= Modifier::REVERSED,
+            8 => style.modifier |= Modifier::HIDDEN,
+            9 => style.modifier |= Modifier::CROSSED_OUT,
+            21 => {
+                style.modifier.remove(Modifier::ANY_UNDERLINE);
+                style.modifier |= Modifier::DOUBLE_UNDERLINED;
+            }
+            22 => style.modifier.remove(Modifier::BOLD | Modifier::DIM),
+            23 => style.modifier.remove(Modifier::ITALIC),
+            24 => style.modifier.remove(Modifier::ANY_UNDERLINE),
+            25 => style.modifier.remove(Modifier::ANY_BLINK),
+            27 => style.modifier.remove(Modifier::REVERSED),
+            28 => style.modifier.remove(Modifier::HIDDEN),
+            29 => style.modifier.remove(Modifier::CROSSED_OUT),
+            c @ 30..=37 => style.fg = named_color(usize::from(c - 30)),
+            38 => style.fg = parse_extended_color(&sub, &mut params).or(style.fg),
+            39 => style.fg = Some(Color::Reset),
+            c @ 40..=47 => style.bg = named_color(usize::from(c - 40)),
+            48 => style.bg = parse_extended_color(&sub, &mut params).or(style.bg),
+            49 => style.bg = Some(Color::Reset),
+            53 => style.modifier |= Modifier::OVERLINED,
+            55 => style.modifier.remove(Modifier::OVERLINED),
+            58 => {
+                style.underline_color =
+                    parse_extended_color(&sub, &mut params).or(style.underline_color)
+            }
+            59 => style.underline_color = Some(Color::Reset),
+            c @ 90..=97 => style.fg = named_color(usize::from(c - 90 + 8)),
+            c @ 100..=107 => style.bg = named_color(usize::from(c - 100 + 8)),
+            _ => {}
+        }
+    }
+
+    style
+}
+
+/// Splits `s` into plain text chunks and escape sequences.
+enum Token<'a> {
+    Plain(&'a str),
+    /// Parameters of SGR sequence.
+    Sgr(&'a str),
+    /// OSC 8 sequence which opens (`Some`) or closes (`None`) hyperlink.
+    Link(Option<Hyperlink>),
+    /// Any other escape sequence.
+    Other,
+}
+
+fn next_token(s: &str) -> Option<(Token<'_>, &str)> {
+    if s.is_empty() {
+        return None;
+    }
+
+    let Some(rest) = s.strip_prefix(ESC) else {
+        let end = s.find(ESC).unwrap_or(s.len());
+        return Some((Token::Plain(&s[..end]), &s[end..]));
+    };
+
+    let mut chars = rest.chars();
+    let token = match chars.next() {
+        // CSI: parameters and intermediate bytes until final byte in [0x40; 0x7E]
+        Some('[') => {
+            let body = &rest[1..];
+            match body.find(|c: char| ('\x40'..='\x7e').contains(&c)) {
+                Some(end) => {
+                    let token = if body[end..].starts_with('m') {
+                        Token::Sgr(&body[..end])
+                    } else {
+                        Token::Other
+                    };
+                    return Some((token, &body[end + 1..]));
+                }
+                None => return Some((Token::Other, "")),
+            }
+        }
+        // OSC: terminated by BEL or ST (ESC \)
+        Some(']') => {
+            let body = &rest[1..];
+            let (end, len) = match (body.find('\x07'), body.find("\x1b\\")) {
+                (Some(bel), Some(st)) if st < bel => (st, 2),
+                (Some(bel), _) => (bel, 1),
+                (None, Some(st)) => (st, 2),
+                (None, None) => (body.len(), 0),
+            };
+            let token = match body[..end].strip_prefix("8;") {
+                Some(link) => Token::Link(parse_link(link)),
+                None => Token::Other,
+            };
+            return Some((token, &body[end + len..]));
+        }
+        Some(_) => Token::Other,
+        None => Token::Other,
+    };
+
+    Some((token, chars.as_str()))
+}
+
+impl Text<'_> {
+    /// Converts [`Text`] into string with SGR escape sequences for styles from its [`Mask`] and
+    /// OSC 8 sequences for its [`Links`].
+    ///
+    /// Style is emitted only when it changes, and all attributes are reset at the end if any
+    /// style was applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let mut text = Text::from("hello world");
+    /// text.mask_mut().add(0..5, Style::new().modifier(Modifier::BOLD));
+    /// assert_eq!(text.to_ansi(), "\x1b[0;1mhello\x1b[0m world");
+    /// ```
+    pub fn to_ansi(&self) -> String {
+        self.to_ansi_with(&Capabilities::default())
+    }
+
+    /// Same as [`to_ansi`] but all styles are [`adapted`] for specified terminal capabilities.
+    /// Links are written as plain text if terminal does not support them.
+    ///
+    /// [`to_ansi`]: Self::to_ansi
+    /// [`adapted`]: Capabilities::adapt
+    pub fn to_ansi_with(&self, caps: &Capabilities) -> String {
+        let mut out = String::with_capacity(self.as_str().len());
+        let mut styles = self.mask().iter().peekable();
+        let mut links = self.links().iter().peekable();
+        let mut current_style = Style::default();
+        let mut current_link = None;
+
+        for (idx, grapheme) in self.as_str().graphemes(true).enumerate() {
+            while styles.next_if(|(range, _)| range.end < idx).is_some() {}
+            while links.next_if(|(range, _)| range.end < idx).is_some() {}
+
+            let link = match links.peek() {
+                Some((range, link)) if caps.hyperlinks && range.contains(idx) => Some(*link),
+                _ => None,
+            };
+            if link != current_link {
+                if current_link.is_some() {
+                    out.push_str(LINK_CLOSE);
+                }
+                if let Some(link) = link {
+                    write_link(&mut out, link);
+                }
+                current_link = link;
+            }
+
+            let style = styles.peek().map(|(_, style)| caps.adapt(**style)).unwrap_or_default();
+            if style != current_style {
+                write!(out, "{style}").expect("Writing into String never fails");
+                current_style = style;
+            }
+
+            out.push_str(grapheme);
+        }
+
+        if current_link.is_some() {
+            out.push_str(LINK_CLOSE);
+        }
+
+        if current_style != Style::default() {
+            write!(out, "{}", Style::default()).expect("Writing into String never fails");
+        }
+
+        out
+    }
+
+    /// Creates [`Text`] from string with ANSI escape sequences. SGR sequences are converted
+    /// into [`Mask`] styles, OSC 8 sequences are converted into [`Links`], all other escape
+    /// sequences are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let text = Text::from_ansi("\x1b[1;31mhello\x1b[0m world");
+    /// assert_eq!(text.as_str(), "hello world");
+    /// assert_eq!(text.mask()[0], Style::new().fg(Color::Red).modifier(Modifier::BOLD));
+    /// assert_eq!(text.mask()[5], Style::default());
+    /// ```
+    pub fn from_ansi(s: &str) -> Text<'static> {
+        let mut string = String::with_capacity(s.len());
+        let mut mask = Mask::new();
+        let mut style = Style::default();
+        let mut links = Links::new();
+        let mut link: Option<(usize, Hyperlink)> = None;
+        // Count of graphemes in `string` and byte offset of its last grapheme
+        let mut idx: usize = 0;
+        let mut last = 0;
+
+        let mut rest = s;
+        while let Some((token, next)) = next_token(rest) {
+            match token {
+                Token::Plain(plain) => {
+                    // Escape sequence can split a grapheme, e.g. before a combining mark, so
+                    // the last grapheme is segmented again together with `plain`
+                    let (from, mut count) = (last, idx.saturating_sub(1));
+                    string.push_str(plain);
+                    for (byte, _) in string[from..].grapheme_indices(true) {
+                        count += 1;
+                        last = from + byte;
+                    }
+                    if count > idx && style != Style::default() {
+                        mask.replace(idx..count, style);
+                    }
+                    idx = count;
+                }
+                Token::Sgr(params) => style = apply_sgr(style, params),
+                Token::Link(next_link) => {
+                    // Links without graphemes inside are dropped
+                    if let Some((start, link)) = link.take().filter(|(start, _)| *start < idx) {
+                        links.add(start..idx, link);
+                    }
+                    link = next_link.map(|next_link| (idx, next_link));
+                }
+                Token::Other => {}
+            }
+            rest = next;
+        }
+
+        if let Some((start, link)) = link.filter(|(start, _)| *start < idx) {
+            links.add(start..idx, link);
+        }
+
+        let mut text = Text::from(string);
+        *text.mask_mut() = mask;
+        *text.links_mut() = links;
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[test]
+    fn every_modifier_has_code() {
+        for modifier in Modifier::all().iter() {
+            assert!(MODIFIER_CODES.iter().any(|(m, _)| *m == modifier), "{modifier:?}");
+        }
+    }
+
+    #[rstest]
+    #[case::empty(Style::new(), "\x1b[0m")]
+    #[case::named(Style::new().fg(Color::Green).bg(Color::BrightBlue), "\x1b[0;32;104m")]
+    #[case::rgb(Style::new().bg(Color::Rgb(1, 2, 3)), "\x1b[0;48;2;1;2;3m")]
+    #[case::underlines(Style::new().modifier(Modifier::CURLY_UNDERLINED), "\x1b[0;4:3m")]
+    #[case::all_modifiers(
+        Style::new().modifier(Modifier::all().normalize()),
+        "\x1b[0;1;2;3;4:5;6;7;8;9;53m"
+    )]
+    #[case::underline_color(
+        Style::new().underline_color(Color::Rgb(255, 0, 0)).modifier(Modifier::CURLY_UNDERLINED),
+        "\x1b[0;4:3;58;2;255;0;0m"
+    )]
+    #[case::named_underline_color(Style::new().underline_color(Color::BrightRed), "\x1b[0;58;5;9m")]
+    #[case::reset(
+        Style::new().fg(Color::Reset).bg(Color::Reset).underline_color(Color::Reset),
+        "\x1b[0;39;49;59m"
+    )]
+    #[case::sub_modifier(Style::new().fg(Color::Red) - Modifier::BOLD, "\x1b[0;31m")]
+    fn style_to_sgr(#[case] style: Style, #[case] expected: &str) {
+        assert_eq!(style.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::reset("0", Style::default())]
+    #[case::empty("", Style::default())]
+    #[case::bold_dim("1;2", Style::new().modifier(Modifier::BOLD | Modifier::DIM))]
+    #[case::underline("4", Style::new().modifier(Modifier::UNDERLINED))]
+    #[case::double_underline("21", Style::new().modifier(Modifier::DOUBLE_UNDERLINED))]
+    #[case::curly_replaces_underline("4;4:3", Style::new().modifier(Modifier::CURLY_UNDERLINED))]
+    #[case::no_underline("4:3;24", Style::new())]
+    #[case::blink("5;6", Style::new().modifier(Modifier::RAPID_BLINK))]
+    #[case::others(
+        "7;8;9;53",
+        Style::new().modifier(
+            Modifier::REVERSED | Modifier::HIDDEN | Modifier::CROSSED_OUT | Modifier::OVERLINED
+        )
+    )]
+    #[case::named_colors("31;102", Style::new().fg(Color::Red).bg(Color::BrightGreen))]
+    #[case::bright_fg("97", Style::new().fg(Color::BrightWhite))]
+    #[case::default_colors("31;41;39;49", Style::new().fg(Color::Reset).bg(Color::Reset))]
+    #[case::rgb("38;2;10;20;30", Style::new().fg(Color::Rgb(10, 20, 30)))]
+    #[case::rgb_colon("48:2::10:20:30", Style::new().bg(Color::Rgb(10, 20, 30)))]
+    #[case::indexed_named("38;5;9", Style::new().fg(Color::BrightRed))]
+    #[case::indexed_cube("38;5;196", Style::new().fg(Color::Rgb(255, 0, 0)))]
+    #[case::indexed_gray("48:5:232", Style::new().bg(Color::Rgb(8, 8, 8)))]
+    #[case::underline_color("58;2;1;2;3", Style::new().underline_color(Color::Rgb(1, 2, 3)))]
+    #[case::underline_color_colon("58:5:1", Style::new().underline_color(Color::Red))]
+    #[case::no_underline_color("58;5;1;59", Style::new().underline_color(Color::Reset))]
+    #[case::unknown("1;123;3", Style::new().modifier(Modifier::BOLD | Modifier::ITALIC))]
+    #[case::invalid("1;x;999", Style::new().modifier(Modifier::BOLD))]
+    fn parse_sgr(#[case] params: &str, #[case] expected: Style) {
+        assert_eq!(apply_sgr(Style::default(), params), expected);
+    }
+
+    #[rstest]
+    #[case::empty(Style::new())]
+    #[case::colors(Style::new().fg(Color::Rgb(1, 2, 3)).bg(Color::Magenta))]
+    #[case::underline_color(Style::new().underline_color(Color::Rgb(7, 8, 9)))]
+    #[case::named_underline_color(Style::new().underline_color(Color::Blue))]
+    #[case::reset(Style::new().fg(Color::Reset).bg(Color::Reset).underline_color(Color::Reset))]
+    #[case::modifiers(Style::new().modifier(
+        Modifier::DIM | Modifier::DOTTED_UNDERLINED | Modifier::SLOW_BLINK | Modifier::OVERLINED
+    ))]
+    fn style_roundtrip(#[case] style: Style) {
+        let sgr = style.to_string();
+        let params = &sgr[2..sgr.len() - 1];
+        assert_eq!(apply_sgr(Style::default(), params), style);
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let mut text = Text::from("老虎 says hello");
+        // Imported styles are absolute, so they're set with `replace` rather than layered with
+        // `add`, which would also record replaced underline kinds in `sub_modifier`.
+        let red = Style::new().fg(Color::Red);
+        let underlined = Style::new().modifier(Modifier::UNDERLINED);
+        text.mask_mut().replace(0..1, red);
+        text.mask_mut().replace(1..2, red.modifier(Modifier::UNDERLINED));
+        text.mask_mut().replace(2..4, underlined);
+
+        let ansi = text.to_ansi();
+        assert_eq!(ansi, "\x1b[0;31m老\x1b[0;4;31m虎\x1b[0;4m s\x1b[0mays hello");
+
+        let parsed = Text::from_ansi(&ansi);
+        assert_eq!(parsed.as_str(), text.as_str());
+        assert_eq!(parsed.mask(), text.mask());
+    }
+
+    #[test]
+    fn export_with_capabilities() {
+        let mut text = Text::from("ab");
+        text.mask_mut().add(0..1, Style::new().fg(Color::Rgb(0, 0, 1)).underline_color(Color::Red));
+        text.mask_mut().add(1..2, Style::new().fg(Color::Black));
+
+        let caps = Capabilities {
+            colors: ColorDepth::Ansi16,
+            underline_color: false,
+            ..Default::default()
+        };
+        // Both graphemes have the same style after downsampling
+        assert_eq!(text.to_ansi_with(&caps), "\x1b[0;30mab\x1b[0m");
+
+        let caps = Capabilities { colors: ColorDepth::Monochrome, ..caps };
+        assert_eq!(text.to_ansi_with(&caps), "ab");
+    }
+
+    #[rstest]
+    #[case::dumb(&[("TERM", "dumb"), ("COLORTERM", "truecolor")], ColorDepth::Monochrome, false)]
+    #[case::no_color(&[("TERM", "xterm"), ("NO_COLOR", "1")], ColorDepth::Monochrome, false)]
+    #[case::empty_no_color(&[("TERM", "xterm"), ("NO_COLOR", "")], ColorDepth::Ansi16, false)]
+    #[case::xterm(&[("TERM", "xterm-256color")], ColorDepth::Ansi16, false)]
+    #[case::truecolor(&[("TERM", "xterm"), ("COLORTERM", "24bit")], ColorDepth::TrueColor, false)]
+    #[case::kitty(&[("TERM", "xterm-kitty"), ("COLORTERM", "24bit")], ColorDepth::TrueColor, true)]
+    #[case::wezterm(&[("TERM", "xterm"), ("TERM_PROGRAM", "WezTerm")], ColorDepth::Ansi16, true)]
+    fn detect_capabilities(
+        #[case] env: &[(&str, &str)],
+        #[case] colors: ColorDepth,
+        #[case] extended_underlines: bool,
+    ) {
+        let caps = detect(env);
+        assert_eq!(caps.colors, colors);
+        assert_eq!(caps.underline_color, extended_underlines);
+        assert_eq!(caps.styled_underlines, extended_underlines);
+    }
+
+    #[rstest]
+    #[case::xterm(&[("TERM", "xterm-256color")], false)]
+    #[case::kitty(&[("TERM", "xterm-kitty")], true)]
+    #[case::iterm(&[("TERM", "xterm"), ("TERM_PROGRAM", "iTerm.app")], true)]
+    #[case::old_vte(&[("TERM", "xterm"), ("VTE_VERSION", "4802")], false)]
+    #[case::new_vte(&[("TERM", "xterm"), ("VTE_VERSION", "6003")], true)]
+    fn detect_hyperlinks(#[case] env: &[(&str, &str)], #[case] hyperlinks: bool) {
+        assert_eq!(detect(env).hyperlinks, hyperlinks);
+    }
+
+    fn detect(env: &[(&str, &str)]) -> Capabilities {
+        Capabilities::detect_with(|name| {
+            env.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn export_links() {
+        let mut text = Text::from("see a.rs");
+        text.links_mut().add(4..8, Hyperlink::new("file:///a.rs").with_id("1"));
+        text.mask_mut().add(6.., Style::new().fg(Color::Red));
+
+        assert_eq!(
+            text.to_ansi(),
+            "see \x1b]8;id=1;file:///a.rs\x1b\\a.\x1b[0;31mrs\x1b]8;;\x1b\\\x1b[0m"
+        );
+
+        let caps = Capabilities { hyperlinks: false, ..Default::default() };
+        assert_eq!(text.to_ansi_with(&caps), "see a.\x1b[0;31mrs\x1b[0m");
+    }
+
+    #[rstest]
+    #[case::injected_st("a\x1b\\\x1b[2Jb", None, "\x1b]8;;a%1B\\%1B[2Jb\x1b\\")]
+    #[case::injected_bel("a\x07b", None, "\x1b]8;;a%07b\x1b\\")]
+    #[case::non_ascii("file:///é", None, "\x1b]8;;file:///%C3%A9\x1b\\")]
+    #[case::uri_separators("a:b;c", None, "\x1b]8;;a:b;c\x1b\\")]
+    #[case::id_separators("a", Some("x:y;z"), "\x1b]8;id=x%3Ay%3Bz;a\x1b\\")]
+    #[case::id_escape("a", Some("\x1b]0;"), "\x1b]8;id=%1B]0%3B;a\x1b\\")]
+    #[case::percent("a%41", Some("%"), "\x1b]8;id=%25;a%2541\x1b\\")]
+    fn escape_links(#[case] uri: &str, #[case] id: Option<&str>, #[case] expected: &str) {
+        let link = Hyperlink::new(uri);
+        let link = match id {
+            Some(id) => link.with_id(id),
+            None => link,
+        };
+
+        let mut out = String::new();
+        write_link(&mut out, &link);
+        assert_eq!(out, expected);
+        let body = out.strip_prefix("\x1b]8;").and_then(|out| out.strip_suffix("\x1b\\"));
+        assert_eq!(body.and_then(parse_link), Some(link));
+    }
+
+    #[test]
+    fn links_roundtrip() {
+        let mut text = Text::from("one two three");
+        text.links_mut().add(0..3, Hyperlink::new("https://one"));
+        text.links_mut().add(4..7, Hyperlink::new("https://two").with_id("2"));
+        text.links_mut().add(7..8, Hyperlink::new("https://space"));
+
+        let parsed = Text::from_ansi(&text.to_ansi());
+        assert_eq!(parsed.as_str(), text.as_str());
+        assert_eq!(parsed.links(), text.links());
+    }
+
+    #[test]
+    fn escaped_links_roundtrip() {
+        let mut text = Text::from("link");
+        let link = Hyperlink::new("https://ex.com/é?q=100%").with_id("a:b");
+        text.links_mut().add(0..4, link.clone());
+
+        let parsed = Text::from_ansi(&text.to_ansi());
+        assert_eq!(parsed.links().iter().next(), Some((crate::IdxRange::new(0, 3), &link)));
+    }
+
+    #[rstest]
+    #[case::plain("a:b", "a:b")]
+    #[case::encoded("%C3%a9%25", "é%")]
+    #[case::invalid("%zz%4", "%zz%4")]
+    #[case::not_utf8("%FF", "\u{FFFD}")]
+    fn decode_percent(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(percent_decode(s), expected);
+    }
+
+    #[test]
+    fn import_links() {
+        let text = Text::from_ansi("a\x1b]8;;x\x07b\x1b]8;;y\x1b\\c\x1b]8;;\x1b\\d\x1b]8;;z\x07");
+        assert_eq!(text.as_str(), "abcd");
+
+        let links: Vec<_> =
+            text.links().iter().map(|(r, l)| (r.into_tuple(), l.uri().to_string())).collect();
+        assert_eq!(links, vec![((1, 1), "x".to_string()), ((2, 2), "y".to_string())]);
+    }
+
+    #[rstest]
+    #[case::closed_at_start("\x1b]8;;x\x07\x1b]8;;\x07ab", "ab")]
+    #[case::open_at_start("\x1b]8;;x\x07", "")]
+    #[case::closed_at_end("ab\x1b]8;;x\x1b\\\x1b]8;;\x1b\\", "ab")]
+    #[case::open_at_end("ab\x1b]8;;x\x07", "ab")]
+    fn import_empty_links(#[case] ansi: &str, #[case] expected: &str) {
+        let text = Text::from_ansi(ansi);
+        assert_eq!(text.as_str(), expected);
+        assert!(text.links().is_empty());
+    }
+
+    #[test]
+    fn import_skips_other_sequences() {
+        let text = Text::from_ansi("\x1b[2J\x1b]0;title\x07a\x1b[1mb\x1b]2;x\x1b\\c\x1b[m");
+        assert_eq!(text.as_str(), "abc");
+        assert_eq!(text.mask()[0], Style::default());
+        assert_eq!(text.mask()[1], Style::new().modifier(Modifier::BOLD));
+        assert_eq!(text.mask()[2], Style::new().modifier(Modifier::BOLD));
+        assert_eq!(text.mask()[3], Style::default());
+    }
+
+    #[rstest]
+    #[case::combining_mark("y\x1b[0m\u{0301}a\x1b[31mb", "y\u{0301}ab", 3)]
+    #[case::crlf("x\r\x1b[1m\n\x1b[0;31mb", "x\r\nb", 3)]
+    fn import_grapheme_split_by_sequence(
+        #[case] ansi: &str,
+        #[case] expected: &str,
+        #[case] graphemes: usize,
+    ) {
+        let text = Text::from_ansi(ansi);
+        assert_eq!(text.as_str(), expected);
+        assert_eq!(text.grapheme_count(), graphemes);
+        assert_eq!(text.mask()[graphemes - 1], Style::new().fg(Color::Red));
+        assert_eq!(text.mask()[graphemes - 2], Style::default());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    error,
+    fmt::{self, Display},
+};
+
+use crate::{mask::BoundsError, style::ParseError};
+
+/// An error which can be returned by checked methods of [`Text`] and [`Mask`].
+///
+/// [`Text`]: crate::Text
+/// [`Mask`]: crate::Mask
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// Index `idx` is greater than length `len` of the text in the same unit.
+    IndexOutOfBounds { idx: usize, len: usize },
+    /// Offset does not start a grapheme (or a unit it was converted from) and snapping is not
+    /// allowed.
+    NotGraphemeBoundary { offset: usize },
+    /// Range bounds can not be represented by [`IdxRange`].
+    ///
+    /// [`IdxRange`]: crate::IdxRange
+    InvalidRange(BoundsError),
+    /// Negative `offset` of a shift at index `idx` removes more than `idx` indexes.
+    ShiftUnderflow { idx: usize, offset: isize },
+    /// String can not be parsed.
+    ParseError(ParseError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IndexOutOfBounds { idx, len } => {
+                write!(f, "index {idx} is out of bounds of length {len}")
+            }
+            Error::NotGraphemeBoundary { offset } => {
+                write!(f, "offset {offset} is not on a grapheme boundary")
+            }
+            Error::InvalidRange(e) => write!(f, "invalid range: {e}"),
+            Error::ShiftUnderflow { idx, offset } => {
+                write!(f, "shift by {offset} at index {idx} underflows index 0")
+            }
+            Error::ParseError(e) => write!(f, "can not parse: {e}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidRange(e) => Some(e),
+            Error::ParseError(e) => Some(e),
+            Error::IndexOutOfBounds { .. }
+            | Error::NotGraphemeBoundary { .. }
+            | Error::ShiftUnderflow { .. } => None,
+        }
+    }
+}
+
+impl From<BoundsError> for Error {
+    fn from(e: BoundsError) -> Self {
+        Self::InvalidRange(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Self::ParseError(e)
+    }
+}
@@ -1,11 +1,17 @@
 #![allow(dead_code)] // TODO: dev stage
 #![allow(unused)] // TODO: dev stage
 
+pub mod ansi;
+mod error;
+pub mod link;
 pub mod mask;
 mod raw;
 pub mod style;
 pub mod text;
+pub mod theme;
+pub mod unit;
 
+pub use error::Error;
 pub use mask::{IdxRange, Mask};
-pub use style::{Color, Modifier, Style};
+pub use style::{Color, Modifier, Palette, Style};
 pub use text::Text;
@@ -0,0 +1,200 @@
+use std::{iter::FusedIterator, sync::Arc};
+
+use crate::{
+    mask::{self, RangeAttrs},
+    IdxRange,
+};
+
+/// Target of [OSC 8](https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda)
+/// hyperlink.
+///
+/// `Hyperlink` is cheap to clone, so the same link can be attached to many ranges.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Hyperlink {
+    uri: Arc<str>,
+    id: Option<Arc<str>>,
+}
+
+/// [`Links`] saves [`hyperlinks`] for specified ranges of graphemes. Unlike [`Mask`] it does not
+/// cover all graphemes: most graphemes usually have no link at all.
+///
+/// [`hyperlinks`]: Hyperlink
+/// [`Mask`]: crate::Mask
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Links {
+    attrs: RangeAttrs<Option<Hyperlink>>,
+}
+
+/// An iterator over the items of [`Links`].
+///
+/// The iterator element type is ([`IdxRange`], &'a [`Hyperlink`]).
+///
+/// This struct is created by the [`iter`] method on [`Links`].
+///
+/// [`iter`]: Links::iter
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Iter<'a> {
+    inner: mask::Iter<'a, Option<Hyperlink>>,
+}
+
+impl Hyperlink {
+    /// Creates hyperlink to `uri`. Bytes of `uri` outside of printable ASCII are percent-encoded
+    /// when the link is exported into ANSI string.
+    pub fn new(uri: impl Into<Arc<str>>) -> Self {
+        Self { uri: uri.into(), id: None }
+    }
+
+    /// Set `id` of hyperlink. Terminals highlight all parts of links with the same `id` together,
+    /// e.g. when one link is split into several lines. Like `uri`, `id` is percent-encoded when
+    /// the link is exported, including `:` and `;`.
+    #[must_use]
+    pub fn with_id(mut self, id: impl Into<Arc<str>>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Returns target uri.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Returns `id` of hyperlink.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+}
+
+impl Links {
+    /// Create empty [`Links`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach `link` to specified `range`. Links which were attached to the `range` before are
+    /// replaced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::link::{Hyperlink, Links};
+    /// let mut links = Links::new();
+    /// links.add(0..4, Hyperlink::new("https://example.com"));
+    /// assert_eq!(links.get(3).map(Hyperlink::uri), Some("https://example.com"));
+    /// assert_eq!(links.get(4), None);
+    /// ```
+    pub fn add(&mut self, range: impl Into<IdxRange>, link: Hyperlink) {
+        self.attrs.replace(range, Some(link));
+    }
+
+    /// Remove all links for specified `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::link::{Hyperlink, Links};
+    /// let mut links = Links::new();
+    /// links.add(0..4, Hyperlink::new("https://example.com"));
+    /// links.remove(1..);
+    /// assert!(links.get(0).is_some());
+    /// assert_eq!(links.get(1), None);
+    /// ```
+    pub fn remove(&mut self, range: impl Into<IdxRange>) {
+        self.attrs.remove(range);
+    }
+
+    /// Returns link for grapheme with index `idx`.
+    pub fn get(&self, idx: usize) -> Option<&Hyperlink> {
+        self.attrs.get(idx).and_then(Option::as_ref)
+    }
+
+    /// Returns `true` if there are no links.
+    pub fn is_empty(&self) -> bool {
+        self.attrs.iter().all(|(_, link)| link.is_none())
+    }
+
+    /// Moves links after edit of text which replaced graphemes in `range` with `len` new
+    /// graphemes. See [`RangeAttrs::splice`] for details.
+    pub fn splice(&mut self, range: impl Into<IdxRange>, len: usize) {
+        self.attrs.splice(range, len);
+    }
+
+    pub(crate) fn splice_at(&mut self, start: usize, removed: usize, inserted: usize) {
+        self.attrs.splice_at(start, removed, inserted);
+    }
+
+    /// Moves links of graphemes starting at `idx` by `offset`. See [`RangeAttrs::shift`] for
+    /// details.
+    pub fn shift(&mut self, idx: usize, offset: isize) {
+        self.attrs.shift(idx, offset);
+    }
+
+    /// Gets an iterator over all ranges with links in ascending order.
+    ///
+    /// The iterator element type is ([`IdxRange`], &'a [`Hyperlink`]).
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { inner: self.attrs.iter() }
+    }
+}
+
+impl<'a> IntoIterator for &'a Links {
+    type Item = (IdxRange, &'a Hyperlink);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (IdxRange, &'a Hyperlink);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.find_map(|(range, link)| Some((range, link.as_ref()?)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<'a> FusedIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn replace_links() {
+        let (a, b) = (Hyperlink::new("a"), Hyperlink::new("b").with_id("1"));
+
+        let mut links = Links::new();
+        links.add(0..=5, a.clone());
+        links.add(2..=3, b.clone());
+
+        let items: Vec<_> = links.iter().map(|(r, l)| (r.into_tuple(), l.clone())).collect();
+        assert_eq!(items, vec![((0, 1), a.clone()), ((2, 3), b), ((4, 5), a)]);
+    }
+
+    #[test]
+    fn links_follow_edits() {
+        let link = Hyperlink::new("a");
+
+        let mut links = Links::new();
+        links.add(2..4, link.clone());
+        links.splice(0..1, 3);
+        assert_eq!(links.iter().map(|(r, _)| r).collect::<Vec<_>>(), vec![IdxRange::new(4, 5)]);
+
+        links.shift(5, -1);
+        let items: Vec<_> = links.iter().map(|(r, l)| (r.into_tuple(), l.clone())).collect();
+        assert_eq!(items, vec![((4, 4), link)]);
+    }
+
+    #[test]
+    fn remove_links() {
+        let mut links = Links::new();
+        links.add(0..=5, Hyperlink::new("a"));
+        links.remove(..);
+        assert!(links.is_empty());
+    }
+}
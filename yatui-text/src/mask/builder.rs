@@ -0,0 +1,233 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    mask::{cow::Cow, StoragePolicy},
+    IdxRange, Mask, Style,
+};
+
+/// Builder of [`Mask`] from ascending non intersecting runs.
+///
+/// Unlike [`Mask::add`] it does not look up and merge existing styles: every run is appended
+/// after the previous one in one pass, and adjacent equal styles are joined on the fly. Gaps
+/// between runs get [`default`] style.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{mask::MaskBuilder, Mask, Style, Color};
+/// let mut builder = MaskBuilder::new();
+/// builder.push(0..2, Style::new().fg(Color::Red)).unwrap();
+/// builder.push(4..6, Style::new().fg(Color::Blue)).unwrap();
+/// assert!(builder.push(5..7, Style::default()).is_err());
+///
+/// let mask = builder.build();
+/// assert_eq!(mask[1], Style::new().fg(Color::Red));
+/// assert_eq!(mask[3], Style::default());
+/// assert_eq!(mask[5], Style::new().fg(Color::Blue));
+/// ```
+///
+/// [`default`]: Style::default
+#[derive(Debug, Default)]
+pub struct MaskBuilder {
+    runs: Vec<(IdxRange, Style)>,
+    storage: StoragePolicy,
+    /// The last run which is not inserted into map yet, it can be joined with the next one.
+    pending: Option<(IdxRange, Style)>,
+    /// The last pushed range.
+    last: Option<IdxRange>,
+}
+
+/// An error which is returned when runs passed to [`MaskBuilder`] are not ascending or overlap.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct OverlapError {
+    /// The last accepted range.
+    pub previous: IdxRange,
+    /// Rejected range.
+    pub range: IdxRange,
+}
+
+impl fmt::Display for OverlapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "run {} overlaps or precedes previous run {}", self.range, self.previous)
+    }
+}
+
+impl Error for OverlapError {}
+
+impl MaskBuilder {
+    /// Create empty [`MaskBuilder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create empty [`MaskBuilder`] for mask with specified [`storage`].
+    ///
+    /// [`storage`]: Mask::with_storage
+    pub fn with_storage(storage: StoragePolicy) -> Self {
+        Self { storage, ..Default::default() }
+    }
+
+    /// Append `style` for `range`. The `range` must start after the end of the previously pushed
+    /// range, otherwise nothing is changed and an error is returned. Empty ranges are ignored.
+    pub fn push(&mut self, range: impl Into<IdxRange>, style: Style) -> Result<(), OverlapError> {
+        let range = range.into();
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let start = match self.last {
+            Some(previous) if range.start <= previous.end => {
+                return Err(OverlapError { previous, range });
+            }
+            // `previous.end < range.start`, so it can not overflow
+            Some(previous) => previous.end + 1,
+            None => 0,
+        };
+
+        if start < range.start {
+            self.append(IdxRange::new(start, range.start - 1), Style::default());
+        }
+        self.append(range, style);
+        self.last = Some(range);
+        Ok(())
+    }
+
+    /// Creates [`Mask`]. Graphemes after the last run get [`default`] style.
+    ///
+    /// [`default`]: Style::default
+    pub fn build(mut self) -> Mask {
+        let tail = match self.last {
+            Some(last) if last.end == usize::MAX => None,
+            Some(last) => Some(last.end + 1),
+            None => Some(0),
+        };
+        if let Some(start) = tail {
+            self.append(IdxRange::new(start, usize::MAX), Style::default());
+        }
+        self.flush();
+
+        Mask { cow: Cow::from_runs(self.runs, self.storage), storage: self.storage }
+    }
+
+    fn append(&mut self, range: IdxRange, style: Style) {
+        match &mut self.pending {
+            Some((pending, pending_style)) if *pending_style == style => pending.end = range.end,
+            _ => {
+                self.flush();
+                self.pending = Some((range, style));
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(run) = self.pending.take() {
+            self.runs.push(run);
+        }
+    }
+}
+
+impl Mask {
+    /// Creates [`Mask`] from ascending non intersecting runs in one pass with [`MaskBuilder`].
+    /// Gaps between runs get [`default`] style.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OverlapError`] if some run starts before the end of the previous one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color};
+    /// let tokens = (0..1000).map(|i| (i * 2..i * 2 + 1, Style::new().fg(Color::Red)));
+    /// let mask = Mask::from_sorted_runs(tokens).unwrap();
+    /// assert_eq!(mask[998], Style::new().fg(Color::Red));
+    /// assert_eq!(mask[999], Style::default());
+    ///
+    /// assert!(Mask::from_sorted_runs([(2..4, Style::new()), (0..1, Style::new())]).is_err());
+    /// ```
+    ///
+    /// [`default`]: Style::default
+    pub fn from_sorted_runs<R>(
+        runs: impl IntoIterator<Item = (R, Style)>,
+    ) -> Result<Mask, OverlapError>
+    where
+        R: Into<IdxRange>,
+    {
+        let mut builder = MaskBuilder::new();
+        for (range, style) in runs {
+            builder.push(range, style)?;
+        }
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mask, Color};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    const MAX: usize = usize::MAX;
+
+    #[rstest]
+    #[case::empty(vec![], Mask::new())]
+    #[case::full(
+        vec![(0..=MAX, Style::new().fg(Color::Red))],
+        mask!(.. => Style::new().fg(Color::Red))
+    )]
+    #[case::gaps(
+        vec![(1..=2, Style::new().fg(Color::Red)), (5..=5, Style::new().fg(Color::Blue))],
+        mask!(1..=2 => Style::new().fg(Color::Red), 5..=5 => Style::new().fg(Color::Blue))
+    )]
+    #[case::joined(
+        vec![(0..=2, Style::new().fg(Color::Red)), (3..=MAX, Style::new().fg(Color::Red))],
+        mask!(.. => Style::new().fg(Color::Red))
+    )]
+    fn build_from_sorted_runs(
+        #[case] runs: Vec<(std::ops::RangeInclusive<usize>, Style)>,
+        #[case] expected: Mask,
+    ) {
+        let mask = Mask::from_sorted_runs(runs).unwrap();
+        assert_eq!(mask, expected);
+        assert_eq!(mask.iter().len(), expected.iter().len());
+    }
+
+    #[test]
+    fn empty_ranges_are_ignored() {
+        let mut builder = MaskBuilder::new();
+        builder.push(IdxRange::new(3, 2), Style::new().fg(Color::Red)).unwrap();
+        builder.push(0..1, Style::new().fg(Color::Blue)).unwrap();
+        assert_eq!(builder.build(), mask!(0..1 => Style::new().fg(Color::Blue)));
+    }
+
+    #[test]
+    fn same_as_add() {
+        let runs: Vec<_> = (0..100)
+            .map(|i| {
+                (
+                    i * 3..i * 3 + 2,
+                    Style::new().fg(if i % 3 == 0 { Color::Red } else { Color::Blue }),
+                )
+            })
+            .collect();
+        assert_eq!(Mask::from_sorted_runs(runs.clone()).unwrap(), Mask::from_iter(runs));
+    }
+
+    #[rstest]
+    #[case::overlap(IdxRange::new(0, 5), IdxRange::new(5, 6))]
+    #[case::descending(IdxRange::new(4, 5), IdxRange::new(0, 1))]
+    #[case::after_max(IdxRange::new(0, MAX), IdxRange::new(MAX, MAX))]
+    fn overlapping_runs(#[case] previous: IdxRange, #[case] range: IdxRange) {
+        let mut builder = MaskBuilder::new();
+        builder.push(previous, Style::new().fg(Color::Red)).unwrap();
+        assert_eq!(builder.push(range, Style::default()), Err(OverlapError { previous, range }));
+        assert_eq!(builder.build(), Mask::from([(previous, Style::new().fg(Color::Red))]));
+    }
+
+    #[test]
+    fn overlap_error_message() {
+        let err = Mask::from_sorted_runs([(0..5, Style::new()), (3..4, Style::new())]).unwrap_err();
+        assert_eq!(err.to_string(), "run [3;3] overlaps or precedes previous run [0;4]");
+    }
+}
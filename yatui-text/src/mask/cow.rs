@@ -1,13 +1,108 @@
-use std::{iter::FusedIterator, ops::RangeBounds};
-
-use btree_range_map::{
-    generic::map::{IntoIter, Iter},
-    AnyRange, DefaultMapContainer, RangeMap,
+mod runs;
+mod tree;
+
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    iter::{FusedIterator, Peekable},
+    ops::RangeBounds,
+    sync::Arc,
 };
 
-use crate::{IdxRange, Style};
+use super::Attr;
+use crate::IdxRange;
+use runs::RunVec;
+use tree::RunTree;
+
+/// Count of runs after which [`StoragePolicy::Auto`] switches from vector to B-tree. Edits in the
+/// middle of the vector get slower than edits of B-tree between one and two thousands runs (see
+/// `benches/mask.rs`), lookups of vector are a bit faster at any size. Splices move ends of all
+/// runs after the edit in both storages, vector does it faster.
+const MAX_VEC_RUNS: usize = 1 << 10;
+/// Count of runs below which [`StoragePolicy::Auto`] switches from B-tree back to vector. It is
+/// lower than [`MAX_VEC_RUNS`] to not switch back and forth on every edit.
+const MIN_TREE_RUNS: usize = 1 << 9;
+
+/// Storage which is used by [`RangeAttrs`] with more than one attribute.
+///
+/// [`RangeAttrs`]: crate::mask::RangeAttrs
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum StoragePolicy {
+    /// Sorted vector for masks with up to a thousand runs and B-tree for bigger ones.
+    #[default]
+    Auto,
+    /// Sorted vector of runs. Lookup is a binary search, but every edit shifts all runs after
+    /// the edited range. The first few runs are stored without allocation.
+    Vec,
+    /// B-tree of runs. Lookups are logarithmic, edits take logarithmic time plus time
+    /// proportional to count of runs inside the edited range. Splices also move ends of all runs
+    /// after the edit. Every run has the overhead of a tree node.
+    BTree,
+}
+
+/// Storage of attributes for `Multiple` variant of [`Cow`]. Storage always covers all indexes
+/// `0..=usize::MAX` and every index has exactly one attribute. Adjacent runs always have different
+/// attributes: edits join runs which they touched.
+pub(super) trait MaskStorage<T: Attr>: Clone {
+    type Iter<'a>: DoubleEndedIterator<Item = (IdxRange, &'a T)> + ExactSizeIterator + FusedIterator
+    where
+        Self: 'a,
+        T: 'a;
+    type Range<'a>: DoubleEndedIterator<Item = (IdxRange, &'a T)> + FusedIterator
+    where
+        Self: 'a,
+        T: 'a;
+    type Runs: DoubleEndedIterator<Item = (IdxRange, T)> + ExactSizeIterator + FusedIterator;
+
+    /// Creates storage from ascending runs which cover all indexes without gaps.
+    fn from_runs(runs: impl IntoIterator<Item = (IdxRange, T)>) -> Self;
+
+    /// Returns attribute of index `idx`.
+    fn get(&self, idx: usize) -> &T;
+
+    /// Set `attr` for all indexes in `range`.
+    fn replace(&mut self, range: IdxRange, attr: T);
+
+    /// Replace attributes in `range` with the result of `f`.
+    fn update(&mut self, range: IdxRange, f: impl Fn(&T) -> T);
+
+    /// Replaces `removed` indexes starting at `start` with `inserted` indexes with default
+    /// attribute. Only runs after `start` are moved.
+    fn splice(&mut self, start: usize, removed: usize, inserted: usize);
+
+    /// Returns count of runs.
+    fn run_count(&self) -> usize;
+
+    /// Returns index of the run which contains `idx`.
+    fn run_position(&self, idx: usize) -> usize;
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Returns iterator over runs starting from the run which contains `idx`.
+    fn range_from(&self, idx: usize) -> Self::Range<'_>;
 
-/// It is a smart enum for `Mask` content.
+    fn into_runs(self) -> Self::Runs;
+}
+
+/// Returns the last index of a run which ended at `end` after `removed` indexes before it were
+/// replaced with `inserted` indexes. The last run always ends at `usize::MAX`, `None` means that
+/// the run is moved past `usize::MAX`.
+fn spliced_end(end: usize, removed: usize, inserted: usize) -> Option<usize> {
+    match end {
+        usize::MAX => Some(usize::MAX),
+        end => (end - removed).checked_add(inserted),
+    }
+}
+
+/// Storage of `Multiple` variant of [`Cow`]: one of the backends.
+#[derive(Debug, Clone)]
+pub(super) enum Storage<T> {
+    Vec(RunVec<T>),
+    Tree(RunTree<T>),
+}
+
+/// It is a smart enum for `RangeAttrs` content. Comments below talk about styles of `Mask`, but
+/// the same is true for any attributes.
 /// Simply, it has two options:
 /// 1. Single - Single style for one unbounded range (0..=usize::MAX strictly speaking).
 /// 2. Multiple - Multiple styles for different ranges.
@@ -15,68 +110,258 @@ use crate::{IdxRange, Style};
 /// The struct is designed to avoid memory allocation for the most common case when all Text's
 ///     graphemes should have single style (Also default mask also have single style Style::default).
 ///     When mutation of styles needed, it converts `Single` variant to `Multiple` (like std::Cow).
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub(super) enum Cow {
-    Single(Style),
-    Multiple(RangeMap<usize, Style>),
+///
+/// Storage of `Multiple` is shared between clones, so cloning is O(1). It is copied on the first
+/// mutation of a shared storage.
+///
+/// Equality and hashing compare styles of graphemes, not the internal representation: `Single(s)`
+/// is equal to `Multiple` with `s` for all indexes.
+#[derive(Debug, Clone)]
+pub(super) enum Cow<T> {
+    Single(T),
+    Multiple(Arc<Storage<T>>),
+}
+
+pub(super) type CowIter<'a, T> = CowIterCommon<&'a T, <Storage<T> as MaskStorage<T>>::Iter<'a>>;
+
+pub(super) type CowRange<'a, T> = CowIterCommon<&'a T, <Storage<T> as MaskStorage<T>>::Range<'a>>;
+
+pub(super) type CowIntoIter<T> = CowIterCommon<T, <Storage<T> as MaskStorage<T>>::Runs>;
+
+impl<T: Attr> Storage<T> {
+    /// Creates storage from ascending runs which cover all indexes with backend chosen by
+    /// `policy`.
+    fn from_runs_with(
+        policy: StoragePolicy,
+        runs: impl IntoIterator<Item = (IdxRange, T)>,
+    ) -> Self {
+        match policy {
+            StoragePolicy::BTree => Storage::Tree(MaskStorage::from_runs(runs)),
+            _ => Storage::Vec(RunVec::from_runs(runs)).with_policy(policy),
+        }
+    }
+
+    /// Moves runs to the backend which is preferred by `policy` for current count of runs.
+    pub(super) fn with_policy(self, policy: StoragePolicy) -> Self {
+        match (self.prefers_tree(policy), self) {
+            (false, Storage::Tree(tree)) => Storage::Vec(RunVec::from_runs(tree.into_runs())),
+            (true, Storage::Vec(vec)) => Storage::Tree(RunTree::from_runs(vec.into_runs())),
+            (_, storage) => storage,
+        }
+    }
+
+    /// Returns `true` if `policy` prefers B-tree for current count of runs.
+    fn prefers_tree(&self, policy: StoragePolicy) -> bool {
+        let count = self.run_count();
+        match policy {
+            StoragePolicy::Vec => false,
+            StoragePolicy::BTree => true,
+            StoragePolicy::Auto if self.is_tree() => count >= MIN_TREE_RUNS,
+            StoragePolicy::Auto => count > MAX_VEC_RUNS,
+        }
+    }
+
+    /// Returns `true` if storage is B-tree.
+    pub(super) fn is_tree(&self) -> bool {
+        matches!(self, Storage::Tree(_))
+    }
 }
 
-pub(super) type CowIter<'a> =
-    CowIterCommon<&'a Style, Iter<'a, usize, Style, DefaultMapContainer<usize, Style>>>;
+impl<T: Attr> MaskStorage<T> for Storage<T> {
+    type Iter<'a>
+        = Either<<RunVec<T> as MaskStorage<T>>::Iter<'a>, <RunTree<T> as MaskStorage<T>>::Iter<'a>>
+    where
+        T: 'a;
+    type Range<'a>
+        =
+        Either<<RunVec<T> as MaskStorage<T>>::Range<'a>, <RunTree<T> as MaskStorage<T>>::Range<'a>>
+    where
+        T: 'a;
+    type Runs = Either<<RunVec<T> as MaskStorage<T>>::Runs, <RunTree<T> as MaskStorage<T>>::Runs>;
+
+    fn from_runs(runs: impl IntoIterator<Item = (IdxRange, T)>) -> Self {
+        Storage::from_runs_with(StoragePolicy::Auto, runs)
+    }
+
+    fn get(&self, idx: usize) -> &T {
+        match self {
+            Storage::Vec(vec) => vec.get(idx),
+            Storage::Tree(map) => MaskStorage::get(map, idx),
+        }
+    }
 
-pub(super) type CowIntoIter =
-    CowIterCommon<Style, IntoIter<usize, Style, DefaultMapContainer<usize, Style>>>;
+    fn replace(&mut self, range: IdxRange, attr: T) {
+        match self {
+            Storage::Vec(vec) => vec.replace(range, attr),
+            Storage::Tree(map) => MaskStorage::replace(map, range, attr),
+        }
+    }
 
-impl Cow {
+    fn update(&mut self, range: IdxRange, f: impl Fn(&T) -> T) {
+        match self {
+            Storage::Vec(vec) => vec.update(range, f),
+            Storage::Tree(map) => MaskStorage::update(map, range, f),
+        }
+    }
+
+    fn splice(&mut self, start: usize, removed: usize, inserted: usize) {
+        match self {
+            Storage::Vec(vec) => vec.splice(start, removed, inserted),
+            Storage::Tree(map) => map.splice(start, removed, inserted),
+        }
+    }
+
+    fn run_count(&self) -> usize {
+        match self {
+            Storage::Vec(vec) => vec.run_count(),
+            Storage::Tree(map) => map.run_count(),
+        }
+    }
+
+    fn run_position(&self, idx: usize) -> usize {
+        match self {
+            Storage::Vec(vec) => vec.run_position(idx),
+            Storage::Tree(map) => map.run_position(idx),
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        match self {
+            Storage::Vec(vec) => Either::Left(vec.iter()),
+            Storage::Tree(map) => Either::Right(MaskStorage::iter(map)),
+        }
+    }
+
+    fn range_from(&self, idx: usize) -> Self::Range<'_> {
+        match self {
+            Storage::Vec(vec) => Either::Left(vec.range_from(idx)),
+            Storage::Tree(map) => Either::Right(map.range_from(idx)),
+        }
+    }
+
+    fn into_runs(self) -> Self::Runs {
+        match self {
+            Storage::Vec(vec) => Either::Left(vec.into_runs()),
+            Storage::Tree(map) => Either::Right(map.into_runs()),
+        }
+    }
+}
+
+impl<T: Attr> Cow<T> {
     pub(super) fn new() -> Self {
-        Self::Single(Style::default())
+        Self::Single(T::default())
     }
 
     /// Always converts `single` variant of cow to `multiple` with memory allocation if cow is
-    /// not `multiple` already
-    pub(super) fn to_mut(&mut self) -> &mut RangeMap<usize, Style> {
-        match *self {
-            Self::Single(s) => {
-                let mut map = RangeMap::new();
-                map.insert(0..=usize::MAX, s);
-                *self = Self::Multiple(map);
-                let Self::Multiple(ref mut map) = self else { unreachable!() };
-                map
-            }
-            Self::Multiple(ref mut map) => map,
+    /// not `multiple` already. Storage is chosen by `policy`.
+    pub(super) fn to_mut(&mut self, policy: StoragePolicy) -> &mut Storage<T> {
+        if let Self::Single(s) = self {
+            let run = (IdxRange::new(0, usize::MAX), std::mem::take(s));
+            *self = Self::Multiple(Arc::new(Storage::from_runs_with(policy, [run])));
         }
+        let Self::Multiple(storage) = self else { unreachable!() };
+        Arc::make_mut(storage)
     }
 
-    pub(super) fn add_style(&mut self, range: impl Into<IdxRange>, style: Style) {
+    pub(super) fn add_style(
+        &mut self,
+        range: impl Into<IdxRange>,
+        style: T,
+        policy: StoragePolicy,
+    ) {
         let range = range.into();
         match self {
-            Cow::Single(s) if range.is_full() => *s = s.merge(style),
-            _ => self.to_mut().update(range, |styles| {
-                Some(match styles {
-                    Some(cur_style) => cur_style.merge(style),
-                    None => style,
-                })
-            }),
+            Cow::Single(s) if range.is_full() => *s = s.combine(&style),
+            _ => self.to_mut(policy).update(range, |cur_style| cur_style.combine(&style)),
         }
     }
 
-    pub(super) fn replace_style(&mut self, range: impl Into<IdxRange>, style: Style) {
+    pub(super) fn replace_style(
+        &mut self,
+        range: impl Into<IdxRange>,
+        style: T,
+        policy: StoragePolicy,
+    ) {
         let range = range.into();
         match self {
             Cow::Single(s) if range.is_full() => {
                 *s = style;
             }
-            _ => self.to_mut().insert(range, style),
+            _ => self.to_mut(policy).replace(range, style),
         }
     }
 
-    pub(super) fn iter(&self) -> CowIter<'_> {
+    /// Replaces `removed` indexes starting at `start` with `inserted` indexes with default
+    /// attribute. Attributes after the removed indexes are shifted, attributes shifted past
+    /// `usize::MAX` are dropped.
+    pub(super) fn splice(
+        &mut self,
+        start: usize,
+        removed: usize,
+        inserted: usize,
+        policy: StoragePolicy,
+    ) {
+        match self {
+            _ if removed == 0 && inserted == 0 => {}
+            Cow::Single(s) if *s == T::default() => {}
+            _ => {
+                self.to_mut(policy).splice(start, removed, inserted);
+                self.normalize(policy);
+            }
+        }
+    }
+
+    pub(super) fn iter(&self) -> CowIter<'_, T> {
         match self {
             Cow::Single(s) => CowIter::Single(Some(s)),
             Cow::Multiple(m) => CowIter::Multiple(m.iter()),
         }
     }
 
+    /// Returns iterator which starts from the run containing `idx`.
+    /// Returns index of the run which contains `idx`.
+    pub(super) fn run_position(&self, idx: usize) -> usize {
+        match self {
+            Cow::Single(_) => 0,
+            Cow::Multiple(m) => m.run_position(idx),
+        }
+    }
+
+    pub(super) fn range_from(&self, idx: usize) -> CowRange<'_, T> {
+        match self {
+            Cow::Single(s) => CowRange::Single(Some(s)),
+            Cow::Multiple(m) => CowRange::Multiple(m.range_from(idx)),
+        }
+    }
+
+    /// Brings cow to the canonical form: converts `multiple` variant back to `single` (freeing
+    /// memory) when one style covers all indexes. Storage coalesces runs on every edit, so it is
+    /// not scanned. Runs are moved to the storage preferred by `policy`.
+    pub(super) fn normalize(&mut self, policy: StoragePolicy) {
+        let Cow::Multiple(storage) = self else { return };
+        if storage.run_count() == 1 {
+            *self = Cow::Single(storage.get(0).clone());
+        } else if storage.prefers_tree(policy) != storage.is_tree() {
+            let Cow::Multiple(storage) = std::mem::take(self) else { unreachable!() };
+            *self = Cow::Multiple(Arc::new(Arc::unwrap_or_clone(storage).with_policy(policy)));
+        }
+    }
+
+    /// Creates normalized cow from sorted non intersecting `runs` which cover all indexes.
+    pub(super) fn from_runs(
+        runs: impl IntoIterator<Item = (IdxRange, T)>,
+        policy: StoragePolicy,
+    ) -> Self {
+        let mut cow = Cow::Multiple(Arc::new(Storage::from_runs_with(policy, runs)));
+        cow.normalize(policy);
+        cow
+    }
+
+    /// Returns iterator over ranges where adjacent ranges always have different styles.
+    fn runs(&self) -> Coalesce<CowIter<'_, T>> {
+        Coalesce::new(self.iter())
+    }
+
     /// Returns `true` if it had allocated memory.
     pub(super) fn is_owned(&self) -> bool {
         match *self {
@@ -86,27 +371,43 @@ impl Cow {
     }
 }
 
-impl Default for Cow {
+impl<T: Attr> Default for Cow<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl IntoIterator for Cow {
-    type Item = <CowIntoIter as Iterator>::Item;
-    type IntoIter = CowIntoIter;
+impl<T: Attr> PartialEq for Cow<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.runs().eq(other.runs())
+    }
+}
+
+impl<T: Attr + Eq> Eq for Cow<T> {}
+
+impl<T: Attr + Hash> Hash for Cow<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for run in self.runs() {
+            run.hash(state);
+        }
+    }
+}
+
+impl<T: Attr> IntoIterator for Cow<T> {
+    type Item = <CowIntoIter<T> as Iterator>::Item;
+    type IntoIter = CowIntoIter<T>;
 
     fn into_iter(self) -> Self::IntoIter {
         match self {
             Cow::Single(s) => CowIntoIter::Single(Some(s)),
-            Cow::Multiple(m) => CowIntoIter::Multiple(m.into_iter()),
+            Cow::Multiple(m) => CowIntoIter::Multiple(Arc::unwrap_or_clone(m).into_runs()),
         }
     }
 }
 
 // Common iterator type for cow for owned and borrowed types
-// S - it is style type: owned (Style) or borrowed (&Style)
-// I - it is iterator of btree_range_map dependency: owned (IntoIter) or borrowed (Iter)
+// S - it is attribute type: owned (T) or borrowed (&T)
+// I - it is iterator of storage: owned (Runs) or borrowed (Iter)
 pub(super) enum CowIterCommon<S, I> {
     Single(Option<S>),
     Multiple(I),
@@ -166,22 +467,103 @@ where
 {
 }
 
+/// Iterator over one of two storage backends.
+pub(super) enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L, R> Iterator for Either<L, R>
+where
+    L: Iterator,
+    R: Iterator<Item = L::Item>,
+{
+    type Item = L::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Either::Left(l) => l.next(),
+            Either::Right(r) => r.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Either::Left(l) => l.size_hint(),
+            Either::Right(r) => r.size_hint(),
+        }
+    }
+}
+
+impl<L, R> DoubleEndedIterator for Either<L, R>
+where
+    L: DoubleEndedIterator,
+    R: DoubleEndedIterator<Item = L::Item>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            Either::Left(l) => l.next_back(),
+            Either::Right(r) => r.next_back(),
+        }
+    }
+}
+
+impl<L, R> ExactSizeIterator for Either<L, R>
+where
+    L: ExactSizeIterator,
+    R: ExactSizeIterator<Item = L::Item>,
+{
+}
+
+impl<L, R> FusedIterator for Either<L, R>
+where
+    L: FusedIterator,
+    R: FusedIterator<Item = L::Item>,
+{
+}
+
+/// Iterator adapter which joins adjacent ranges with equal attributes.
+pub(super) struct Coalesce<I: Iterator> {
+    inner: Peekable<I>,
+}
+
+impl<I: Iterator> Coalesce<I> {
+    fn new(inner: I) -> Self {
+        Self { inner: inner.peekable() }
+    }
+}
+
+impl<'a, I, T> Iterator for Coalesce<I>
+where
+    I: Iterator<Item = (IdxRange, &'a T)>,
+    T: PartialEq + 'a,
+{
+    type Item = (IdxRange, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut range, style) = self.inner.next()?;
+        while let Some((next, _)) = self.inner.next_if(|(_, next_style)| *next_style == style) {
+            range.end = next.end;
+        }
+        Some((range, style))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Color;
+    use crate::{Color, Style};
     use pretty_assertions::assert_eq;
     use rstest::rstest;
     use rstest_reuse::{self, *};
 
-    // No macro in btree_range_map crate :(
-    macro_rules! range_map {
+    type Cow = super::Cow<Style>;
+
+    // Tree storage with specified runs
+    macro_rules! tree {
         ($($range:expr => $style:expr),+ ,) => {{
-            let mut map = RangeMap::new();
-            $(
-                map.insert($range, $style);
-            )*
-            map
+            let runs = [$((IdxRange::from($range), $style)),+];
+            Arc::new(Storage::Tree(MaskStorage::from_runs(runs)))
         }}
     }
 
@@ -194,14 +576,14 @@ mod tests {
 
     #[test]
     fn multiple_cow_is_owned() {
-        assert!(Cow::Multiple(RangeMap::new()).is_owned());
+        assert!(Cow::Multiple(tree!(.. => Style::default(),)).is_owned());
     }
 
     #[test]
     fn cow_single_to_mut() {
         let mut cow = Cow::Single(Style::new().fg(Color::Red));
-        cow.to_mut();
-        assert_eq!(cow, Cow::Multiple(range_map!(0..=usize::MAX => Style::new().fg(Color::Red),)));
+        assert!(!cow.to_mut(StoragePolicy::Auto).is_tree());
+        assert_eq!(cow, Cow::Multiple(tree!(0..=usize::MAX => Style::new().fg(Color::Red),)));
     }
 
     #[template]
@@ -216,7 +598,7 @@ mod tests {
         vec![
             (IdxRange::from(5..), Style::new().fg(Color::Red))
         ],
-        Cow::Multiple(range_map!(
+        Cow::Multiple(tree!(
             0..=4 => Style::default(),
             5.. => Style::new().fg(Color::Red),
         ))
@@ -226,7 +608,7 @@ mod tests {
             (IdxRange::from(1..=2), Style::new().fg(Color::Red)),
             (IdxRange::from(6..=8), Style::new().fg(Color::Green))
         ],
-        Cow::Multiple(range_map!(
+        Cow::Multiple(tree!(
             0..=0 => Style::default(),
             1..=2 => Style::new().fg(Color::Red),
             3..6 => Style::default(),
@@ -249,7 +631,7 @@ mod tests {
             (IdxRange::from(1..=2), Style::new().fg(Color::Red)),
             (IdxRange::from(2..4), Style::new().bg(Color::Green))
         ],
-        Cow::Multiple(range_map!(
+        Cow::Multiple(tree!(
             0..=0 => Style::default(),
             1..=1 => Style::new().fg(Color::Red),
             2..=2 => Style::new().fg(Color::Red).bg(Color::Green),
@@ -257,11 +639,16 @@ mod tests {
             4.. => Style::default(),
         ))
     )]
-    fn cow_add_styles(#[case] styles: Vec<(IdxRange, Style)>, #[case] expected: Cow) {
+    fn cow_add_styles(
+        #[case] styles: Vec<(IdxRange, Style)>,
+        #[case] expected: Cow,
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
         let mut cow = Cow::default();
         for (range, style) in styles {
-            cow.add_style(range, style);
+            cow.add_style(range, style, policy);
         }
+        assert_eq!(cow.is_owned(), expected.is_owned());
         assert_eq!(cow, expected);
     }
 
@@ -278,21 +665,285 @@ mod tests {
             (IdxRange::from(1..=2), Style::new().fg(Color::Red)),
             (IdxRange::from(2..4), Style::new().bg(Color::Green))
         ],
-        Cow::Multiple(range_map!(
+        Cow::Multiple(tree!(
             0..=0 => Style::default(),
             1..=1 => Style::new().fg(Color::Red),
             2..4 => Style::new().bg(Color::Green),
             4.. => Style::default(),
         ))
     )]
-    fn cow_replace_styles(#[case] styles: Vec<(IdxRange, Style)>, #[case] expected: Cow) {
+    fn cow_replace_styles(
+        #[case] styles: Vec<(IdxRange, Style)>,
+        #[case] expected: Cow,
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
         let mut cow = Cow::default();
         for (range, style) in styles {
-            cow.replace_style(range, style);
+            cow.replace_style(range, style, policy);
         }
+        assert_eq!(cow.is_owned(), expected.is_owned());
+        assert_eq!(cow, expected);
+    }
+
+    #[test]
+    fn single_equals_full_multiple() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let style = Style::new().fg(Color::Red);
+        let single = Cow::Single(style);
+        let multiple = Cow::Multiple(tree!(0..=usize::MAX => style,));
+        let split = Cow::Multiple(tree!(0..=4 => style, 5.. => style.bg(Color::Green),));
+
+        let state = RandomState::new();
+        assert_eq!(single, multiple);
+        assert_eq!(state.hash_one(&single), state.hash_one(&multiple));
+        assert_ne!(single, split);
+    }
+
+    #[rstest]
+    #[case::single(Cow::Single(Style::default()), Cow::Single(Style::default()))]
+    #[case::full_multiple(
+        Cow::Multiple(tree!(0..=usize::MAX => Style::new().fg(Color::Red),)),
+        Cow::Single(Style::new().fg(Color::Red))
+    )]
+    #[case::different_styles(
+        Cow::Multiple(tree!(
+            0..=4 => Style::default(),
+            5.. => Style::new().fg(Color::Red),
+        )),
+        Cow::Multiple(tree!(
+            0..=4 => Style::default(),
+            5.. => Style::new().fg(Color::Red),
+        ))
+    )]
+    fn cow_normalize(#[case] mut cow: Cow, #[case] expected: Cow) {
+        cow.normalize(StoragePolicy::Auto);
+        assert_eq!(cow.is_owned(), expected.is_owned());
         assert_eq!(cow, expected);
     }
 
+    #[rstest]
+    fn edits_coalesce_equal_neighbours(
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        let (red, green) = (Style::new().fg(Color::Red), Style::new().fg(Color::Green));
+        let mut cow = Cow::new();
+        cow.replace_style(0..=1, red, policy);
+        cow.replace_style(2..=3, green, policy);
+        cow.normalize(policy);
+        assert_eq!(cow.iter().len(), 3);
+
+        cow.replace_style(2..=3, red, policy);
+        cow.add_style(4.., red, policy);
+        cow.normalize(policy);
+        assert!(matches!(cow, Cow::Single(s) if s == red));
+    }
+
+    #[test]
+    fn auto_policy_switches_storage() {
+        let style = Style::new().fg(Color::Red);
+        let runs = (0..=MAX_VEC_RUNS).map(|idx| {
+            let style = if idx % 2 == 0 { style } else { Style::default() };
+            let end = if idx == MAX_VEC_RUNS { usize::MAX } else { idx };
+            (IdxRange::new(idx, end), style)
+        });
+        let cow = Cow::from_runs(runs, StoragePolicy::Auto);
+        assert!(matches!(&cow, Cow::Multiple(storage) if storage.is_tree()));
+
+        let tree = tree! {
+            0..=9 => style,
+            10..=usize::MAX => Style::default(),
+        };
+        assert!(!Arc::unwrap_or_clone(tree).with_policy(StoragePolicy::Auto).is_tree());
+    }
+
+    #[test]
+    fn backends_are_equal() {
+        let runs = [
+            (IdxRange::new(0, 3), Style::new().fg(Color::Red)),
+            (IdxRange::new(4, 9), Style::default()),
+            (IdxRange::new(10, usize::MAX), Style::new().bg(Color::Blue)),
+        ];
+        let vec = Cow::from_runs(runs, StoragePolicy::Vec);
+        let tree = Cow::from_runs(runs, StoragePolicy::BTree);
+
+        assert!(matches!(&vec, Cow::Multiple(storage) if !storage.is_tree()));
+        assert!(matches!(&tree, Cow::Multiple(storage) if storage.is_tree()));
+        assert_eq!(vec, tree);
+        assert!(vec.iter().eq(tree.iter()));
+    }
+
+    const MAX: usize = usize::MAX;
+
+    fn runs(storage: &Storage<Style>) -> Vec<(usize, usize, Style)> {
+        storage.iter().map(|(range, style)| (range.start, range.end, *style)).collect()
+    }
+
+    fn red() -> Style {
+        Style::new().fg(Color::Red)
+    }
+
+    fn empty_storage(policy: StoragePolicy) -> Storage<Style> {
+        Storage::from_runs_with(policy, [(IdxRange::new(0, MAX), Style::default())])
+    }
+
+    #[rstest]
+    #[case::start(IdxRange::new(0, 2), vec![(0, 2, red()), (3, MAX, Style::default())])]
+    #[case::middle(
+        IdxRange::new(3, 4),
+        vec![(0, 2, Style::default()), (3, 4, red()), (5, MAX, Style::default())]
+    )]
+    #[case::end(IdxRange::new(3, MAX), vec![(0, 2, Style::default()), (3, MAX, red())])]
+    #[case::full(IdxRange::new(0, MAX), vec![(0, MAX, red())])]
+    fn storage_replace_in_single_run(
+        #[case] range: IdxRange,
+        #[case] expected: Vec<(usize, usize, Style)>,
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        let mut storage = empty_storage(policy);
+        storage.replace(range, red());
+        assert_eq!(runs(&storage), expected);
+    }
+
+    #[rstest]
+    fn storage_replace_joins_neighbours(
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        let mut storage = empty_storage(policy);
+        storage.replace(IdxRange::new(2, 3), red());
+        storage.replace(IdxRange::new(6, 7), red());
+        assert_eq!(
+            runs(&storage),
+            vec![
+                (0, 1, Style::default()),
+                (2, 3, red()),
+                (4, 5, Style::default()),
+                (6, 7, red()),
+                (8, MAX, Style::default()),
+            ]
+        );
+
+        storage.replace(IdxRange::new(4, 5), red());
+        assert_eq!(
+            runs(&storage),
+            vec![(0, 1, Style::default()), (2, 7, red()), (8, MAX, Style::default())]
+        );
+    }
+
+    #[rstest]
+    fn storage_update_splits_and_joins_runs(
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        let bold = Style::new().bg(Color::Blue);
+        let mut storage = empty_storage(policy);
+        storage.replace(IdxRange::new(2, 5), red());
+        storage.update(IdxRange::new(4, 8), |style| style.merge(bold));
+        assert_eq!(
+            runs(&storage),
+            vec![
+                (0, 1, Style::default()),
+                (2, 3, red()),
+                (4, 5, red().merge(bold)),
+                (6, 8, bold),
+                (9, MAX, Style::default()),
+            ]
+        );
+
+        storage.update(IdxRange::new(0, MAX), |_| Style::default());
+        assert_eq!(runs(&storage), vec![(0, MAX, Style::default())]);
+    }
+
+    #[rstest]
+    fn storage_iterate_from_both_ends(
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        let storage = Storage::from_runs_with(
+            policy,
+            [
+                (IdxRange::new(0, 1), red()),
+                (IdxRange::new(2, 4), Style::default()),
+                (IdxRange::new(5, MAX), red()),
+            ],
+        );
+
+        let mut iter = storage.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back(), Some((IdxRange::new(5, MAX), &red())));
+        assert_eq!(iter.next(), Some((IdxRange::new(0, 1), &red())));
+        assert_eq!(iter.next_back(), Some((IdxRange::new(2, 4), &Style::default())));
+        assert_eq!(iter.next(), None);
+
+        let from: Vec<_> = storage.range_from(3).map(|(range, _)| range).collect();
+        assert_eq!(from, vec![IdxRange::new(2, 4), IdxRange::new(5, MAX)]);
+
+        let reversed: Vec<_> = storage.into_runs().rev().map(|(range, _)| range.start).collect();
+        assert_eq!(reversed, vec![5, 2, 0]);
+    }
+
+    /// Applies the same random edits to storage and to a naive vector with a style per index and
+    /// checks that they are always equal. Indexes past the end of the vector have the style of
+    /// its last index.
+    #[rstest]
+    fn storage_matches_model(
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] policy: StoragePolicy,
+    ) {
+        const LEN: usize = 4096;
+        const EDITED: usize = 128;
+
+        let styles = [Style::default(), red(), Style::new().bg(Color::Blue)];
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        let mut storage = empty_storage(policy);
+        let mut model = vec![Style::default(); LEN];
+        for _ in 0..300 {
+            let start = random(EDITED);
+            let len = random(16);
+            let style = styles[random(styles.len())];
+            match random(4) {
+                0 => {
+                    storage.replace(IdxRange::new(start, start + len), style);
+                    model[start..=start + len].fill(style);
+                }
+                1 => {
+                    storage.update(IdxRange::new(start, start + len), |s| s.merge(style));
+                    model[start..=start + len].iter_mut().for_each(|s| *s = s.merge(style));
+                }
+                2 => {
+                    storage.replace(IdxRange::new(start, MAX), style);
+                    model[start..].fill(style);
+                }
+                _ => {
+                    let inserted = random(8);
+                    storage.splice(start, len, inserted);
+                    model.splice(start..start + len, vec![Style::default(); inserted]);
+                    model.resize(LEN, *model.last().unwrap());
+                }
+            }
+
+            let runs = runs(&storage);
+            assert_eq!(runs.len(), storage.run_count());
+            assert_eq!(runs.last().map(|run| run.1), Some(MAX));
+            for (run, next) in runs.iter().zip(&runs[1..]) {
+                assert_eq!(run.1 + 1, next.0, "{runs:?}");
+                assert_ne!(run.2, next.2, "{runs:?}");
+            }
+            let idx = random(LEN);
+            let position = runs.iter().position(|run| run.1 >= idx);
+            assert_eq!(Some(storage.run_position(idx)), position);
+            for (start, end, style) in runs.into_iter().take_while(|run| run.0 < LEN) {
+                let expected = &model[start..=end.min(LEN - 1)];
+                assert!(expected.iter().all(|s| *s == style), "{start}..={end}");
+            }
+            assert_eq!(*storage.get(MAX), model[LEN - 1]);
+        }
+    }
+
     #[test]
     fn cow_check_iter_traits() {
         fn check_iter<I, S>(i: I)
@@ -308,4 +959,22 @@ mod tests {
         check_iter(cow.iter());
         check_iter(cow.into_iter());
     }
+
+    #[test]
+    fn clone_shares_storage() {
+        let red = Style::new().fg(Color::Red);
+        let mut cow = Cow::new();
+        cow.add_style(0..4, red, StoragePolicy::Auto);
+
+        let mut clone = cow.clone();
+        let (Cow::Multiple(a), Cow::Multiple(b)) = (&cow, &clone) else { panic!("cow is owned") };
+        assert!(Arc::ptr_eq(a, b));
+
+        clone.add_style(4..6, red, StoragePolicy::Auto);
+        clone.normalize(StoragePolicy::Auto);
+        let (Cow::Multiple(a), Cow::Multiple(b)) = (&cow, &clone) else { panic!("cow is owned") };
+        assert!(!Arc::ptr_eq(a, b));
+        assert_eq!(cow.iter().next(), Some((IdxRange::new(0, 3), &red)));
+        assert_eq!(clone.iter().next(), Some((IdxRange::new(0, 5), &red)));
+    }
 }
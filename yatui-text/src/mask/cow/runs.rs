@@ -0,0 +1,360 @@
+use std::{iter::FusedIterator, ops::Range};
+
+use crate::{
+    mask::{
+        cow::{spliced_end, MaskStorage},
+        Attr,
+    },
+    IdxRange,
+};
+
+/// Count of runs which are stored inline without memory allocation.
+const INLINE_RUNS: usize = 4;
+
+/// The last index of the range and its attribute. The range starts right after the previous run.
+type Run<T> = (usize, T);
+
+/// Sorted run-length storage of attributes. The last run always ends at `usize::MAX` and adjacent
+/// runs always have different attributes.
+///
+/// All edits shift the tail of the vector, so it is fast only for small count of runs, but it has
+/// no per-node overhead and the first few runs do not allocate at all.
+#[derive(Debug, Clone)]
+pub(in crate::mask) enum RunVec<T> {
+    Inline { len: usize, runs: [Run<T>; INLINE_RUNS] },
+    Heap(Vec<Run<T>>),
+}
+
+/// An iterator over runs of [`RunVec`].
+pub(in crate::mask) struct Iter<'a, T> {
+    runs: &'a [Run<T>],
+    front: usize,
+    back: usize,
+}
+
+/// An owning iterator over runs of [`RunVec`].
+pub(in crate::mask) struct IntoIter<T> {
+    runs: RunVec<T>,
+    front: usize,
+    back: usize,
+}
+
+impl<T: Attr> RunVec<T> {
+    fn new() -> Self {
+        RunVec::Inline { len: 0, runs: std::array::from_fn(|_| (0, T::default())) }
+    }
+
+    fn as_slice(&self) -> &[Run<T>] {
+        match self {
+            RunVec::Inline { len, runs } => &runs[..*len],
+            RunVec::Heap(runs) => runs,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Run<T>] {
+        match self {
+            RunVec::Inline { len, runs } => &mut runs[..*len],
+            RunVec::Heap(runs) => runs,
+        }
+    }
+
+    /// Replaces runs in `range` with `with`. Moves runs to the heap if they do not fit inline.
+    fn replace_runs(&mut self, range: Range<usize>, with: &[Run<T>]) {
+        let new_len = self.as_slice().len() - range.len() + with.len();
+        match self {
+            RunVec::Inline { len, runs } if new_len <= INLINE_RUNS => {
+                // Unused runs after `len` are moved into the gap and overwritten
+                let tail = &mut runs[range.start..(*len).max(new_len)];
+                if with.len() > range.len() {
+                    tail.rotate_right(with.len() - range.len());
+                } else {
+                    tail.rotate_left(range.len() - with.len());
+                }
+                runs[range.start..range.start + with.len()].clone_from_slice(with);
+                for run in runs.iter_mut().take(*len).skip(new_len) {
+                    run.1 = T::default();
+                }
+                *len = new_len;
+            }
+            RunVec::Inline { .. } => {
+                let mut runs = Vec::with_capacity(new_len.next_power_of_two());
+                runs.extend_from_slice(self.as_slice());
+                runs.splice(range, with.iter().cloned());
+                *self = RunVec::Heap(runs);
+            }
+            RunVec::Heap(runs) => {
+                runs.splice(range, with.iter().cloned());
+            }
+        }
+    }
+
+    fn push(&mut self, run: Run<T>) {
+        let len = self.as_slice().len();
+        self.replace_runs(len..len, &[run]);
+    }
+
+    /// Returns index of the run which contains `idx`.
+    fn position(&self, idx: usize) -> usize {
+        self.as_slice().partition_point(|(end, _)| *end < idx)
+    }
+
+    fn start(runs: &[Run<T>], pos: usize) -> usize {
+        match pos {
+            0 => 0,
+            pos => runs[pos - 1].0 + 1,
+        }
+    }
+
+    /// Splits the run which contains `idx` so that a new run starts at `idx`. Returns position
+    /// of that run.
+    fn split_at(&mut self, idx: usize) -> usize {
+        let pos = self.position(idx);
+        let runs = self.as_slice();
+        if Self::start(runs, pos) < idx {
+            let run = (idx - 1, runs[pos].1.clone());
+            self.replace_runs(pos..pos, &[run]);
+            pos + 1
+        } else {
+            pos
+        }
+    }
+
+    /// Joins adjacent runs with equal attributes between positions `first` and `last`
+    /// (inclusive). Runs outside of them are not checked, so edits coalesce only runs which
+    /// they touched.
+    fn coalesce(&mut self, first: usize, last: usize) {
+        let runs = self.as_mut_slice();
+        let last = last.min(runs.len() - 1);
+        let mut kept = first;
+        for pos in first + 1..=last {
+            if runs[pos].1 == runs[kept].1 {
+                runs[kept].0 = runs[pos].0;
+            } else {
+                kept += 1;
+                runs.swap(kept, pos);
+            }
+        }
+
+        if kept < last {
+            self.replace_runs(kept + 1..last + 1, &[]);
+        }
+    }
+}
+
+impl<T: Attr> MaskStorage<T> for RunVec<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+    type Range<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+    type Runs = IntoIter<T>;
+
+    fn from_runs(runs: impl IntoIterator<Item = (IdxRange, T)>) -> Self {
+        let mut vec = RunVec::new();
+        for (range, attr) in runs {
+            match vec.as_mut_slice().last_mut() {
+                Some(last) if last.1 == attr => last.0 = range.end,
+                _ => vec.push((range.end, attr)),
+            }
+        }
+        debug_assert_eq!(vec.as_slice().last().map(|(end, _)| *end), Some(usize::MAX));
+        vec
+    }
+
+    fn get(&self, idx: usize) -> &T {
+        &self.as_slice()[self.position(idx)].1
+    }
+
+    fn replace(&mut self, range: IdxRange, attr: T) {
+        if range.is_empty() {
+            return;
+        }
+
+        let (first, last) = (self.position(range.start), self.position(range.end));
+        let runs = self.as_slice();
+
+        let mut with: [Run<T>; 3] = Default::default();
+        let mut len = 0;
+        if Self::start(runs, first) < range.start {
+            with[len] = (range.start - 1, runs[first].1.clone());
+            len += 1;
+        }
+        with[len] = (range.end, attr);
+        len += 1;
+        if runs[last].0 > range.end {
+            with[len] = runs[last].clone();
+            len += 1;
+        }
+
+        self.replace_runs(first..last + 1, &with[..len]);
+        self.coalesce(first.saturating_sub(1), first + len);
+    }
+
+    fn update(&mut self, range: IdxRange, f: impl Fn(&T) -> T) {
+        if range.is_empty() {
+            return;
+        }
+
+        let first = self.split_at(range.start);
+        let last = match range.end.checked_add(1) {
+            Some(next) => self.split_at(next) - 1,
+            None => self.as_slice().len() - 1,
+        };
+        for run in &mut self.as_mut_slice()[first..=last] {
+            run.1 = f(&run.1);
+        }
+        self.coalesce(first.saturating_sub(1), last + 1);
+    }
+
+    fn splice(&mut self, start: usize, removed: usize, inserted: usize) {
+        let first = self.split_at(start);
+        let len = self.run_count();
+        let Some(tail) = start.checked_add(removed) else {
+            self.replace_runs(first..len, &[(usize::MAX, T::default())]);
+            self.coalesce(first.saturating_sub(1), first);
+            return;
+        };
+
+        // Runs which end before `tail` are removed, the next one is shortened from the start
+        let last = first + self.as_slice()[first..].partition_point(|(end, _)| *end < tail);
+        self.replace_runs(first..last, &[]);
+
+        let runs = self.as_mut_slice();
+        let mut len = runs.len();
+        for (pos, (end, _)) in runs.iter_mut().enumerate().skip(first) {
+            *end = spliced_end(*end, removed, inserted).unwrap_or(usize::MAX);
+            if *end == usize::MAX {
+                len = pos + 1;
+                break;
+            }
+        }
+        let count = self.run_count();
+        self.replace_runs(len..count, &[]);
+
+        if let Some(gap) = inserted.checked_sub(1).map(|len| start.saturating_add(len)) {
+            let count = if gap == usize::MAX { self.run_count() } else { first };
+            self.replace_runs(first..count, &[(gap, T::default())]);
+        }
+        self.coalesce(first.saturating_sub(1), first + 1);
+    }
+
+    fn run_count(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn run_position(&self, idx: usize) -> usize {
+        self.position(idx)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter { runs: self.as_slice(), front: 0, back: self.run_count() }
+    }
+
+    fn range_from(&self, idx: usize) -> Self::Range<'_> {
+        Iter { runs: self.as_slice(), front: self.position(idx), back: self.run_count() }
+    }
+
+    fn into_runs(self) -> Self::Runs {
+        IntoIter { back: self.run_count(), runs: self, front: 0 }
+    }
+}
+
+fn item<T: Attr>(runs: &[Run<T>], pos: usize) -> (IdxRange, &T) {
+    (IdxRange::new(RunVec::start(runs, pos), runs[pos].0), &runs[pos].1)
+}
+
+impl<T: Attr> IntoIter<T> {
+    /// Takes attribute of the run at `pos`. Taken runs are never visited again and only their
+    /// ends are used to compute start of the next run.
+    fn take(&mut self, pos: usize) -> (IdxRange, T) {
+        let range = item(self.runs.as_slice(), pos).0;
+        (range, std::mem::take(&mut self.runs.as_mut_slice()[pos].1))
+    }
+}
+
+impl<'a, T: Attr> Iterator for Iter<'a, T> {
+    type Item = (IdxRange, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.front += 1;
+            item(self.runs, self.front - 1)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.back - self.front, Some(self.back - self.front))
+    }
+}
+
+impl<'a, T: Attr> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.back -= 1;
+            item(self.runs, self.back)
+        })
+    }
+}
+
+impl<'a, T: Attr> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: Attr> FusedIterator for Iter<'a, T> {}
+
+impl<T: Attr> Iterator for IntoIter<T> {
+    type Item = (IdxRange, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.front += 1;
+            self.take(self.front - 1)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.back - self.front, Some(self.back - self.front))
+    }
+}
+
+impl<T: Attr> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.back -= 1;
+            self.take(self.back)
+        })
+    }
+}
+
+impl<T: Attr> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: Attr> FusedIterator for IntoIter<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Style};
+    use pretty_assertions::assert_eq;
+
+    const MAX: usize = usize::MAX;
+
+    fn red() -> Style {
+        Style::new().fg(Color::Red)
+    }
+
+    #[test]
+    fn spill_to_heap() {
+        let mut vec = RunVec::from_runs([(IdxRange::new(0, MAX), Style::default())]);
+        for idx in 0..INLINE_RUNS {
+            vec.replace(IdxRange::new(idx * 2, idx * 2), red());
+        }
+        assert!(matches!(vec, RunVec::Heap(_)));
+        assert_eq!(vec.run_count(), INLINE_RUNS * 2);
+        assert_eq!(*vec.get(6), red());
+        assert_eq!(*vec.get(7), Style::default());
+
+        let reversed: Vec<_> = vec.clone().into_runs().rev().map(|(r, _)| r.start).collect();
+        assert_eq!(reversed, vec![7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+}
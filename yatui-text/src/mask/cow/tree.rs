@@ -0,0 +1,252 @@
+use std::{
+    collections::{btree_map, BTreeMap},
+    iter::{FusedIterator, Map},
+};
+
+use crate::{
+    mask::{
+        cow::{spliced_end, MaskStorage},
+        Attr,
+    },
+    IdxRange,
+};
+
+/// B-tree of runs. Key is the last index of the run, the run starts right after the previous key.
+/// The last run always ends at `usize::MAX` and adjacent runs always have different attributes.
+///
+/// Replace and update touch only runs inside the edited range and their neighbours, so they take
+/// logarithmic time plus time proportional to count of touched runs. Splice also moves ends of
+/// all runs after the edit: they're split off the tree, shifted and appended back.
+#[derive(Debug, Clone)]
+pub(in crate::mask) struct RunTree<T> {
+    runs: BTreeMap<usize, T>,
+}
+
+/// An iterator over runs of [`RunTree`]. It takes the run before the last returned from the back
+/// to know where the last run starts.
+pub(in crate::mask) struct Runs<I: Iterator> {
+    inner: I,
+    start: usize,
+    back: Option<I::Item>,
+}
+
+type BorrowedRun<'a, T> = fn((&'a usize, &'a T)) -> (usize, &'a T);
+
+pub(in crate::mask) type Iter<'a, T> = Runs<Map<btree_map::Iter<'a, usize, T>, BorrowedRun<'a, T>>>;
+
+pub(in crate::mask) type Range<'a, T> =
+    Runs<Map<btree_map::Range<'a, usize, T>, BorrowedRun<'a, T>>>;
+
+pub(in crate::mask) type IntoIter<T> = Runs<btree_map::IntoIter<usize, T>>;
+
+impl<T: Attr> RunTree<T> {
+    /// Returns the last index of the run which contains `idx` and its attribute.
+    fn run(&self, idx: usize) -> (usize, &T) {
+        let (end, attr) = self.runs.range(idx..).next().expect("The last run ends at MAX");
+        (*end, attr)
+    }
+
+    /// Splits the run which contains `idx` so that a new run starts at `idx`.
+    fn split_at(&mut self, idx: usize) {
+        let Some(prev) = idx.checked_sub(1) else { return };
+        let (end, attr) = self.run(prev);
+        if end != prev {
+            let attr = attr.clone();
+            self.runs.insert(prev, attr);
+        }
+    }
+
+    /// Removes runs which end between `first` and `last` (inclusive). Indexes of removed runs
+    /// are moved to the next run.
+    fn remove_runs(&mut self, first: usize, last: usize) {
+        while let Some((&end, _)) = self.runs.range(first..=last).next() {
+            self.runs.remove(&end);
+        }
+    }
+
+    /// Joins runs which end between `first` and `last` (inclusive) with the next run if they have
+    /// equal attributes.
+    fn coalesce(&mut self, first: usize, last: usize) {
+        let mut joined = Vec::new();
+        let mut runs = self.runs.range(first..).peekable();
+        while let Some((&end, attr)) = runs.next() {
+            if end > last {
+                break;
+            }
+            if runs.peek().is_some_and(|(_, next)| *next == attr) {
+                joined.push(end);
+            }
+        }
+
+        for end in joined {
+            self.runs.remove(&end);
+        }
+    }
+}
+
+impl<T: Attr> MaskStorage<T> for RunTree<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+    type Range<'a>
+        = Range<'a, T>
+    where
+        T: 'a;
+    type Runs = IntoIter<T>;
+
+    fn from_runs(runs: impl IntoIterator<Item = (IdxRange, T)>) -> Self {
+        let mut map = BTreeMap::new();
+        for (range, attr) in runs {
+            match map.last_entry() {
+                Some(last) if *last.get() == attr => {
+                    last.remove();
+                }
+                _ => {}
+            }
+            map.insert(range.end, attr);
+        }
+        debug_assert_eq!(map.last_key_value().map(|(end, _)| *end), Some(usize::MAX));
+        Self { runs: map }
+    }
+
+    fn get(&self, idx: usize) -> &T {
+        self.run(idx).1
+    }
+
+    fn replace(&mut self, range: IdxRange, attr: T) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.split_at(range.start);
+        // The run which contains `range.end` keeps its end, so it is shortened from the start
+        if range.end > range.start {
+            self.remove_runs(range.start, range.end - 1);
+        }
+        self.runs.insert(range.end, attr);
+        self.coalesce(range.start.saturating_sub(1), range.end);
+    }
+
+    fn update(&mut self, range: IdxRange, f: impl Fn(&T) -> T) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.split_at(range.start);
+        if let Some(next) = range.end.checked_add(1) {
+            self.split_at(next);
+        }
+        for (_, attr) in self.runs.range_mut(range.start..=range.end) {
+            *attr = f(attr);
+        }
+        self.coalesce(range.start.saturating_sub(1), range.end);
+    }
+
+    fn splice(&mut self, start: usize, removed: usize, inserted: usize) {
+        self.split_at(start);
+        let gap = inserted.checked_sub(1).map(|len| start.saturating_add(len));
+        let tail = match start.checked_add(removed) {
+            Some(tail) if gap != Some(usize::MAX) => tail,
+            // All indexes after `start` get the default attribute
+            _ => {
+                self.remove_runs(start, usize::MAX);
+                self.runs.insert(usize::MAX, T::default());
+                self.coalesce(start.saturating_sub(1), start);
+                return;
+            }
+        };
+
+        // Runs which end before `tail` are removed, the next one is shortened from the start
+        if removed > 0 {
+            self.remove_runs(start, tail - 1);
+        }
+        // The first run which is moved to `usize::MAX` or past it becomes the last one
+        if let Some(limit) = (usize::MAX - inserted).checked_add(removed) {
+            let overflow = self.runs.range(limit..).next().map(|(end, _)| *end);
+            if let Some(overflow) = overflow.filter(|end| *end != usize::MAX) {
+                self.remove_runs(overflow + 1, usize::MAX);
+                let attr = self.runs.remove(&overflow).expect("Run is found above");
+                self.runs.insert(usize::MAX, attr);
+            }
+        }
+        if removed != inserted {
+            let mut moved: BTreeMap<_, _> = (self.runs.split_off(&start).into_iter())
+                .map(|(end, attr)| {
+                    (spliced_end(end, removed, inserted).unwrap_or(usize::MAX), attr)
+                })
+                .collect();
+            self.runs.append(&mut moved);
+        }
+
+        if let Some(gap) = gap {
+            self.runs.insert(gap, T::default());
+        }
+        self.coalesce(start.saturating_sub(1), gap.unwrap_or(start));
+    }
+
+    fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    // B-tree does not keep sizes of subtrees, so runs before `idx` are counted
+    fn run_position(&self, idx: usize) -> usize {
+        self.runs.range(..idx).count()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Runs::new(self.runs.iter().map((|(end, attr)| (*end, attr)) as BorrowedRun<'_, T>), 0)
+    }
+
+    fn range_from(&self, idx: usize) -> Self::Range<'_> {
+        let start = self.runs.range(..idx).next_back().map_or(0, |(end, _)| end + 1);
+        let runs = self.runs.range(idx..);
+        Runs::new(runs.map((|(end, attr)| (*end, attr)) as BorrowedRun<'_, T>), start)
+    }
+
+    fn into_runs(self) -> Self::Runs {
+        Runs::new(self.runs.into_iter(), 0)
+    }
+}
+
+impl<I: Iterator> Runs<I> {
+    /// Creates iterator over runs from `inner` where the first run starts at `start`.
+    fn new(inner: I, start: usize) -> Self {
+        Self { inner, start, back: None }
+    }
+}
+
+impl<I, V> Iterator for Runs<I>
+where
+    I: Iterator<Item = (usize, V)>,
+{
+    type Item = (IdxRange, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (end, attr) = self.inner.next().or_else(|| self.back.take())?;
+        let start = std::mem::replace(&mut self.start, end.wrapping_add(1));
+        Some((IdxRange::new(start, end), attr))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let back = usize::from(self.back.is_some());
+        let (lower, upper) = self.inner.size_hint();
+        (lower + back, upper.map(|upper| upper + back))
+    }
+}
+
+impl<I, V> DoubleEndedIterator for Runs<I>
+where
+    I: DoubleEndedIterator<Item = (usize, V)>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (end, attr) = self.back.take().or_else(|| self.inner.next_back())?;
+        self.back = self.inner.next_back();
+        let start = self.back.as_ref().map_or(self.start, |(prev, _)| prev + 1);
+        Some((IdxRange::new(start, end), attr))
+    }
+}
+
+impl<I, V> ExactSizeIterator for Runs<I> where I: ExactSizeIterator<Item = (usize, V)> {}
+
+impl<I, V> FusedIterator for Runs<I> where I: FusedIterator<Item = (usize, V)> {}
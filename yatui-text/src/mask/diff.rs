@@ -0,0 +1,135 @@
+use std::iter::FusedIterator;
+
+use crate::{
+    mask::{ops::Zip, Attr, Iter, RangeAttrs},
+    IdxRange, Style,
+};
+
+/// An iterator over ranges of graphemes which have different attributes in two [`RangeAttrs`].
+///
+/// The iterator element type is ([`IdxRange`], Option<&'a T>, &'a T).
+///
+/// This struct is created by the [`diff`] method on [`RangeAttrs`].
+///
+/// [`diff`]: RangeAttrs::diff
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Diff<'a, T: Attr = Style> {
+    inner: Zip<'a, Iter<'a, T>, Iter<'a, T>, T>,
+}
+
+type DiffItem<'a, T> = Option<(IdxRange, Option<&'a T>, &'a T)>;
+
+impl<T: Attr> RangeAttrs<T> {
+    /// Gets an iterator over ranges of graphemes whose attributes in `new` differ from `old`,
+    /// e.g. graphemes which should be repainted after text was highlighted again. Returned ranges
+    /// are in ascending order, and ranges with the same pair of attributes are never adjacent.
+    ///
+    /// Both run lists are walked at the same time, so it takes linear time of count of runs.
+    ///
+    /// The iterator element type is ([`IdxRange`], Option<&'a T>, &'a T): range, old attribute
+    /// and new attribute. The old attribute is always `Some` for attributes of the same text, it
+    /// is `None` only in [`Text::diff`] for changed graphemes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, IdxRange};
+    /// let red = Style::new().fg(Color::Red);
+    /// let old = Mask::from([(0..4, red)]);
+    /// let new = Mask::from([(0..2, red), (6..8, red)]);
+    ///
+    /// let changed: Vec<_> = Mask::diff(&old, &new).collect();
+    /// assert_eq!(
+    ///     changed,
+    ///     vec![
+    ///         (IdxRange::new(2, 3), Some(&red), &Style::default()),
+    ///         (IdxRange::new(6, 7), Some(&Style::default()), &red),
+    ///     ]
+    /// );
+    /// ```
+    ///
+    /// [`Text::diff`]: crate::Text::diff
+    pub fn diff<'a>(old: &'a Self, new: &'a Self) -> Diff<'a, T> {
+        Diff { inner: Zip::new(old.iter(), new.iter()) }
+    }
+
+    /// The same as [`diff`] for runs of any window. Both `old` and `new` must cover the same
+    /// indexes.
+    ///
+    /// [`diff`]: Self::diff
+    pub(crate) fn diff_runs<'a>(
+        old: impl Iterator<Item = (IdxRange, &'a T)>,
+        new: impl Iterator<Item = (IdxRange, &'a T)>,
+    ) -> impl Iterator<Item = (IdxRange, Option<&'a T>, &'a T)>
+    where
+        T: 'a,
+    {
+        Zip::new(old, new).filter_map(changed)
+    }
+}
+
+fn changed<'a, T: Attr>((range, old, new): (IdxRange, &'a T, &'a T)) -> DiffItem<'a, T> {
+    (old != new).then_some((range, Some(old), new))
+}
+
+impl<'a, T: Attr> Iterator for Diff<'a, T> {
+    type Item = (IdxRange, Option<&'a T>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.find_map(changed)
+    }
+}
+
+impl<'a, T: Attr> FusedIterator for Diff<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mask, Color, Mask};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    const MAX: usize = usize::MAX;
+
+    /// Range, old and new styles, `None` is used for default style to keep cases short.
+    type Item = ((usize, usize), Option<Style>, Option<Style>);
+
+    fn red() -> Style {
+        Style::new().fg(Color::Red)
+    }
+
+    #[rstest]
+    #[case::equal(mask!(0..4 => red()), mask!(0..2 => red(), 2..4 => red()), vec![])]
+    #[case::both_single(Mask::new(), mask!(.. => red()), vec![((0, MAX), None, Some(red()))])]
+    #[case::shifted(
+        mask!(2..4 => red()),
+        mask!(3..5 => red()),
+        vec![((2, 2), Some(red()), None), ((4, 4), None, Some(red()))]
+    )]
+    #[case::changed_inside(
+        mask!(0..6 => red()),
+        mask!(0..6 => red(), 2..4 => Style::new().bg(Color::Blue)),
+        vec![((2, 3), Some(red()), Some(red().bg(Color::Blue)))]
+    )]
+    fn diff_masks(#[case] old: Mask, #[case] new: Mask, #[case] expected: Vec<Item>) {
+        let style = |style: &Style| Some(*style).filter(|style| *style != Style::default());
+        let diff: Vec<_> = Mask::diff(&old, &new)
+            .map(|(range, old, new)| (range.into_tuple(), old.and_then(style), style(new)))
+            .collect();
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn diff_storages() {
+        let old = Mask::from([(0..4, red())]);
+        let mut new = old.clone();
+        new.set_storage(crate::mask::StoragePolicy::BTree);
+        assert_eq!(Mask::diff(&old, &new).next(), None);
+
+        new.add(10..12, red());
+        assert_eq!(
+            Mask::diff(&old, &new).map(|(range, ..)| range).collect::<Vec<_>>(),
+            [IdxRange::new(10, 11)]
+        );
+    }
+}
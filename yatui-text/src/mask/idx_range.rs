@@ -1,19 +1,52 @@
 use std::{
-    fmt::Display,
+    error::Error,
+    fmt::{self, Debug, Display},
     fs::write,
-    ops::{Bound, RangeBounds, RangeInclusive},
+    marker::PhantomData,
+    ops::{Bound, Range, RangeBounds, RangeInclusive},
 };
 
+use crate::unit::{ByteIdx, CharIdx, Col, GraphemeIdx, Unit, Utf16Idx};
+
 /// Wrapper about inclusive range \[`start`;`end`\]. `IdxRange` used by [`Mask`] to specify
 /// a range of graphemes indexes. `IdxRange` can be constructed from any range of std library.
 ///
+/// `U` is the [unit](crate::unit) of indexes. Ranges of `usize` are always ranges of graphemes,
+/// ranges in other units are constructed from ranges of unit newtypes or with
+/// [`from_units`](IdxRange::from_units).
+///
 /// [`Mask`]: crate::Mask
-#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy)]
-pub struct IdxRange {
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy)]
+pub struct IdxRange<U = GraphemeIdx> {
     pub start: usize,
     pub end: usize,
+    unit: PhantomData<U>,
+}
+
+/// An error which is returned when bounds can not be represented by [`IdxRange`] or do not form a
+/// range.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BoundsError {
+    /// Start bound is `Excluded(usize::MAX)`.
+    StartOverflow,
+    /// End bound is `Excluded(0)`.
+    EndUnderflow,
+    /// Start bound is after the end bound, e.g. `3..1`. Empty ranges like `3..3` are valid.
+    StartAfterEnd,
 }
 
+impl Display for BoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundsError::StartOverflow => f.write_str("excluded start bound overflows usize"),
+            BoundsError::EndUnderflow => f.write_str("excluded end bound underflows usize"),
+            BoundsError::StartAfterEnd => f.write_str("start bound is after the end bound"),
+        }
+    }
+}
+
+impl Error for BoundsError {}
+
 impl IdxRange {
     /// Creates a new inclusive range.
     ///
@@ -21,10 +54,10 @@ impl IdxRange {
     ///
     /// ```
     /// # use yatui_text::IdxRange;
-    /// assert_eq!(IdxRange { start: 0, end: 3 }, IdxRange::new(0, 3));
+    /// assert_eq!(IdxRange::from(0..=3), IdxRange::new(0, 3));
     /// ```
     pub const fn new(start: usize, end: usize) -> Self {
-        Self { start, end }
+        Self { start, end, unit: PhantomData }
     }
 
     /// Creates a new inclusive range from specified bounds.
@@ -32,8 +65,10 @@ impl IdxRange {
     /// # Panics
     ///
     /// Panics if overflow happened on calculation `start` or `end` for [`Excluded`] bounds.
+    /// See [`try_from_bounds`] for non-panicking version.
     ///
     /// [`Excluded`]: Bound::Excluded
+    /// [`try_from_bounds`]: Self::try_from_bounds
     ///
     /// # Examples
     ///
@@ -46,25 +81,88 @@ impl IdxRange {
     /// );
     /// ```
     pub fn from_bounds(range: impl RangeBounds<usize>) -> Self {
+        Self::from_any_bounds(range)
+    }
+
+    /// Creates a new inclusive range from specified bounds. Returns an error if `start` or `end`
+    /// for [`Excluded`] bounds can not be represented by `usize` or if `start` is after `end`.
+    ///
+    /// [`Excluded`]: Bound::Excluded
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{mask::BoundsError, IdxRange};
+    /// # use std::ops::Bound;
+    /// assert_eq!(IdxRange::try_from_bounds(1..3), Ok(IdxRange::new(1, 2)));
+    /// assert_eq!(IdxRange::try_from_bounds(..0), Err(BoundsError::EndUnderflow));
+    /// assert_eq!(IdxRange::try_from_bounds(3..1), Err(BoundsError::StartAfterEnd));
+    /// assert_eq!(
+    ///     IdxRange::try_from_bounds((Bound::Excluded(usize::MAX), Bound::Unbounded)),
+    ///     Err(BoundsError::StartOverflow)
+    /// );
+    /// ```
+    pub fn try_from_bounds(range: impl RangeBounds<usize>) -> Result<Self, BoundsError> {
+        let range = Self::try_from_any_bounds(range)?;
+        // `start == end + 1` is an empty range like `3..3`
+        if range.end.checked_add(1).is_some_and(|next| range.start > next) {
+            return Err(BoundsError::StartAfterEnd);
+        }
+        Ok(range)
+    }
+}
+
+impl<U: Unit> IdxRange<U> {
+    /// Creates a new inclusive range of indexes in unit `U`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::ByteIdx, IdxRange};
+    /// let range = IdxRange::from_units(ByteIdx(2), ByteIdx(5));
+    /// assert_eq!(range, IdxRange::from(ByteIdx(2)..ByteIdx(6)));
+    /// assert_eq!(range.into_units(), (ByteIdx(2), ByteIdx(5)));
+    /// ```
+    pub fn from_units(start: U, end: U) -> Self {
+        Self { start: start.into(), end: end.into(), unit: PhantomData }
+    }
+
+    /// Converts range into tuple (`start`, `end`) of unit `U`.
+    pub fn into_units(self) -> (U, U) {
+        (U::from(self.start), U::from(self.end))
+    }
+
+    fn from_any_bounds<T: Copy + Into<usize>>(range: impl RangeBounds<T>) -> Self {
+        match Self::try_from_any_bounds(range) {
+            Ok(range) => range,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn try_from_any_bounds<T: Copy + Into<usize>>(
+        range: impl RangeBounds<T>,
+    ) -> Result<Self, BoundsError> {
         let start = match range.start_bound() {
-            Bound::Included(s) => *s,
-            Bound::Excluded(s) => s
-                .checked_add(1)
-                .expect("There is no support for excluded and overflowed start_bound"),
+            Bound::Included(s) => (*s).into(),
+            Bound::Excluded(s) => {
+                Into::<usize>::into(*s).checked_add(1).ok_or(BoundsError::StartOverflow)?
+            }
             Bound::Unbounded => 0,
         };
 
         let end = match range.end_bound() {
-            Bound::Included(s) => *s,
+            Bound::Included(s) => (*s).into(),
             Bound::Excluded(s) => {
-                s.checked_sub(1).expect("There is no support for excluded and overflowed end_bound")
+                Into::<usize>::into(*s).checked_sub(1).ok_or(BoundsError::EndUnderflow)?
             }
             Bound::Unbounded => usize::MAX,
         };
 
-        IdxRange::new(start, end)
+        Ok(Self { start, end, unit: PhantomData })
     }
+}
 
+impl<U> IdxRange<U> {
     /// Returns `true` if `idx` in the range.
     ///
     /// # Examples
@@ -128,15 +226,173 @@ impl IdxRange {
     pub const fn is_full(&self) -> bool {
         self.start == 0 && self.end == usize::MAX
     }
+
+    const fn with(start: usize, end: usize) -> Self {
+        Self { start, end, unit: PhantomData }
+    }
+
+    /// Returns indexes which are contained in both ranges or `None` if there are no such indexes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// let range = IdxRange::new(0, 5);
+    /// assert_eq!(range.intersection(IdxRange::new(3, 8)), Some(IdxRange::new(3, 5)));
+    /// assert_eq!(IdxRange::new(0, 2).intersection(IdxRange::new(3, 8)), None);
+    /// ```
+    pub const fn intersection(self, other: Self) -> Option<Self> {
+        let start = if self.start > other.start { self.start } else { other.start };
+        let end = if self.end < other.end { self.end } else { other.end };
+
+        if start <= end {
+            Some(Self::with(start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if ranges have common indexes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// assert!(IdxRange::new(0, 3).overlaps(&IdxRange::new(3, 8)));
+    /// assert!(!IdxRange::new(0, 2).overlaps(&IdxRange::new(3, 8)));
+    /// ```
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        !self.is_empty() && !other.is_empty() && self.start <= other.end && other.start <= self.end
+    }
+
+    /// Returns the smallest range which contains both ranges if they overlap or one of them
+    /// starts right after the end of another. Returns `None` if there is a gap between ranges or
+    /// one of them is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// let range = IdxRange::new(3, 5);
+    /// assert_eq!(range.union_if_adjacent(IdxRange::new(0, 2)), Some(IdxRange::new(0, 5)));
+    /// assert_eq!(range.union_if_adjacent(IdxRange::new(0, 1)), None);
+    /// ```
+    pub const fn union_if_adjacent(self, other: Self) -> Option<Self> {
+        if self.is_empty()
+            || other.is_empty()
+            || self.start > other.end.saturating_add(1)
+            || other.start > self.end.saturating_add(1)
+        {
+            return None;
+        }
+
+        let start = if self.start < other.start { self.start } else { other.start };
+        let end = if self.end > other.end { self.end } else { other.end };
+        Some(Self::with(start, end))
+    }
+
+    /// Splits range into \[`start`;`idx` - 1\] and \[`idx`;`end`\]. Returns `None` if one of
+    /// the parts would be empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// let (left, right) = IdxRange::new(0, 5).split_at(2).unwrap();
+    /// assert_eq!((left, right), (IdxRange::new(0, 1), IdxRange::new(2, 5)));
+    /// assert_eq!(IdxRange::new(0, 5).split_at(0), None);
+    /// assert_eq!(IdxRange::new(0, 5).split_at(6), None);
+    /// ```
+    pub const fn split_at(self, idx: usize) -> Option<(Self, Self)> {
+        if self.start < idx && idx <= self.end {
+            Some((Self::with(self.start, idx - 1), Self::with(idx, self.end)))
+        } else {
+            None
+        }
+    }
+
+    /// Moves both ends of the range by `offset`. Returns `None` if one of them overflows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// assert_eq!(IdxRange::new(2, 5).checked_shift(-2), Some(IdxRange::new(0, 3)));
+    /// assert_eq!(IdxRange::new(2, 5).checked_shift(-3), None);
+    /// assert_eq!(IdxRange::from(2..).checked_shift(1), None);
+    /// ```
+    pub const fn checked_shift(self, offset: isize) -> Option<Self> {
+        match (self.start.checked_add_signed(offset), self.end.checked_add_signed(offset)) {
+            (Some(start), Some(end)) => Some(Self::with(start, end)),
+            _ => None,
+        }
+    }
+
+    /// Moves both ends of the range by `offset`, saturating at `0` and `usize::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// assert_eq!(IdxRange::new(2, 5).saturating_shift(-3), IdxRange::new(0, 2));
+    /// assert_eq!(IdxRange::from(2..).saturating_shift(1), IdxRange::from(3..));
+    /// ```
+    pub const fn saturating_shift(self, offset: isize) -> Self {
+        Self::with(self.start.saturating_add_signed(offset), self.end.saturating_add_signed(offset))
+    }
+
+    /// Returns part of the range which is in \[0;`len`), e.g. part of the range inside a text
+    /// with `len` graphemes. Returns `None` if there is no such part.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// assert_eq!(IdxRange::from(2..).clamp_to(5), Some(IdxRange::new(2, 4)));
+    /// assert_eq!(IdxRange::new(2, 3).clamp_to(2), None);
+    /// ```
+    pub const fn clamp_to(self, len: usize) -> Option<Self> {
+        match len.checked_sub(1) {
+            Some(last) => self.intersection(Self::with(0, last)),
+            None => None,
+        }
+    }
+
+    /// Converts range into exclusive [`Range`]. Empty range is converted into `start..start`.
+    /// Returns `None` if `end` is `usize::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::IdxRange;
+    /// assert_eq!(IdxRange::new(2, 5).to_range(), Some(2..6));
+    /// assert_eq!(IdxRange::new(2, 0).to_range(), Some(2..2));
+    /// assert_eq!(IdxRange::from(2..).to_range(), None);
+    /// ```
+    pub const fn to_range(self) -> Option<Range<usize>> {
+        if self.is_empty() {
+            Some(self.start..self.start)
+        } else if self.end == usize::MAX {
+            None
+        } else {
+            Some(self.start..self.end + 1)
+        }
+    }
+}
+
+impl<U> Debug for IdxRange<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdxRange").field("start", &self.start).field("end", &self.end).finish()
+    }
 }
 
-impl Display for IdxRange {
+impl<U> Display for IdxRange<U> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[{};{}]", self.start, self.end)
     }
 }
 
-impl RangeBounds<usize> for IdxRange {
+impl RangeBounds<usize> for IdxRange<GraphemeIdx> {
     fn start_bound(&self) -> Bound<&usize> {
         Bound::Included(&self.start)
     }
@@ -146,7 +402,7 @@ impl RangeBounds<usize> for IdxRange {
     }
 }
 
-impl RangeBounds<usize> for &IdxRange {
+impl RangeBounds<usize> for &IdxRange<GraphemeIdx> {
     fn start_bound(&self) -> Bound<&usize> {
         (*self).start_bound()
     }
@@ -156,26 +412,6 @@ impl RangeBounds<usize> for &IdxRange {
     }
 }
 
-#[doc(hidden)]
-impl btree_range_map::AsRange for IdxRange {
-    type Item = usize;
-
-    fn start(&self) -> Bound<&Self::Item> {
-        self.start_bound()
-    }
-
-    fn end(&self) -> Bound<&Self::Item> {
-        self.end_bound()
-    }
-}
-
-#[doc(hidden)]
-impl btree_range_map::IntoRange for IdxRange {
-    fn into_range(self) -> btree_range_map::AnyRange<Self::Item> {
-        btree_range_map::AnyRange::from(self)
-    }
-}
-
 impl IntoIterator for IdxRange {
     type Item = usize;
     type IntoIter = RangeInclusive<usize>;
@@ -218,20 +454,44 @@ impl_traits_std_range!([
     std::ops::RangeToInclusive<usize>,
 ]);
 
-#[doc(hidden)]
-impl From<btree_range_map::AnyRange<usize>> for IdxRange {
-    fn from(value: btree_range_map::AnyRange<usize>) -> Self {
-        Self::from_bounds(value)
-    }
-}
+macro_rules! impl_from_unit_ranges {
+    ($($unit:ty $(,)?)*) => {
+        $(
+            impl From<std::ops::Range<$unit>> for IdxRange<$unit> {
+                fn from(range: std::ops::Range<$unit>) -> Self {
+                    Self::from_any_bounds(range)
+                }
+            }
+
+            impl From<std::ops::RangeFrom<$unit>> for IdxRange<$unit> {
+                fn from(range: std::ops::RangeFrom<$unit>) -> Self {
+                    Self::from_any_bounds(range)
+                }
+            }
 
-#[doc(hidden)]
-impl From<&btree_range_map::AnyRange<usize>> for IdxRange {
-    fn from(value: &btree_range_map::AnyRange<usize>) -> Self {
-        Self::from_bounds(*value)
+            impl From<std::ops::RangeInclusive<$unit>> for IdxRange<$unit> {
+                fn from(range: std::ops::RangeInclusive<$unit>) -> Self {
+                    Self::from_any_bounds(range)
+                }
+            }
+
+            impl From<std::ops::RangeTo<$unit>> for IdxRange<$unit> {
+                fn from(range: std::ops::RangeTo<$unit>) -> Self {
+                    Self::from_any_bounds(range)
+                }
+            }
+
+            impl From<std::ops::RangeToInclusive<$unit>> for IdxRange<$unit> {
+                fn from(range: std::ops::RangeToInclusive<$unit>) -> Self {
+                    Self::from_any_bounds(range)
+                }
+            }
+        )*
     }
 }
 
+impl_from_unit_ranges!(GraphemeIdx, ByteIdx, CharIdx, Utf16Idx, Col);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +532,100 @@ mod tests {
     fn create_from_bounds(#[case] range: IdxRange, #[case] expected: IdxRange) {
         assert_eq!(range, expected);
     }
+
+    #[test]
+    fn ranges_of_units() {
+        use crate::unit::{ByteIdx, Utf16Idx};
+
+        assert_eq!(
+            IdxRange::from(ByteIdx(1)..ByteIdx(3)),
+            IdxRange::from_units(ByteIdx(1), ByteIdx(2))
+        );
+        assert_eq!(IdxRange::from(..=Utf16Idx(3)).into_units(), (Utf16Idx(0), Utf16Idx(3)));
+        assert_eq!(IdxRange::from(ByteIdx(2)..).len(), usize::MAX - 1);
+        assert_eq!(
+            format!("{:?}", IdxRange::from(ByteIdx(1)..=ByteIdx(2))),
+            "IdxRange { start: 1, end: 2 }"
+        );
+    }
+
+    #[rstest]
+    #[case::start_overflow((Excluded(usize::MAX), Included(1)), Err(BoundsError::StartOverflow))]
+    #[case::end_underflow((Included(0), Excluded(0)), Err(BoundsError::EndUnderflow))]
+    #[case::both((Excluded(usize::MAX), Excluded(0)), Err(BoundsError::StartOverflow))]
+    #[case::valid((Excluded(0), Excluded(usize::MAX)), Ok(IdxRange::new(1, usize::MAX - 1)))]
+    #[case::start_after_end((Included(3), Excluded(1)), Err(BoundsError::StartAfterEnd))]
+    #[case::inclusive_start_after_end((Included(3), Included(1)), Err(BoundsError::StartAfterEnd))]
+    #[case::excluded_start_after_end((Excluded(2), Included(1)), Err(BoundsError::StartAfterEnd))]
+    #[case::empty((Included(3), Excluded(3)), Ok(IdxRange::new(3, 2)))]
+    #[case::empty_inclusive((Included(3), Included(2)), Ok(IdxRange::new(3, 2)))]
+    #[case::empty_at_max(
+        (Included(usize::MAX), Excluded(usize::MAX)),
+        Ok(IdxRange::new(usize::MAX, usize::MAX - 1))
+    )]
+    fn try_create_from_bounds(
+        #[case] bounds: (Bound<usize>, Bound<usize>),
+        #[case] expected: Result<IdxRange, BoundsError>,
+    ) {
+        assert_eq!(IdxRange::try_from_bounds(bounds), expected);
+    }
+
+    #[rstest]
+    #[case::disjoint(IdxRange::new(0, 1), IdxRange::new(3, 4), None, None)]
+    #[case::adjacent(IdxRange::new(0, 2), IdxRange::new(3, 4), None, Some(IdxRange::new(0, 4)))]
+    #[case::overlap(
+        IdxRange::new(0, 3),
+        IdxRange::new(3, 4),
+        Some(IdxRange::new(3, 3)),
+        Some(IdxRange::new(0, 4))
+    )]
+    #[case::nested(
+        IdxRange::new(0, 9),
+        IdxRange::new(3, 4),
+        Some(IdxRange::new(3, 4)),
+        Some(IdxRange::new(0, 9))
+    )]
+    #[case::full(
+        IdxRange::from(..),
+        IdxRange::from(5..),
+        Some(IdxRange::from(5..)),
+        Some(IdxRange::from(..))
+    )]
+    #[case::empty(IdxRange::new(3, 2), IdxRange::new(0, 4), None, None)]
+    fn range_algebra(
+        #[case] a: IdxRange,
+        #[case] b: IdxRange,
+        #[case] intersection: Option<IdxRange>,
+        #[case] union: Option<IdxRange>,
+    ) {
+        assert_eq!(a.intersection(b), intersection);
+        assert_eq!(b.intersection(a), intersection);
+        assert_eq!(a.overlaps(&b), intersection.is_some());
+        assert_eq!(a.union_if_adjacent(b), union);
+        assert_eq!(b.union_if_adjacent(a), union);
+    }
+
+    #[rstest]
+    #[case::forward(IdxRange::new(1, 2), 3, Some(IdxRange::new(4, 5)), IdxRange::new(4, 5))]
+    #[case::backward(IdxRange::new(1, 2), -2, None, IdxRange::new(0, 0))]
+    #[case::unbounded(IdxRange::from(1..), 1, None, IdxRange::from(2..))]
+    fn shift_range(
+        #[case] range: IdxRange,
+        #[case] offset: isize,
+        #[case] checked: Option<IdxRange>,
+        #[case] saturating: IdxRange,
+    ) {
+        assert_eq!(range.checked_shift(offset), checked);
+        assert_eq!(range.saturating_shift(offset), saturating);
+    }
+
+    #[test]
+    fn const_algebra() {
+        const RANGE: Option<IdxRange> = IdxRange::new(0, 9).clamp_to(5);
+        const SPLIT: Option<(IdxRange, IdxRange)> = IdxRange::new(0, 4).split_at(2);
+
+        assert_eq!(RANGE, Some(IdxRange::new(0, 4)));
+        assert_eq!(SPLIT, Some((IdxRange::new(0, 1), IdxRange::new(2, 4))));
+        assert_eq!(IdxRange::new(0, 9).clamp_to(0), None);
+    }
 }
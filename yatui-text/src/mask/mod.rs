@@ -1,61 +1,205 @@
+mod builder;
 mod cow;
+mod diff;
 mod idx_range;
+mod ops;
+mod stack;
 
-use crate::Style;
-use btree_range_map::{
-    generic::map::{IntoIter as MapIntoIter, Iter as MapIter},
-    AnyRange, DefaultMapContainer as MapSlab, RangeMap,
-};
+use crate::{Error, Style};
+pub use builder::{MaskBuilder, OverlapError};
 use cow::Cow;
-pub use idx_range::IdxRange;
+pub use cow::StoragePolicy;
+pub use diff::Diff;
+pub use idx_range::{BoundsError, IdxRange};
+pub use ops::MergeStrategy;
+pub use stack::MaskStack;
 use std::{
     borrow::Borrow,
+    hash::{Hash, Hasher},
     iter::{ExactSizeIterator, Extend, FromIterator, FusedIterator},
-    ops::{Index, RangeInclusive},
+    ops::{Index, RangeBounds, RangeInclusive},
 };
 
-use self::cow::{CowIntoIter, CowIter};
+use self::cow::{CowIntoIter, CowIter, CowRange, MaskStorage};
+
+/// Value which can be attached to ranges of graphemes with [`RangeAttrs`].
+///
+/// Every grapheme always has an attribute: graphemes without explicitly attached attribute have
+/// the [`default`] one.
+///
+/// [`default`]: Default::default
+pub trait Attr: Clone + PartialEq + Default {
+    /// Returns attribute of a grapheme with attribute `self` after [`add`] of `other`. By default
+    /// `other` replaces `self`.
+    ///
+    /// [`add`]: RangeAttrs::add
+    fn combine(&self, other: &Self) -> Self {
+        other.clone()
+    }
+}
+
+impl Attr for Style {
+    /// Merges styles with [`Style::merge`].
+    fn combine(&self, other: &Self) -> Self {
+        Style::merge(*self, *other)
+    }
+}
+
+/// `None` means that grapheme has no attribute, so [`add`] of `None` keeps existing attributes.
+///
+/// [`add`]: RangeAttrs::add
+impl<T: Clone + PartialEq> Attr for Option<T> {
+    fn combine(&self, other: &Self) -> Self {
+        other.clone().or_else(|| self.clone())
+    }
+}
+
+/// [`RangeAttrs`] saves [`attributes`] for specified ranges of graphemes, e.g. diagnostic ids,
+/// semantic token types or fold markers. [`Mask`] is `RangeAttrs` of styles.
+///
+/// It is always kept in canonical form: adjacent ranges with equal attributes are joined, so
+/// [`iter`] never returns two neighbours with the same attribute. Two `RangeAttrs` are equal (and
+/// have the same hash) if they give the same attributes to all graphemes, no matter how they were
+/// built.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{mask::RangeAttrs, IdxRange};
+/// let mut diagnostics = RangeAttrs::new();
+/// diagnostics.replace(4..8, Some(42));
+/// assert_eq!(diagnostics[5], Some(42));
+/// assert_eq!(diagnostics.first_where(Option::is_some).map(|(r, _)| r), Some(IdxRange::new(4, 7)));
+/// ```
+///
+/// Attributes of `RangeAttrs` without any edits take no memory. After the first edit they are
+/// moved to the storage chosen by [`StoragePolicy`].
+///
+/// [`attributes`]: Attr
+/// [`iter`]: RangeAttrs::iter
+#[derive(Debug, Clone)]
+pub struct RangeAttrs<T> {
+    cow: Cow<T>,
+    storage: StoragePolicy,
+}
 
 /// [`Mask`] saves [`styles`] for specified ranges of graphemes.
 ///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{Mask, Style, Color};
+/// let mut mask = Mask::new();
+/// mask.add(0..2, Style::new().fg(Color::Red));
+/// mask.add(2.., Style::new().fg(Color::Red));
+/// assert_eq!(mask, Mask::from([(.., Style::new().fg(Color::Red))]));
+/// assert_eq!(mask.iter().count(), 1);
+/// ```
+///
 /// [`styles`]: Style
-#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
-pub struct Mask {
-    cow: Cow,
-}
+pub type Mask = RangeAttrs<Style>;
 
-/// An iterator over the items of [`Mask`].
+/// An iterator over the items of [`RangeAttrs`].
 ///
-/// The iterator element type is ([`IdxRange`], &'a [`Style`]).
+/// The iterator element type is ([`IdxRange`], &'a T).
 ///
-/// This struct is created by the [`iter`] method on [`Mask`]. See its documentation
+/// This struct is created by the [`iter`] method on [`RangeAttrs`]. See its documentation
 /// for more.
 ///
-/// [`iter`]: Mask::iter
+/// [`iter`]: RangeAttrs::iter
 #[must_use = "Iterators are lazy and do nothing unless consumed"]
-pub struct Iter<'a> {
-    inner: CowIter<'a>,
+pub struct Iter<'a, T: Attr = Style> {
+    inner: CowIter<'a, T>,
 }
 
-/// An owning iterator over the items of [`Mask`].
+/// An iterator over the items of [`RangeAttrs`] which intersect a window. Ranges are clipped to
+/// the window.
 ///
-/// The iterator element type is ([`IdxRange`], [`Style`]).
+/// The iterator element type is ([`IdxRange`], &'a T).
 ///
-/// This struct is created by the [`into_iter`] method on [`Mask`].
+/// This struct is created by the [`range`] method on [`RangeAttrs`].
+///
+/// [`range`]: RangeAttrs::range
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Range<'a, T: Attr = Style> {
+    inner: CowRange<'a, T>,
+    window: IdxRange,
+}
+
+/// An iterator over the items of [`RangeAttrs`] clipped to graphemes `0..len`.
+///
+/// The iterator element type is ([`IdxRange`], &'a T).
+///
+/// This struct is created by the [`iter_bounded`] method on [`RangeAttrs`].
+///
+/// [`iter_bounded`]: RangeAttrs::iter_bounded
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Bounded<'a, T: Attr = Style> {
+    inner: Iter<'a, T>,
+    len: usize,
+    remaining: usize,
+}
+
+/// An iterator over the items of [`RangeAttrs`] with attributes matching a predicate.
+///
+/// The iterator element type is ([`IdxRange`], &'a T).
+///
+/// This struct is created by the [`find_runs`] method on [`RangeAttrs`].
+///
+/// [`find_runs`]: RangeAttrs::find_runs
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct FindRuns<'a, P, T: Attr = Style> {
+    inner: Iter<'a, T>,
+    pred: P,
+}
+
+/// An owning iterator over the items of [`RangeAttrs`].
+///
+/// The iterator element type is ([`IdxRange`], T).
+///
+/// This struct is created by the [`into_iter`] method on [`RangeAttrs`].
 ///
 /// [`into_iter`]: IntoIterator::into_iter
 #[must_use = "Iterators are lazy and do nothing unless consumed"]
-pub struct IntoIter {
-    inner: CowIntoIter,
+pub struct IntoIter<T: Attr = Style> {
+    inner: CowIntoIter<T>,
 }
 
-impl Mask {
-    /// Create empty [`Mask`].
+impl<T: Attr> RangeAttrs<T> {
+    /// Create empty [`RangeAttrs`].
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Add `style` for specified `range`. Merges all styles for intersecting ranges.
+    /// Create empty [`RangeAttrs`] which keeps its attributes in specified `storage`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{mask::StoragePolicy, Mask, Style, Color};
+    /// let mut mask = Mask::with_storage(StoragePolicy::BTree);
+    /// mask.add(0..2, Style::new().fg(Color::Red));
+    /// assert_eq!(mask.storage(), StoragePolicy::BTree);
+    /// assert_eq!(mask, Mask::from([(0..2, Style::new().fg(Color::Red))]));
+    /// ```
+    pub fn with_storage(storage: StoragePolicy) -> Self {
+        Self { storage, ..Default::default() }
+    }
+
+    /// Returns [`StoragePolicy`] of the attributes.
+    pub fn storage(&self) -> StoragePolicy {
+        self.storage
+    }
+
+    /// Change [`StoragePolicy`] of the attributes. Existing attributes are moved to the new
+    /// storage.
+    pub fn set_storage(&mut self, storage: StoragePolicy) {
+        self.storage = storage;
+        self.cow.normalize(storage);
+    }
+
+    /// Add `attr` for specified `range`. Attributes for intersecting ranges are combined with
+    /// [`Attr::combine`], e.g. styles are merged.
     ///
     /// # Examples
     ///
@@ -66,12 +210,13 @@ impl Mask {
     /// mask.add(0..2, Style::new().bg(Color::Green));
     /// assert_eq!(mask[0], Style::new().fg(Color::Yellow).bg(Color::Green));
     /// ```
-    pub fn add(&mut self, range: impl Into<IdxRange>, style: Style) {
-        self.cow.add_style(range, style);
+    pub fn add(&mut self, range: impl Into<IdxRange>, attr: T) {
+        self.cow.add_style(range, attr, self.storage);
+        self.cow.normalize(self.storage);
     }
 
-    /// Replace `style` for specified `range`. All styles in the `range` are erased before
-    /// insert the new `style`. If you wan't to save existed styles, try to use [`add`] method.
+    /// Replace `attr` for specified `range`. All attributes in the `range` are erased before
+    /// insert the new `attr`. If you wan't to save existed attributes, try to use [`add`] method.
     ///
     /// # Examples
     ///
@@ -84,12 +229,13 @@ impl Mask {
     /// ```
     ///
     /// [`add`]: Self::add
-    pub fn replace(&mut self, range: impl Into<IdxRange>, style: Style) {
-        self.cow.replace_style(range, style);
+    pub fn replace(&mut self, range: impl Into<IdxRange>, attr: T) {
+        self.cow.replace_style(range, attr, self.storage);
+        self.cow.normalize(self.storage);
     }
 
-    /// Remove all styles for specified `range`. Internally it calls [`replace`] with
-    /// [`default`] styles.
+    /// Remove all attributes for specified `range`. Internally it calls [`replace`] with
+    /// [`default`] attribute.
     ///
     /// # Examples
     ///
@@ -102,60 +248,344 @@ impl Mask {
     /// ```
     ///
     /// [`replace`]: Self::replace
-    /// [`default`]: crate::Color::default
+    /// [`default`]: Default::default
     pub fn remove(&mut self, range: impl Into<IdxRange>) {
-        self.cow.replace_style(range, Style::default());
+        self.cow.replace_style(range, T::default(), self.storage);
+        self.cow.normalize(self.storage);
+    }
+
+    /// Checked version of [`add`]. Returns [`Error::InvalidRange`] if `range` can not be
+    /// represented by [`IdxRange`], e.g. `..0`. Mask is not changed on error.
+    ///
+    /// Mask does not know length of the text, use [`Text::check_range`] to check that `range`
+    /// is inside the text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{mask::BoundsError, Color, Error, Mask, Style};
+    /// let mut mask = Mask::new();
+    /// assert_eq!(mask.try_add(0..2, Style::new().fg(Color::Red)), Ok(()));
+    /// assert_eq!(
+    ///     mask.try_add(..0, Style::new().fg(Color::Red)),
+    ///     Err(Error::InvalidRange(BoundsError::EndUnderflow))
+    /// );
+    /// ```
+    ///
+    /// [`add`]: Self::add
+    /// [`Text::check_range`]: crate::Text::check_range
+    pub fn try_add(&mut self, range: impl RangeBounds<usize>, attr: T) -> Result<(), Error> {
+        self.add(IdxRange::try_from_bounds(range)?, attr);
+        Ok(())
     }
 
-    /// Gets an iterator over all pairs of ranges and their styles. It returns non intersecting
-    /// ranges in ascending order with style info.
+    /// Checked version of [`replace`]. See [`try_add`] for errors.
     ///
-    /// The iterator element type is ([`IdxRange`], &'a [`Style`]).
-    pub fn iter(&self) -> Iter<'_> {
+    /// [`replace`]: Self::replace
+    /// [`try_add`]: Self::try_add
+    pub fn try_replace(&mut self, range: impl RangeBounds<usize>, attr: T) -> Result<(), Error> {
+        self.replace(IdxRange::try_from_bounds(range)?, attr);
+        Ok(())
+    }
+
+    /// Checked version of [`remove`]. See [`try_add`] for errors.
+    ///
+    /// [`remove`]: Self::remove
+    /// [`try_add`]: Self::try_add
+    pub fn try_remove(&mut self, range: impl RangeBounds<usize>) -> Result<(), Error> {
+        self.remove(IdxRange::try_from_bounds(range)?);
+        Ok(())
+    }
+
+    /// Follows an edit of text which replaced graphemes in `range` with `len` new graphemes.
+    /// Attributes of graphemes after the `range` are moved with them, new graphemes get the
+    /// [`default`] attribute. An empty `range` (e.g. [`IdxRange::new(3, 2)`]) inserts `len`
+    /// graphemes at its `start`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, IdxRange};
+    /// let red = Style::new().fg(Color::Red);
+    /// let mut mask = Mask::new();
+    /// // "let x = 1" with red "1"
+    /// mask.add(8..9, red);
+    ///
+    /// // "let x = 1" -> "let value = 1"
+    /// mask.splice(4..5, 5);
+    /// assert_eq!(mask.first_where(|style| *style == red).unwrap().0, IdxRange::new(12, 12));
+    /// ```
+    ///
+    /// [`default`]: Default::default
+    /// [`IdxRange::new(3, 2)`]: IdxRange::new
+    pub fn splice(&mut self, range: impl Into<IdxRange>, len: usize) {
+        let range = range.into();
+        self.splice_at(range.start, range.len(), len);
+    }
+
+    /// Checked version of [`splice`]. See [`try_add`] for errors.
+    ///
+    /// [`splice`]: Self::splice
+    /// [`try_add`]: Self::try_add
+    pub fn try_splice(&mut self, range: impl RangeBounds<usize>, len: usize) -> Result<(), Error> {
+        self.splice(IdxRange::try_from_bounds(range)?, len);
+        Ok(())
+    }
+
+    /// Replaces `removed` graphemes starting at `start` with `inserted` graphemes. Unlike
+    /// [`splice`] it can insert graphemes at index 0.
+    ///
+    /// [`splice`]: Self::splice
+    pub(crate) fn splice_at(&mut self, start: usize, removed: usize, inserted: usize) {
+        self.cow.splice(start, removed, inserted, self.storage);
+    }
+
+    /// Moves attributes of graphemes starting at `idx` by `offset`. Positive `offset` inserts
+    /// graphemes with [`default`] attribute before `idx`, negative removes graphemes before `idx`
+    /// (but not more than `idx`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color};
+    /// let red = Style::new().fg(Color::Red);
+    /// let mut mask = Mask::from([(2..4, red)]);
+    ///
+    /// mask.shift(0, 2);
+    /// assert_eq!(mask, Mask::from([(4..6, red)]));
+    /// mask.shift(4, -3);
+    /// assert_eq!(mask, Mask::from([(1..3, red)]));
+    /// ```
+    ///
+    /// [`default`]: Default::default
+    pub fn shift(&mut self, idx: usize, offset: isize) {
+        match usize::try_from(offset) {
+            Ok(len) => self.splice_at(idx, 0, len),
+            Err(_) => {
+                let len = offset.unsigned_abs().min(idx);
+                self.splice_at(idx - len, len, 0);
+            }
+        }
+    }
+
+    /// Checked version of [`shift`]. Returns [`Error::ShiftUnderflow`] instead of clamping if
+    /// negative `offset` removes more than `idx` graphemes. Mask is not changed on error.
+    ///
+    /// Mask does not know length of the text, so `idx` after the end of the text is not an
+    /// error. Compare it with [`Text::grapheme_count`] or edit the [`Text`] instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Error, Mask};
+    /// let mut mask = Mask::new();
+    /// assert_eq!(mask.try_shift(2, -3), Err(Error::ShiftUnderflow { idx: 2, offset: -3 }));
+    /// ```
+    ///
+    /// [`shift`]: Self::shift
+    /// [`Text`]: crate::Text
+    /// [`Text::grapheme_count`]: crate::Text::grapheme_count
+    pub fn try_shift(&mut self, idx: usize, offset: isize) -> Result<(), Error> {
+        if offset < 0 && offset.unsigned_abs() > idx {
+            return Err(Error::ShiftUnderflow { idx, offset });
+        }
+        self.shift(idx, offset);
+        Ok(())
+    }
+
+    /// Gets an iterator over all pairs of ranges and their attributes. It returns non
+    /// intersecting ranges in ascending order with attribute info.
+    ///
+    /// The iterator element type is ([`IdxRange`], &'a T).
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter { inner: self.cow.iter() }
     }
-}
 
-impl<R: Into<IdxRange>> Extend<(R, Style)> for Mask {
-    fn extend<T: IntoIterator<Item = (R, Style)>>(&mut self, iter: T) {
-        for (range, style) in iter.into_iter() {
-            self.add(range.into(), style);
+    /// Gets an iterator over pairs of ranges and their attributes for the first `len`
+    /// graphemes. Unlike [`iter`] the last range ends at `len - 1` instead of `usize::MAX`, and
+    /// the iterator knows its exact length. Nothing is returned for `len == 0`.
+    ///
+    /// The iterator element type is ([`IdxRange`], &'a T).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, IdxRange};
+    /// let mut mask = Mask::new();
+    /// mask.add(0..2, Style::new().fg(Color::Green));
+    /// mask.add(10..12, Style::new().fg(Color::Red));
+    ///
+    /// let runs = mask.iter_bounded(5);
+    /// assert_eq!(runs.len(), 2);
+    /// assert_eq!(
+    ///     runs.collect::<Vec<_>>(),
+    ///     vec![
+    ///         (IdxRange::new(0, 1), &Style::new().fg(Color::Green)),
+    ///         (IdxRange::new(2, 4), &Style::default()),
+    ///     ]
+    /// );
+    /// ```
+    ///
+    /// [`iter`]: Self::iter
+    pub fn iter_bounded(&self, len: usize) -> Bounded<'_, T> {
+        let remaining = len.checked_sub(1).map_or(0, |last| self.cow.run_position(last) + 1);
+        Bounded { inner: self.iter(), len, remaining }
+    }
+
+    /// Returns attribute for grapheme with index `idx`. Unlike [`Index`] it never panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color};
+    /// let mut mask = Mask::new();
+    /// mask.add(0..=1, Style::new().fg(Color::Green));
+    /// assert_eq!(mask.get(1), Some(&Style::new().fg(Color::Green)));
+    /// assert_eq!(mask.get(2), Some(&Style::default()));
+    /// ```
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        match &self.cow {
+            Cow::Single(s) => Some(s),
+            Cow::Multiple(m) => Some(m.get(idx)),
         }
     }
+
+    /// Gets an iterator over pairs of ranges and their attributes which intersect `window`.
+    /// Returned ranges are clipped to the `window`. The first run is found by a binary search and
+    /// iteration stops right after the `window`, so it is cheap to draw only a visible part of a
+    /// large text.
+    ///
+    /// The iterator element type is ([`IdxRange`], &'a T).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, IdxRange};
+    /// let mut mask = Mask::new();
+    /// mask.add(2..6, Style::new().fg(Color::Green));
+    ///
+    /// let runs: Vec<_> = mask.range(4..8).collect();
+    /// assert_eq!(
+    ///     runs,
+    ///     vec![
+    ///         (IdxRange::new(4, 5), &Style::new().fg(Color::Green)),
+    ///         (IdxRange::new(6, 7), &Style::default()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn range(&self, window: impl Into<IdxRange>) -> Range<'_, T> {
+        let window = window.into();
+        Range { inner: self.cow.range_from(window.start), window }
+    }
+
+    /// Checked version of [`range`]. See [`try_add`] for errors.
+    ///
+    /// [`range`]: Self::range
+    /// [`try_add`]: Self::try_add
+    pub fn try_range(&self, window: impl RangeBounds<usize>) -> Result<Range<'_, T>, Error> {
+        Ok(self.range(IdxRange::try_from_bounds(window)?))
+    }
+
+    /// Returns the first run whose attribute matches `pred`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, IdxRange};
+    /// let mut mask = Mask::new();
+    /// mask.add(3..5, Style::new().bg(Color::Red));
+    ///
+    /// let (range, _) = mask.first_where(|style| style.bg == Some(Color::Red)).unwrap();
+    /// assert_eq!(range, IdxRange::new(3, 4));
+    /// ```
+    pub fn first_where(&self, pred: impl FnMut(&T) -> bool) -> Option<(IdxRange, &T)> {
+        self.find_runs(pred).next()
+    }
+
+    /// Gets an iterator over all runs whose attributes match `pred` in ascending order.
+    ///
+    /// The iterator element type is ([`IdxRange`], &'a T).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Modifier};
+    /// let mut mask = Mask::new();
+    /// mask.add(0..2, Style::new().modifier(Modifier::BOLD));
+    /// mask.add(4..6, Style::new().modifier(Modifier::BOLD | Modifier::ITALIC));
+    ///
+    /// let bold = mask.find_runs(|style| style.modifier.contains(Modifier::BOLD));
+    /// assert_eq!(bold.count(), 2);
+    /// ```
+    pub fn find_runs<P>(&self, pred: P) -> FindRuns<'_, P, T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        FindRuns { inner: self.iter(), pred }
+    }
+}
+
+impl<T: Attr> Default for RangeAttrs<T> {
+    fn default() -> Self {
+        Self { cow: Cow::default(), storage: StoragePolicy::default() }
+    }
 }
 
-impl<'a, R: Into<IdxRange>> Extend<(R, &'a Style)> for Mask {
-    fn extend<T: IntoIterator<Item = (R, &'a Style)>>(&mut self, iter: T) {
-        for (range, style) in iter.into_iter() {
-            self.add(range.into(), *style)
+impl<T: Attr> PartialEq for RangeAttrs<T> {
+    /// Attributes are equal if they are the same for all graphemes. Storage is not compared.
+    fn eq(&self, other: &Self) -> bool {
+        self.cow == other.cow
+    }
+}
+
+impl<T: Attr + Eq> Eq for RangeAttrs<T> {}
+
+impl<T: Attr + Hash> Hash for RangeAttrs<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cow.hash(state);
+    }
+}
+
+impl<T: Attr, R: Into<IdxRange>> Extend<(R, T)> for RangeAttrs<T> {
+    fn extend<I: IntoIterator<Item = (R, T)>>(&mut self, iter: I) {
+        for (range, attr) in iter.into_iter() {
+            self.cow.add_style(range, attr, self.storage);
         }
+        self.cow.normalize(self.storage);
     }
 }
 
-impl<R: Into<IdxRange>> FromIterator<(R, Style)> for Mask {
-    fn from_iter<T: IntoIterator<Item = (R, Style)>>(iter: T) -> Self {
-        let mut mask = Mask::default();
-        mask.extend(iter);
-        mask
+impl<'a, T: Attr, R: Into<IdxRange>> Extend<(R, &'a T)> for RangeAttrs<T> {
+    fn extend<I: IntoIterator<Item = (R, &'a T)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(range, attr)| (range, attr.clone())));
+    }
+}
+
+impl<T: Attr, R: Into<IdxRange>> FromIterator<(R, T)> for RangeAttrs<T> {
+    fn from_iter<I: IntoIterator<Item = (R, T)>>(iter: I) -> Self {
+        let mut attrs = RangeAttrs::default();
+        attrs.extend(iter);
+        attrs
     }
 }
 
-impl<R, const N: usize> From<[(R, Style); N]> for Mask
+impl<T, R, const N: usize> From<[(R, T); N]> for RangeAttrs<T>
 where
+    T: Attr,
     R: Into<IdxRange>,
 {
-    fn from(value: [(R, Style); N]) -> Self {
+    fn from(value: [(R, T); N]) -> Self {
         Self::from_iter(value)
     }
 }
 
-impl Index<usize> for Mask {
-    type Output = Style;
+impl<T: Attr> Index<usize> for RangeAttrs<T> {
+    type Output = T;
 
-    /// Get [`Style`] for specified index `idx`. If no styles in this [`Mask`] for this `idx` then
-    /// [`default style`] will be returned.
+    /// Get attribute for specified index `idx`. If no attributes in this [`RangeAttrs`] for this
+    /// `idx` then [`default`] attribute will be returned.
     ///
-    /// [`default style`]: Style::new
+    /// [`default`]: Default::default
     ///
     /// # Examples
     ///
@@ -172,37 +602,32 @@ impl Index<usize> for Mask {
     /// assert_eq!(mask[0], Style::new().fg(Color::Green));
     /// ```
     fn index(&self, index: usize) -> &Self::Output {
-        match &self.cow {
-            Cow::Single(s) => s,
-            Cow::Multiple(m) => {
-                m.get(index).expect("Multiple map always contains styles for every idx")
-            }
-        }
+        self.get(index).expect("Multiple map always contains attributes for every idx")
     }
 }
 
-impl std::iter::IntoIterator for Mask {
-    type Item = (IdxRange, Style);
-    type IntoIter = IntoIter;
+impl<T: Attr> std::iter::IntoIterator for RangeAttrs<T> {
+    type Item = (IdxRange, T);
+    type IntoIter = IntoIter<T>;
 
-    /// Gets an owned iterator over all pairs of ranges and their styles. It returns non
-    /// intersecting ranges in ascending order with style info.
+    /// Gets an owned iterator over all pairs of ranges and their attributes. It returns non
+    /// intersecting ranges in ascending order with attribute info.
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter { inner: self.cow.into_iter() }
     }
 }
 
-impl<'a> std::iter::IntoIterator for &'a Mask {
-    type Item = (IdxRange, &'a Style);
-    type IntoIter = Iter<'a>;
+impl<'a, T: Attr> std::iter::IntoIterator for &'a RangeAttrs<T> {
+    type Item = (IdxRange, &'a T);
+    type IntoIter = Iter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = (IdxRange, &'a Style);
+impl<'a, T: Attr> Iterator for Iter<'a, T> {
+    type Item = (IdxRange, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next()
@@ -213,22 +638,104 @@ impl<'a> Iterator for Iter<'a> {
     }
 }
 
-impl<'a> DoubleEndedIterator for Iter<'a> {
+impl<'a, T: Attr> DoubleEndedIterator for Iter<'a, T> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.inner.next_back()
     }
 }
 
-impl<'a> ExactSizeIterator for Iter<'a> {
+impl<'a, T: Attr> ExactSizeIterator for Iter<'a, T> {
     fn len(&self) -> usize {
         self.inner.len()
     }
 }
 
-impl<'a> FusedIterator for Iter<'a> {}
+impl<'a, T: Attr> FusedIterator for Iter<'a, T> {}
+
+impl<'a, T: Attr> Iterator for Range<'a, T> {
+    type Item = (IdxRange, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.is_empty() {
+            return None;
+        }
+
+        let window = self.window;
+        let (range, attr) = self.inner.next()?;
+        if range.start > window.end {
+            self.window = IdxRange::new(1, 0);
+            return None;
+        }
+
+        let clipped = IdxRange::new(range.start.max(window.start), range.end.min(window.end));
+        if range.end >= window.end {
+            self.window = IdxRange::new(1, 0);
+        }
+        Some((clipped, attr))
+    }
+}
+
+impl<'a, T: Attr> FusedIterator for Range<'a, T> {}
+
+impl<'a, T: Attr> Bounded<'a, T> {
+    fn clip(&self, (range, attr): (IdxRange, &'a T)) -> (IdxRange, &'a T) {
+        (IdxRange::new(range.start, range.end.min(self.len - 1)), attr)
+    }
+}
+
+impl<'a, T: Attr> Iterator for Bounded<'a, T> {
+    type Item = (IdxRange, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.inner.next().map(|item| self.clip(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: Attr> DoubleEndedIterator for Bounded<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let len = self.len;
+        self.inner.by_ref().rev().find(|(range, _)| range.start < len).map(|item| self.clip(item))
+    }
+}
+
+impl<'a, T: Attr> ExactSizeIterator for Bounded<'a, T> {}
+
+impl<'a, T: Attr> FusedIterator for Bounded<'a, T> {}
+
+impl<'a, P, T> Iterator for FindRuns<'a, P, T>
+where
+    P: FnMut(&T) -> bool,
+    T: Attr,
+{
+    type Item = (IdxRange, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+        self.inner.find(|(_, attr)| pred(attr))
+    }
+}
+
+impl<'a, P, T> FusedIterator for FindRuns<'a, P, T>
+where
+    P: FnMut(&T) -> bool,
+    T: Attr,
+{
+}
 
-impl Iterator for IntoIter {
-    type Item = (IdxRange, Style);
+impl<T: Attr> Iterator for IntoIter<T> {
+    type Item = (IdxRange, T);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.inner.next()
@@ -238,17 +745,17 @@ impl Iterator for IntoIter {
         self.inner.size_hint()
     }
 }
-impl DoubleEndedIterator for IntoIter {
+impl<T: Attr> DoubleEndedIterator for IntoIter<T> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.inner.next_back()
     }
 }
-impl ExactSizeIterator for IntoIter {
+impl<T: Attr> ExactSizeIterator for IntoIter<T> {
     fn len(&self) -> usize {
         self.inner.len()
     }
 }
-impl FusedIterator for IntoIter {}
+impl<T: Attr> FusedIterator for IntoIter<T> {}
 
 /// Creates a [`Mask`] containing styles for specified ranges.
 ///
@@ -593,5 +1100,195 @@ mod tests {
         assert_eq!(mask[2], Style::default());
     }
 
+    #[test]
+    fn mask_is_canonical() {
+        let red = Style::new().fg(Color::Red);
+
+        let mut mask = mask!(0..2 => red, 4..6 => red);
+        mask.add(2..4, red);
+        let runs: Vec<_> = mask.iter().map(|(r, s)| (r.start..=r.end, *s)).collect();
+        assert_eq!(runs, vec![(0..=5, red), (6..=MAX, Style::default())]);
+
+        mask.remove(..6);
+        assert_eq!(mask, Mask::new());
+        assert!(!mask.cow.is_owned());
+    }
+
+    #[test]
+    fn equal_masks_built_differently() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let style = Style::new().bg(Color::Blue);
+        let a = mask!(.. => style);
+        let b = mask!(..5 => style, 5.. => style);
+        let c: Mask = [(0..=2, style), (3..=6, style), (7..=MAX, style)].into();
+
+        let state = RandomState::new();
+        assert_eq!(a, b);
+        assert_eq!(b, c);
+        assert_eq!(state.hash_one(&a), state.hash_one(&c));
+        assert_ne!(a, mask!(..5 => style));
+    }
+
+    #[rstest]
+    #[case::inside_one_run(3..5, vec![(3..=4, Style::new().fg(Color::Red))])]
+    #[case::several_runs(
+        1..=7,
+        vec![
+            (1..=1, Style::default()),
+            (2..=5, Style::new().fg(Color::Red)),
+            (6..=7, Style::new().fg(Color::Blue)),
+        ]
+    )]
+    #[case::tail(9.., vec![(9..=MAX, Style::new().fg(Color::Blue))])]
+    #[case::single_point(5..=5, vec![(5..=5, Style::new().fg(Color::Red))])]
+    #[case::empty(IdxRange::new(5, 4), vec![])]
+    fn mask_range(
+        #[case] window: impl Into<IdxRange>,
+        #[case] expected: Vec<(RangeInclusive<usize>, Style)>,
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] storage: StoragePolicy,
+    ) {
+        let mut mask = Mask::with_storage(storage);
+        mask.extend([(2..6, Style::new().fg(Color::Red)), (6..MAX, Style::new().fg(Color::Blue))]);
+        mask.add(MAX..=MAX, Style::new().fg(Color::Blue));
+        let runs: Vec<_> = mask.range(window).map(|(r, s)| (r.start..=r.end, *s)).collect();
+        assert_eq!(runs, expected);
+    }
+
+    #[test]
+    fn mask_range_of_single() {
+        let mask = Mask::new();
+        let runs: Vec<_> = mask.range(10..20).collect();
+        assert_eq!(runs, vec![(IdxRange::new(10, 19), &Style::default())]);
+    }
+
+    #[rstest]
+    #[case::empty(0, vec![])]
+    #[case::inside_first_run(1, vec![(0..=0, Style::new().fg(Color::Red))])]
+    #[case::run_boundary(
+        2,
+        vec![(0..=1, Style::new().fg(Color::Red))]
+    )]
+    #[case::default_tail(
+        8,
+        vec![
+            (0..=1, Style::new().fg(Color::Red)),
+            (2..=3, Style::default()),
+            (4..=5, Style::new().fg(Color::Blue)),
+            (6..=7, Style::default()),
+        ]
+    )]
+    fn mask_iter_bounded(
+        #[case] len: usize,
+        #[case] expected: Vec<(RangeInclusive<usize>, Style)>,
+    ) {
+        let mask = mask!(0..2 => Style::new().fg(Color::Red), 4..6 => Style::new().fg(Color::Blue));
+
+        let runs = mask.iter_bounded(len);
+        assert_eq!(runs.len(), expected.len());
+        let runs: Vec<_> = runs.map(|(r, s)| (r.start..=r.end, *s)).collect();
+        assert_eq!(runs, expected);
+
+        let mut rev: Vec<_> =
+            mask.iter_bounded(len).rev().map(|(r, s)| (r.start..=r.end, *s)).collect();
+        rev.reverse();
+        assert_eq!(rev, expected);
+    }
+
+    #[test]
+    fn mask_find_runs() {
+        let red = Style::new().fg(Color::Red);
+        let mask = mask!(0..2 => red, 4..6 => red, 8..10 => red);
+
+        let runs: Vec<_> = mask.find_runs(|s| *s == red).map(|(r, _)| r.into_tuple()).collect();
+        assert_eq!(runs, vec![(0, 1), (4, 5), (8, 9)]);
+        assert_eq!(mask.first_where(|s| *s == red.bg(Color::Red)), None);
+        assert_eq!(mask.first_where(|s| s.fg.is_none()).map(|(r, _)| r), Some(IdxRange::new(2, 3)));
+    }
+
+    #[rstest]
+    #[case::insert_at_start(
+        (0, 0, 2),
+        vec![(0..=3, None), (4..=5, Some('r')), (6..=7, None), (8..=MAX, Some('b'))]
+    )]
+    #[case::remove_inside(
+        (3, 1, 0),
+        vec![(0..=1, None), (2..=2, Some('r')), (3..=4, None), (5..=MAX, Some('b'))]
+    )]
+    #[case::replace_across_runs(
+        (3, 4, 1),
+        vec![(0..=1, None), (2..=2, Some('r')), (3..=3, None), (4..=MAX, Some('b'))]
+    )]
+    #[case::remove_tail((4, MAX - 3, 0), vec![(0..=1, None), (2..=3, Some('r')), (4..=MAX, None)])]
+    #[case::insert_at_max(
+        (MAX, 0, 1),
+        vec![
+            (0..=1, None),
+            (2..=3, Some('r')),
+            (4..=5, None),
+            (6..=MAX - 1, Some('b')),
+            (MAX..=MAX, None),
+        ]
+    )]
+    #[case::insert_past_max((5, 0, MAX), vec![(0..=1, None), (2..=3, Some('r')), (4..=MAX, None)])]
+    #[case::shift_to_max(
+        (1, 0, MAX - 5),
+        vec![(0..=MAX - 4, None), (MAX - 3..=MAX - 2, Some('r')), (MAX - 1..=MAX, None)]
+    )]
+    fn attrs_splice(
+        #[case] (start, removed, inserted): (usize, usize, usize),
+        #[case] expected: Vec<(RangeInclusive<usize>, Option<char>)>,
+        #[values(StoragePolicy::Vec, StoragePolicy::BTree)] storage: StoragePolicy,
+    ) {
+        let mut attrs = RangeAttrs::with_storage(storage);
+        attrs.extend([(2..4, Some('r')), (6..MAX, Some('b'))]);
+        attrs.replace(MAX..=MAX, Some('b'));
+
+        attrs.splice_at(start, removed, inserted);
+        let runs: Vec<_> = attrs.into_iter().map(|(r, c)| (r.start..=r.end, c)).collect();
+        assert_eq!(runs, expected);
+    }
+
+    #[test]
+    fn attrs_shift() {
+        let red = Style::new().fg(Color::Red);
+        let mut mask = mask!(2..4 => red);
+
+        mask.shift(3, 2);
+        assert_eq!(mask, mask!(2..3 => red, 5..6 => red));
+        mask.shift(5, -2);
+        assert_eq!(mask, mask!(2..4 => red));
+        mask.shift(1, -5);
+        assert_eq!(mask, mask!(1..3 => red));
+        mask.splice(0..=0, 0);
+        assert_eq!(mask, mask!(0..2 => red));
+    }
+
+    #[test]
+    fn checked_edits() {
+        let red = Style::new().fg(Color::Red);
+        let mut mask = Mask::new();
+
+        assert_eq!(mask.try_add(1..3, red), Ok(()));
+        assert_eq!(mask.try_replace(..0, red), Err(Error::InvalidRange(BoundsError::EndUnderflow)));
+        assert_eq!(mask.try_shift(1, -2), Err(Error::ShiftUnderflow { idx: 1, offset: -2 }));
+        assert_eq!(mask, Mask::from([(1..3, red)]));
+
+        assert_eq!(mask.try_shift(1, -1), Ok(()));
+        assert_eq!(mask.try_splice(1..1, 1), Ok(()));
+        assert_eq!(mask, Mask::from([(0..1, red), (2..3, red)]));
+        assert_eq!(mask.try_remove(..), Ok(()));
+        assert_eq!(mask.try_range(0..4).map(Iterator::count), Ok(1));
+    }
+
+    #[test]
+    fn combine_options() {
+        let mut attrs = RangeAttrs::new();
+        attrs.add(0..4, Some(1));
+        attrs.add(2..6, None);
+        attrs.add(3..4, Some(2));
+        assert_eq!(attrs, RangeAttrs::from([(0..3, Some(1)), (3..4, Some(2))]));
+    }
+
     fn iter_mask() {}
 }
@@ -0,0 +1,378 @@
+use std::iter::FusedIterator;
+
+use crate::{
+    mask::{cow::Cow, Iter},
+    IdxRange, Mask, Style,
+};
+
+/// Strategy of combining styles in [`Mask::overlay`].
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{mask::MergeStrategy, Mask, Style, Color};
+/// let mut mask = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+/// let search = Mask::from([(2..6, Style::new().bg(Color::Yellow))]);
+///
+/// let only_fg = |existing: Style, overlay: Style| existing.fg(overlay.bg.unwrap_or_default());
+/// mask.overlay(&search, MergeStrategy::Custom(&only_fg));
+/// assert_eq!(mask[3], Style::new().fg(Color::Yellow));
+/// assert_eq!(mask[5], Style::new().fg(Color::Yellow));
+/// ```
+#[derive(Clone, Copy)]
+pub enum MergeStrategy<'a> {
+    /// [`Merge`] overlay style into the existing one.
+    ///
+    /// [`Merge`]: Style::merge
+    Merge,
+    /// Replace existing style with overlay style.
+    Replace,
+    /// Keep existing style if it is not default, otherwise use overlay style.
+    KeepExisting,
+    /// Combine styles with closure which takes existing and overlay styles.
+    Custom(&'a dyn Fn(Style, Style) -> Style),
+}
+
+impl<'a> MergeStrategy<'a> {
+    fn apply(&self, existing: Style, overlay: Style) -> Style {
+        match self {
+            MergeStrategy::Merge => existing.merge(overlay),
+            MergeStrategy::Replace => overlay,
+            MergeStrategy::KeepExisting if existing == Style::default() => overlay,
+            MergeStrategy::KeepExisting => existing,
+            MergeStrategy::Custom(f) => f(existing, overlay),
+        }
+    }
+}
+
+impl std::fmt::Debug for MergeStrategy<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeStrategy::Merge => f.write_str("Merge"),
+            MergeStrategy::Replace => f.write_str("Replace"),
+            MergeStrategy::KeepExisting => f.write_str("KeepExisting"),
+            MergeStrategy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Mask {
+    /// Combines `other` mask into `self` with specified `strategy`. Ranges of `other` with
+    /// [`default`] style are transparent: styles of `self` are not touched there.
+    ///
+    /// Unlike calling [`add`] for every range, it walks both masks once, so it takes O(n + m) for
+    /// masks with n and m ranges.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{mask::MergeStrategy, Mask, Style, Color};
+    /// let mut syntax = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+    /// let selection = Mask::from([(2..6, Style::new().bg(Color::Blue))]);
+    ///
+    /// let mut merged = syntax.clone();
+    /// merged.overlay(&selection, MergeStrategy::Merge);
+    /// assert_eq!(merged[3], Style::new().fg(Color::Red).bg(Color::Blue));
+    ///
+    /// syntax.overlay(&selection, MergeStrategy::KeepExisting);
+    /// assert_eq!(syntax[3], Style::new().fg(Color::Red));
+    /// assert_eq!(syntax[4], Style::new().bg(Color::Blue));
+    /// ```
+    ///
+    /// [`default`]: Style::default
+    /// [`add`]: Self::add
+    pub fn overlay(&mut self, other: &Mask, strategy: MergeStrategy<'_>) {
+        self.combine(other, |existing, overlay| {
+            if overlay == Style::default() {
+                existing
+            } else {
+                strategy.apply(existing, overlay)
+            }
+        });
+    }
+
+    /// Keeps styles of `self` only in ranges where `other` has not [`default`] style. All other
+    /// graphemes get default style. It takes O(n + m) for masks with n and m ranges.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color};
+    /// let mut mask = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+    /// mask.intersect(&Mask::from([(2..6, Style::new().bg(Color::Blue))]));
+    /// assert_eq!(mask[1], Style::default());
+    /// assert_eq!(mask[2], Style::new().fg(Color::Red));
+    /// ```
+    ///
+    /// [`default`]: Style::default
+    pub fn intersect(&mut self, other: &Mask) {
+        self.combine(
+            other,
+            |existing, other| {
+                if other == Style::default() {
+                    Style::default()
+                } else {
+                    existing
+                }
+            },
+        );
+    }
+
+    /// Resets styles of `self` to [`default`] in ranges where `other` has not default style. It
+    /// takes O(n + m) for masks with n and m ranges.
+    ///
+    /// There is no variant which takes a range: difference with a single range is [`remove`],
+    /// which edits only runs inside the range instead of rebuilding the mask.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color};
+    /// let mut mask = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+    /// mask.difference(&Mask::from([(2..6, Style::new().bg(Color::Blue))]));
+    /// assert_eq!(mask[1], Style::new().fg(Color::Red));
+    /// assert_eq!(mask[2], Style::default());
+    ///
+    /// let mut same = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+    /// same.remove(2..6);
+    /// assert_eq!(same, mask);
+    /// ```
+    ///
+    /// [`default`]: Style::default
+    /// [`remove`]: Mask::remove
+    pub fn difference(&mut self, other: &Mask) {
+        self.combine(
+            other,
+            |existing, other| {
+                if other == Style::default() {
+                    existing
+                } else {
+                    Style::default()
+                }
+            },
+        );
+    }
+
+    /// Transforms all styles of the mask with `f` at once. It takes O(n) for mask with n ranges.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Mask, Style, Color, Modifier};
+    /// let mut mask = Mask::from([(0..4, Style::new().fg(Color::Red))]);
+    /// mask.map_styles(|style| style.add_modifier(Modifier::DIM));
+    /// assert_eq!(mask[0], Style::new().fg(Color::Red).modifier(Modifier::DIM));
+    /// assert_eq!(mask[4], Style::new().modifier(Modifier::DIM));
+    /// ```
+    pub fn map_styles(&mut self, mut f: impl FnMut(Style) -> Style) {
+        self.cow = match &self.cow {
+            Cow::Single(style) => Cow::Single(f(*style)),
+            cow => {
+                Cow::from_runs(cow.iter().map(|(range, style)| (range, f(*style))), self.storage)
+            }
+        };
+    }
+
+    /// Replaces styles of `self` with `f(self, other)` for all graphemes.
+    fn combine(&mut self, other: &Mask, mut f: impl FnMut(Style, Style) -> Style) {
+        self.cow = match (&self.cow, &other.cow) {
+            (Cow::Single(a), Cow::Single(b)) => Cow::Single(f(*a, *b)),
+            _ => Cow::from_runs(
+                Zip::new(self.iter(), other.iter()).map(|(range, a, b)| (range, f(*a, *b))),
+                self.storage,
+            ),
+        };
+    }
+}
+
+/// Iterator over two sequences of runs at once. It returns ranges where both sequences have the
+/// same attributes. Both sequences must cover the same indexes without gaps.
+pub(super) struct Zip<'a, A, B, T> {
+    a: A,
+    b: B,
+    cur_a: Option<(IdxRange, &'a T)>,
+    cur_b: Option<(IdxRange, &'a T)>,
+}
+
+impl<'a, A, B, T> Zip<'a, A, B, T>
+where
+    A: Iterator<Item = (IdxRange, &'a T)>,
+    B: Iterator<Item = (IdxRange, &'a T)>,
+{
+    pub(super) fn new(mut a: A, mut b: B) -> Self {
+        Self { cur_a: a.next(), cur_b: b.next(), a, b }
+    }
+}
+
+impl<'a, A, B, T> Iterator for Zip<'a, A, B, T>
+where
+    A: Iterator<Item = (IdxRange, &'a T)>,
+    B: Iterator<Item = (IdxRange, &'a T)>,
+{
+    type Item = (IdxRange, &'a T, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (range_a, attr_a) = self.cur_a?;
+        let (range_b, attr_b) = self.cur_b?;
+        let end = range_a.end.min(range_b.end);
+        let item = (IdxRange::new(range_a.start, end), attr_a, attr_b);
+
+        // The rest of the longer run is returned on the next call
+        self.cur_a = if range_a.end == end {
+            self.a.next()
+        } else {
+            Some((IdxRange::new(end + 1, range_a.end), attr_a))
+        };
+        self.cur_b = if range_b.end == end {
+            self.b.next()
+        } else {
+            Some((IdxRange::new(end + 1, range_b.end), attr_b))
+        };
+
+        Some(item)
+    }
+}
+
+impl<'a, A, B, T> FusedIterator for Zip<'a, A, B, T>
+where
+    A: FusedIterator<Item = (IdxRange, &'a T)>,
+    B: FusedIterator<Item = (IdxRange, &'a T)>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mask, Color, Modifier};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    const MAX: usize = usize::MAX;
+
+    fn runs(mask: &Mask) -> Vec<((usize, usize), Style)> {
+        mask.iter().map(|(range, style)| (range.into_tuple(), *style)).collect()
+    }
+
+    fn base() -> Mask {
+        mask!(0..4 => Style::new().fg(Color::Red), 6..8 => Style::new().fg(Color::Green))
+    }
+
+    fn other() -> Mask {
+        mask!(2..7 => Style::new().fg(Color::Blue).modifier(Modifier::BOLD))
+    }
+
+    #[rstest]
+    #[case::merge(
+        MergeStrategy::Merge,
+        vec![
+            ((0, 1), Style::new().fg(Color::Red)),
+            ((2, 6), Style::new().fg(Color::Blue).modifier(Modifier::BOLD)),
+            ((7, 7), Style::new().fg(Color::Green)),
+            ((8, MAX), Style::default()),
+        ]
+    )]
+    #[case::keep_existing(
+        MergeStrategy::KeepExisting,
+        vec![
+            ((0, 3), Style::new().fg(Color::Red)),
+            ((4, 5), Style::new().fg(Color::Blue).modifier(Modifier::BOLD)),
+            ((6, 7), Style::new().fg(Color::Green)),
+            ((8, MAX), Style::default()),
+        ]
+    )]
+    #[case::custom(
+        MergeStrategy::Custom(&|existing, _| existing.add_modifier(Modifier::ITALIC)),
+        vec![
+            ((0, 1), Style::new().fg(Color::Red)),
+            ((2, 3), Style::new().fg(Color::Red).modifier(Modifier::ITALIC)),
+            ((4, 5), Style::new().modifier(Modifier::ITALIC)),
+            ((6, 6), Style::new().fg(Color::Green).modifier(Modifier::ITALIC)),
+            ((7, 7), Style::new().fg(Color::Green)),
+            ((8, MAX), Style::default()),
+        ]
+    )]
+    fn overlay(
+        #[case] strategy: MergeStrategy<'_>,
+        #[case] expected: Vec<((usize, usize), Style)>,
+    ) {
+        let mut mask = base();
+        mask.overlay(&other(), strategy);
+        assert_eq!(runs(&mask), expected);
+    }
+
+    #[test]
+    fn overlay_replace_keeps_transparent_ranges() {
+        let mut mask = base();
+        mask.overlay(&mask!(2..3 => Style::new().bg(Color::Blue)), MergeStrategy::Replace);
+        assert_eq!(
+            runs(&mask),
+            vec![
+                ((0, 1), Style::new().fg(Color::Red)),
+                ((2, 2), Style::new().bg(Color::Blue)),
+                ((3, 3), Style::new().fg(Color::Red)),
+                ((4, 5), Style::default()),
+                ((6, 7), Style::new().fg(Color::Green)),
+                ((8, MAX), Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlay_is_same_as_add() {
+        let mut overlaid = base();
+        overlaid.overlay(&other(), MergeStrategy::Merge);
+
+        let mut added = base();
+        for (range, style) in &other() {
+            added.add(range, *style);
+        }
+        assert_eq!(overlaid, added);
+    }
+
+    #[test]
+    fn intersect_and_difference() {
+        let mut intersection = base();
+        intersection.intersect(&other());
+        assert_eq!(
+            runs(&intersection),
+            vec![
+                ((0, 1), Style::default()),
+                ((2, 3), Style::new().fg(Color::Red)),
+                ((4, 5), Style::default()),
+                ((6, 6), Style::new().fg(Color::Green)),
+                ((7, MAX), Style::default()),
+            ]
+        );
+
+        let mut difference = base();
+        difference.difference(&other());
+        assert_eq!(
+            runs(&difference),
+            vec![
+                ((0, 1), Style::new().fg(Color::Red)),
+                ((2, 6), Style::default()),
+                ((7, 7), Style::new().fg(Color::Green)),
+                ((8, MAX), Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_masks() {
+        let mut mask = mask!(.. => Style::new().fg(Color::Red));
+        mask.overlay(&mask!(.. => Style::new().bg(Color::Blue)), MergeStrategy::Merge);
+        assert_eq!(mask, mask!(.. => Style::new().fg(Color::Red).bg(Color::Blue)));
+
+        mask.difference(&Mask::new());
+        assert_eq!(mask, mask!(.. => Style::new().fg(Color::Red).bg(Color::Blue)));
+        mask.intersect(&Mask::new());
+        assert_eq!(mask, Mask::new());
+    }
+
+    #[test]
+    fn map_styles_coalesces() {
+        let mut mask = base();
+        mask.map_styles(|_| Style::new().modifier(Modifier::BOLD));
+        assert_eq!(mask, mask!(.. => Style::new().modifier(Modifier::BOLD)));
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::OnceLock;
+
+use crate::{
+    mask::{Bounded, Iter, MergeStrategy},
+    Mask,
+};
+
+/// [`MaskStack`] holds named [`Mask`] layers ordered by priority. Layers with higher priority are
+/// [`merged`] over the lower ones, layers with the same priority are merged in insertion order.
+///
+/// Every layer can be replaced or toggled on its own. The composite mask is built lazily on the
+/// first access and cached until any layer is changed, so moving selection does not rebuild
+/// anything until the stack is drawn again.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{mask::MaskStack, Mask, Style, Color};
+/// let mut stack = MaskStack::new();
+/// stack.set_layer("syntax", 0, Mask::from([(0..4, Style::new().fg(Color::Red))]));
+/// stack.set_layer("selection", 10, Mask::from([(2..6, Style::new().bg(Color::Blue))]));
+/// assert_eq!(stack.mask()[3], Style::new().fg(Color::Red).bg(Color::Blue));
+///
+/// stack.set_enabled("selection", false);
+/// assert_eq!(stack.mask()[3], Style::new().fg(Color::Red));
+/// ```
+///
+/// [`merged`]: MergeStrategy::Merge
+#[derive(Debug, Default, Clone)]
+pub struct MaskStack {
+    /// Layers sorted by priority.
+    layers: Vec<Layer>,
+    composite: OnceLock<Mask>,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    name: String,
+    priority: i32,
+    enabled: bool,
+    mask: Mask,
+}
+
+impl MaskStack {
+    /// Create empty [`MaskStack`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `mask` of layer `name` with specified `priority`. Layer with the same name is replaced,
+    /// new layer is enabled.
+    pub fn set_layer(&mut self, name: impl Into<String>, priority: i32, mask: Mask) {
+        let name = name.into();
+        let enabled = match self.position(&name) {
+            Some(idx) => self.layers.remove(idx).enabled,
+            None => true,
+        };
+
+        let idx = self.layers.partition_point(|layer| layer.priority <= priority);
+        self.layers.insert(idx, Layer { name, priority, enabled, mask });
+        self.invalidate();
+    }
+
+    /// Removes layer `name` and returns its mask.
+    pub fn remove_layer(&mut self, name: &str) -> Option<Mask> {
+        let idx = self.position(name)?;
+        self.invalidate();
+        Some(self.layers.remove(idx).mask)
+    }
+
+    /// Returns mask of layer `name`.
+    pub fn layer(&self, name: &str) -> Option<&Mask> {
+        self.position(name).map(|idx| &self.layers[idx].mask)
+    }
+
+    /// Returns mutable mask of layer `name`. Cached composite mask is dropped.
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Mask> {
+        let idx = self.position(name)?;
+        self.invalidate();
+        Some(&mut self.layers[idx].mask)
+    }
+
+    /// Enables or disables layer `name`. Disabled layers are skipped in composition. Returns
+    /// `false` if there is no such layer.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let Some(idx) = self.position(name) else { return false };
+        if self.layers[idx].enabled != enabled {
+            self.layers[idx].enabled = enabled;
+            self.invalidate();
+        }
+        true
+    }
+
+    /// Returns `true` if layer `name` exists and it is enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name).is_some_and(|idx| self.layers[idx].enabled)
+    }
+
+    /// Returns names of all layers from the lowest priority to the highest.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|layer| layer.name.as_str())
+    }
+
+    /// Returns composite mask of all enabled layers. It is computed only if some layer was
+    /// changed after the previous call.
+    pub fn mask(&self) -> &Mask {
+        self.composite.get_or_init(|| {
+            let mut mask = Mask::new();
+            for layer in self.layers.iter().filter(|layer| layer.enabled) {
+                mask.overlay(&layer.mask, MergeStrategy::Merge);
+            }
+            mask
+        })
+    }
+
+    /// Gets an iterator over ranges and styles of [`composite mask`].
+    ///
+    /// [`composite mask`]: Self::mask
+    pub fn iter(&self) -> Iter<'_> {
+        self.mask().iter()
+    }
+
+    /// Same as [`Mask::iter_bounded`] for [`composite mask`].
+    ///
+    /// [`composite mask`]: Self::mask
+    pub fn iter_bounded(&self, len: usize) -> Bounded<'_> {
+        self.mask().iter_bounded(len)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    fn invalidate(&mut self) {
+        self.composite.take();
+    }
+}
+
+impl<'a> IntoIterator for &'a MaskStack {
+    type Item = <Iter<'a> as Iterator>::Item;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Modifier, Style};
+    use pretty_assertions::assert_eq;
+
+    fn stack() -> MaskStack {
+        let mut stack = MaskStack::new();
+        stack.set_layer("selection", 10, Mask::from([(2..6, Style::new().fg(Color::Blue))]));
+        stack.set_layer("syntax", 0, Mask::from([(0..4, Style::new().fg(Color::Red))]));
+        stack.set_layer("search", 10, Mask::from([(5..8, Style::new().modifier(Modifier::BOLD))]));
+        stack
+    }
+
+    #[test]
+    fn layers_order() {
+        let stack = stack();
+        assert_eq!(stack.names().collect::<Vec<_>>(), vec!["syntax", "selection", "search"]);
+        assert_eq!(stack.mask()[3], Style::new().fg(Color::Blue));
+        assert_eq!(stack.mask()[5], Style::new().fg(Color::Blue).modifier(Modifier::BOLD));
+    }
+
+    #[test]
+    fn stack_is_send_and_sync() {
+        fn check<T: Send + Sync>(_: &T) {}
+        check(&stack());
+    }
+
+    #[test]
+    fn composite_is_cached_until_change() {
+        let mut stack = stack();
+        let first: *const Mask = stack.mask();
+        assert_eq!(first, stack.mask() as *const Mask);
+
+        stack.layer_mut("syntax").unwrap().add(0..1, Style::new().bg(Color::Green));
+        assert!(stack.composite.get().is_none());
+        assert_eq!(stack.mask()[0], Style::new().fg(Color::Red).bg(Color::Green));
+    }
+
+    #[test]
+    fn toggle_and_replace_layers() {
+        let mut stack = stack();
+        assert!(stack.set_enabled("selection", false));
+        assert!(!stack.set_enabled("unknown", false));
+        assert_eq!(stack.mask()[3], Style::new().fg(Color::Red));
+
+        // Replaced layer keeps its state
+        stack.set_layer("selection", -1, Mask::from([(0..1, Style::new().fg(Color::Blue))]));
+        assert!(!stack.is_enabled("selection"));
+        stack.set_enabled("selection", true);
+        assert_eq!(stack.names().collect::<Vec<_>>(), vec!["selection", "syntax", "search"]);
+        assert_eq!(stack.mask()[0], Style::new().fg(Color::Red));
+
+        assert!(stack.remove_layer("syntax").is_some());
+        assert_eq!(stack.mask()[0], Style::new().fg(Color::Blue));
+        assert_eq!(stack.layer("syntax"), None);
+    }
+}
@@ -1,14 +1,54 @@
-use std::{borrow::Cow, cell::Cell};
+mod chunks;
 
+use std::{
+    ops::Range,
+    sync::{Arc, OnceLock},
+};
+
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
+use self::chunks::Chunks;
+use crate::unit::Snap;
+
 /// Wrapper about string.
-/// The only responsibility of this struct is cache size of string and invalidate it when string
-/// is changed.
-#[derive(Debug, Default)]
-pub(super) struct Raw {
-    data: Cow<'static, str>,
-    size: Cell<Option<Size>>,
+/// The only responsibility of this struct is cache size and count of graphemes of string and
+/// invalidate them when string is changed.
+///
+/// Only metrics are chunked: they are cached for every line in a balanced tree of chunks, so
+/// [`replace_range`] measures again only lines which contain edited bytes. The tree is shared
+/// between clones. The string itself stays contiguous, so an edit still moves all bytes after the
+/// edited range, and the first edit of a borrowed or shared string copies it whole. [`modify`]
+/// does not know what was changed and invalidates the whole cache.
+///
+/// [`replace_range`]: Raw::replace_range
+/// [`modify`]: Raw::modify
+#[derive(Debug, Default, Clone)]
+pub(super) struct Raw<'a> {
+    data: Data<'a>,
+    chunks: OnceLock<Chunks>,
+}
+
+/// Storage of string. It is like `Cow<'a, str>` with additional shared variant, which is cloned
+/// without copying the string, and inline variant, which keeps short strings without allocation.
+/// Borrowed, shared and inline strings are copied on the first modification.
+#[derive(Debug, Clone)]
+enum Data<'a> {
+    Borrowed(&'a str),
+    Owned(String),
+    Shared(Arc<str>),
+    Inline(Inline),
+}
+
+/// Max length in bytes of string which is stored inline.
+const INLINE_CAP: usize = 23;
+
+/// Short string stored inside `Data` itself. Capacity is chosen to fit with length and tag of
+/// `Data` into 32 bytes.
+#[derive(Debug, Clone, Copy)]
+struct Inline {
+    len: u8,
+    buf: [u8; INLINE_CAP],
 }
 
 /// Cached size for `Raw` in terminal way
@@ -16,11 +56,11 @@ pub(super) struct Raw {
 /// You should know:
 ///
 /// 1) Not every grapheme have width=1. For example, cjk symbols: "す" have width=2.
-///    This means that you need two terminal columns to display it.
+///    This means that you need two terminal columns to display it. Control characters,
+///    including line delimiters, do not occupy columns at all, see [`width`].
 ///
 /// 2) `Height` is count of lines in terminal way. Line delimiter is "\n" or "\r\n".
-///     Last `line delimiter` after the actual content is always optional and do not create
-///     an empty line.
+///    The last line delimiter after the content is optional: it does not start an empty line.
 ///
 /// ```text
 /// "" - 0 lines
@@ -40,7 +80,7 @@ struct Size {
     height: usize,
 }
 
-impl Raw {
+impl<'a> Raw<'a> {
     pub(super) fn new() -> Self {
         Self::default()
     }
@@ -53,6 +93,36 @@ impl Raw {
         f(self.data.to_mut())
     }
 
+    /// Copies borrowed string, so `Raw` does not depend on lifetime of the string.
+    pub(super) fn into_owned(self) -> Raw<'static> {
+        let data = match self.data {
+            Data::Borrowed(s) => {
+                Inline::new(s).map_or_else(|| Data::Owned(s.to_owned()), Data::Inline)
+            }
+            Data::Owned(s) => Data::Owned(s),
+            Data::Shared(s) => Data::Shared(s),
+            Data::Inline(s) => Data::Inline(s),
+        };
+        Raw { data, chunks: self.chunks }
+    }
+
+    /// Moves owned string to shared storage, so clones of `Raw` do not copy it.
+    pub(super) fn share(&mut self) {
+        if let Data::Owned(s) = &mut self.data {
+            self.data = Data::Shared(std::mem::take(s).into());
+        }
+    }
+
+    /// Replaces bytes in `range` with `with` and updates cached metrics of changed lines. Bytes
+    /// after `range` are moved, so it takes linear time in the length of the string.
+    pub(super) fn replace_range(&mut self, range: Range<usize>, with: &str) {
+        let s = self.data.to_mut();
+        s.replace_range(range.clone(), with);
+        if let Some(chunks) = self.chunks.get_mut() {
+            chunks.replace(s, range, with.len());
+        }
+    }
+
     pub(super) fn width(&self) -> usize {
         self.get_size().width
     }
@@ -61,45 +131,124 @@ impl Raw {
         self.get_size().height
     }
 
-    fn invalidate_size_cache(&self) {
-        self.size.set(None);
+    /// Returns width of line `n` (starting from 0).
+    pub(super) fn line_width(&self, n: usize) -> Option<usize> {
+        self.chunks().line_width(n)
+    }
+
+    /// Returns count of graphemes in the string.
+    pub(super) fn graphemes(&self) -> usize {
+        self.chunks().graphemes()
+    }
+
+    /// Returns byte offset of grapheme `idx`. Index equal to count of graphemes is the end of the
+    /// string.
+    pub(super) fn grapheme_byte(&self, idx: usize) -> Option<usize> {
+        self.chunks().grapheme_byte(self.as_ref(), idx)
+    }
+
+    /// Returns index of grapheme which starts at byte offset `byte`, offset inside of a
+    /// grapheme is moved according to `snap`. Offset equal to the length of the string is the end
+    /// of the string.
+    pub(super) fn byte_grapheme(&self, byte: usize, snap: Snap) -> Option<usize> {
+        self.chunks().byte_grapheme(self.as_ref(), byte, snap)
+    }
+
+    fn invalidate_size_cache(&mut self) {
+        self.chunks.take();
     }
 
     /// Get current cached size. If cache was invalidated compute and cache new size.
     fn get_size(&self) -> Size {
-        match self.size.get() {
-            Some(s) => s,
-            None => {
-                let size = self.as_ref().into();
-                self.size.set(Some(size));
-                size
-            }
+        self.chunks().size()
+    }
+
+    /// Returns cached metrics. If cache was invalidated measure the whole string.
+    fn chunks(&self) -> &Chunks {
+        self.chunks.get_or_init(|| Chunks::new(self.as_ref()))
+    }
+}
+
+impl Data<'_> {
+    fn to_mut(&mut self) -> &mut String {
+        match self {
+            Data::Borrowed(s) => *self = Data::Owned((*s).to_owned()),
+            Data::Shared(s) => *self = Data::Owned(s.as_ref().to_owned()),
+            Data::Inline(s) => *self = Data::Owned(s.as_str().to_owned()),
+            Data::Owned(_) => {}
         }
+        let Data::Owned(s) = self else { unreachable!() };
+        s
     }
 }
 
-impl AsRef<str> for Raw {
+impl Inline {
+    /// Copies `s` into inline buffer. Returns `None` if `s` is longer than [`INLINE_CAP`] bytes.
+    fn new(s: &str) -> Option<Self> {
+        let mut buf = [0; INLINE_CAP];
+        buf.get_mut(..s.len())?.copy_from_slice(s.as_bytes());
+        Some(Self { len: s.len() as u8, buf })
+    }
+
+    fn as_str(&self) -> &str {
+        // Buffer is always filled from `&str`, so it is valid utf-8
+        std::str::from_utf8(&self.buf[..self.len as usize]).unwrap()
+    }
+}
+
+impl Default for Data<'_> {
+    fn default() -> Self {
+        Data::Borrowed("")
+    }
+}
+
+impl AsRef<str> for Raw<'_> {
     fn as_ref(&self) -> &str {
-        self.data.as_ref()
+        match &self.data {
+            Data::Borrowed(s) => s,
+            Data::Owned(s) => s,
+            Data::Shared(s) => s,
+            Data::Inline(s) => s.as_str(),
+        }
     }
 }
 
-impl From<&'static str> for Raw {
-    fn from(value: &'static str) -> Self {
-        Self { data: value.into(), size: Cell::default() }
+impl<'a> From<&'a str> for Raw<'a> {
+    fn from(value: &'a str) -> Self {
+        Self { data: Data::Borrowed(value), ..Default::default() }
     }
 }
 
-impl From<String> for Raw {
+impl From<String> for Raw<'_> {
     fn from(value: String) -> Self {
-        Self { data: value.into(), size: Cell::default() }
+        let data = Inline::new(&value).map_or(Data::Owned(value), Data::Inline);
+        Self { data, ..Default::default() }
+    }
+}
+
+impl From<char> for Raw<'_> {
+    fn from(value: char) -> Self {
+        let data = Data::Inline(Inline::new(value.encode_utf8(&mut [0; 4])).unwrap());
+        Self { data, ..Default::default() }
     }
 }
 
+impl From<Arc<str>> for Raw<'_> {
+    fn from(value: Arc<str>) -> Self {
+        Self { data: Data::Shared(value), ..Default::default() }
+    }
+}
+
+/// Returns count of terminal columns occupied by `s`. Control characters (including line
+/// delimiters) do not occupy columns, though `unicode-width` counts them as one column wide.
+pub(super) fn width(s: &str) -> usize {
+    s.split(char::is_control).map(UnicodeWidthStr::width).sum()
+}
+
 impl<'a> From<&'a str> for Size {
     fn from(s: &'a str) -> Self {
         let mut size = s.split_inclusive('\n').fold(Size::default(), |mut size, line| {
-            let width = UnicodeWidthStr::width(line);
+            let width = width(line);
             size.width = size.width.max(width);
             size.height += 1;
             size
@@ -116,11 +265,59 @@ mod tests {
     #[test]
     fn modify_invalidate_raw_cache() {
         let mut raw =
-            Raw { data: Cow::Borrowed("hello"), size: Cell::new(Some(Size::from("hello"))) };
+            Raw { data: Data::Borrowed("hello"), chunks: OnceLock::from(Chunks::new("hello")) };
 
         raw.modify(|_| {});
 
-        assert_eq!(raw.size.get(), None);
+        assert!(raw.chunks.get().is_none());
+    }
+
+    #[test]
+    fn shared_string_is_copied_on_modify() {
+        let string = "hello".repeat(10);
+        let mut raw = Raw::from(string.clone());
+        raw.share();
+        let mut clone = raw.clone();
+        assert!(std::ptr::eq(raw.as_ref(), clone.as_ref()));
+
+        clone.modify(|s| s.push('!'));
+        assert_eq!(raw.as_ref(), string);
+        assert_eq!(clone.as_ref(), string + "!");
+        assert!(matches!(clone.data, Data::Owned(_)));
+    }
+
+    #[rstest]
+    #[case::empty("", true)]
+    #[case::char("す", true)]
+    #[case::max("a".repeat(INLINE_CAP), true)]
+    #[case::long("a".repeat(INLINE_CAP + 1), false)]
+    fn short_strings_are_inline(#[case] string: impl Into<String>, #[case] inline: bool) {
+        let string = string.into();
+        let mut raw = Raw::from(string.clone());
+        assert_eq!(matches!(raw.data, Data::Inline(_)), inline);
+        assert_eq!(raw.as_ref(), string);
+        assert_eq!(matches!(Raw::from(string.as_str()).into_owned().data, Data::Inline(_)), inline);
+
+        raw.modify(|s| s.push('!'));
+        assert_eq!(raw.as_ref(), string + "!");
+        assert!(matches!(raw.data, Data::Owned(_)));
+    }
+
+    #[test]
+    fn check_raw_sizeof() {
+        assert_eq!(std::mem::size_of::<Data>(), 32);
+        assert_eq!(std::mem::size_of::<Raw>(), 48);
+        assert_eq!(std::mem::size_of::<crate::Text>(), 144);
+    }
+
+    #[rstest]
+    #[case::empty("", 0)]
+    #[case::ascii("hello", 5)]
+    #[case::combining("y\u{0301}es", 3)]
+    #[case::crlf("a\r\nb", 3)]
+    fn count_graphemes(#[case] string: &'static str, #[case] expected: usize) {
+        let raw = Raw::from(string);
+        assert_eq!(raw.graphemes(), expected);
     }
 
     #[rstest]
@@ -0,0 +1,535 @@
+use std::{ops::Range, sync::Arc};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{width, Size};
+use crate::unit::Snap;
+
+/// Preferred length of chunk in bytes. Chunk is longer if it contains a longer line.
+const CHUNK_BYTES: usize = 4096;
+
+/// Metrics of a line including its line delimiter.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+struct Line {
+    bytes: usize,
+    graphemes: usize,
+    width: usize,
+}
+
+/// Total metrics of consecutive lines.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+struct Summary {
+    bytes: usize,
+    graphemes: usize,
+    lines: usize,
+    /// The largest width of a line.
+    width: usize,
+    chunks: usize,
+}
+
+/// Leaf of the tree: consecutive lines with about [`CHUNK_BYTES`] bytes.
+#[derive(Debug, Default, Clone)]
+struct Chunk {
+    summary: Summary,
+    lines: Vec<Line>,
+}
+
+/// Node of a height-balanced (AVL) tree of chunks. Every node keeps summary of its subtree, so
+/// searches of line, byte or grapheme skip whole subtrees. Nodes are never changed after
+/// creation, so they are shared between clones.
+#[derive(Debug)]
+enum Node {
+    Leaf(Chunk),
+    Branch { summary: Summary, height: u8, left: Arc<Node>, right: Arc<Node> },
+}
+
+/// Metrics of string which are cached for every line and grouped into chunks of lines. It is only
+/// a cache: the string is stored elsewhere and passed to edits. Chunks are leaves of a balanced
+/// tree, so lookups take logarithmic time, an edit of the cache takes logarithmic time plus time
+/// to measure lines of edited chunks, and totals are O(1).
+///
+/// The tree is shared between clones: clone is O(1) and edit creates new nodes only on paths to
+/// replaced chunks.
+///
+/// Line starts after `'\n'`. It is a boundary of graphemes regardless of the rest of string, so
+/// metrics of lines do not depend on each other.
+#[derive(Debug, Default, Clone)]
+pub(super) struct Chunks {
+    root: Option<Arc<Node>>,
+}
+
+impl Chunks {
+    pub(super) fn new(s: &str) -> Self {
+        Self { root: build(group(measure(s))) }
+    }
+
+    /// Returns count of graphemes of string.
+    pub(super) fn graphemes(&self) -> usize {
+        self.summary().graphemes
+    }
+
+    /// Returns size of string.
+    pub(super) fn size(&self) -> Size {
+        let summary = self.summary();
+        Size { width: summary.width, height: summary.lines }
+    }
+
+    /// Returns width of line `n` (starting from 0).
+    pub(super) fn line_width(&self, n: usize) -> Option<usize> {
+        if n >= self.summary().lines {
+            return None;
+        }
+        let (chunk, before) = self.chunk_at(n, |summary| summary.lines);
+        Some(chunk.lines[n - before.lines].width)
+    }
+
+    /// Returns byte offset of grapheme `idx` in `s`. Index equal to count of graphemes is the
+    /// end of `s`. Only line which contains the grapheme is scanned.
+    pub(super) fn grapheme_byte(&self, s: &str, idx: usize) -> Option<usize> {
+        let graphemes = self.graphemes();
+        if idx >= graphemes {
+            return (idx == graphemes).then_some(s.len());
+        }
+
+        let (chunk, before) = self.chunk_at(idx, |summary| summary.graphemes);
+        let (mut idx, mut start) = (idx - before.graphemes, before.bytes);
+        for line in &chunk.lines {
+            if idx < line.graphemes {
+                let mut graphemes = s[start..start + line.bytes].grapheme_indices(true);
+                return graphemes.nth(idx).map(|(byte, _)| start + byte);
+            }
+            idx -= line.graphemes;
+            start += line.bytes;
+        }
+        unreachable!("Chunk contains grapheme {idx}")
+    }
+
+    /// Returns index of grapheme which starts at byte offset `byte` of `s`. Offset inside of a
+    /// grapheme is moved according to `snap`, [`Snap::Error`] returns `None` for it. Offset equal
+    /// to the length of `s` is the end of `s`. Only line which contains the offset is scanned.
+    pub(super) fn byte_grapheme(&self, s: &str, byte: usize, snap: Snap) -> Option<usize> {
+        if byte >= s.len() {
+            return (byte == s.len()).then(|| self.graphemes());
+        }
+
+        let (chunk, before) = self.chunk_at(byte, |summary| summary.bytes);
+        let (mut idx, mut start) = (before.graphemes, before.bytes);
+        for line in &chunk.lines {
+            if byte < start + line.bytes {
+                for (offset, _) in s[start..start + line.bytes].grapheme_indices(true) {
+                    if start + offset >= byte {
+                        return snap_grapheme(idx, start + offset == byte, snap);
+                    }
+                    idx += 1;
+                }
+                return snap_grapheme(idx, false, snap);
+            }
+            idx += line.graphemes;
+            start += line.bytes;
+        }
+        unreachable!("Chunk contains byte {byte}")
+    }
+
+    /// Updates metrics after bytes `removed` of string were replaced with `inserted` bytes. `s`
+    /// is the string after the edit.
+    pub(super) fn replace(&mut self, s: &str, removed: Range<usize>, inserted: usize) {
+        let Some(root) = &self.root else {
+            *self = Self::new(s);
+            return;
+        };
+
+        // Line delimiter before the line with the previous byte and line delimiter at the end of
+        // the line with the next byte are not touched, so lines between them are still aligned
+        let (first, first_chunk, first_line, start) = self.line_at(removed.start.saturating_sub(1));
+        let (mut last, last_chunk, last_line, last_start) = self.line_at(removed.end);
+        let old_end = last_start + last_chunk.lines[last_line].bytes;
+        let end = old_end + inserted - removed.len();
+
+        let lines = first_chunk.lines[..first_line]
+            .iter()
+            .copied()
+            .chain(measure(&s[start..end]))
+            .chain(last_chunk.lines[last_line + 1..].iter().copied());
+        let mut chunks = group(lines.collect());
+
+        // Short chunk is joined with the next one, so edits do not leave many small chunks
+        let short = chunks.last().is_some_and(|chunk| chunk.summary.bytes < CHUNK_BYTES / 2);
+        if short && last + 1 < root.summary().chunks {
+            let (next, _) = self.chunk_at(last + 1, |summary| summary.chunks);
+            let tail = chunks.pop().expect("Chunk is short");
+            chunks
+                .extend(group(tail.lines.into_iter().chain(next.lines.iter().copied()).collect()));
+            last += 1;
+        }
+
+        let (before, rest) = split(root, first);
+        let (_, after) = split(&rest.expect("Replaced chunks are in the tree"), last + 1 - first);
+        self.root = concat(concat(before, build(chunks)), after);
+    }
+
+    fn summary(&self) -> Summary {
+        self.root.as_ref().map_or_else(Summary::default, |root| root.summary())
+    }
+
+    /// Returns chunk which contains position `pos` measured by `metric` and summary of all chunks
+    /// before it. The last chunk is returned if `pos` is out of bounds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no chunks.
+    fn chunk_at(&self, mut pos: usize, metric: impl Fn(&Summary) -> usize) -> (&Chunk, Summary) {
+        let mut node = self.root.as_ref().expect("Chunks are not empty");
+        let mut before = Summary::default();
+        loop {
+            match &**node {
+                Node::Leaf(chunk) => return (chunk, before),
+                Node::Branch { left, right, .. } => {
+                    let left_summary = left.summary();
+                    if pos < metric(&left_summary) {
+                        node = left;
+                    } else {
+                        pos -= metric(&left_summary);
+                        before = before.add(left_summary);
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns index of chunk, the chunk, index of line in the chunk and byte offset of line which
+    /// contains byte `byte` or of the last line if `byte` is out of bounds.
+    fn line_at(&self, byte: usize) -> (usize, &Chunk, usize, usize) {
+        let (chunk, before) = self.chunk_at(byte, |summary| summary.bytes);
+        let mut start = before.bytes;
+        for (i, line) in chunk.lines.iter().enumerate() {
+            if byte < start + line.bytes || i + 1 == chunk.lines.len() {
+                return (before.chunks, chunk, i, start);
+            }
+            start += line.bytes;
+        }
+        unreachable!("Every chunk has lines")
+    }
+}
+
+/// Returns grapheme `next` if offset is its start. Otherwise offset is inside of grapheme
+/// `next - 1` and it is moved according to `snap`.
+fn snap_grapheme(next: usize, exact: bool, snap: Snap) -> Option<usize> {
+    match snap {
+        _ if exact => Some(next),
+        Snap::Left => Some(next - 1),
+        Snap::Right => Some(next),
+        Snap::Error => None,
+    }
+}
+
+impl Summary {
+    fn add(self, other: Summary) -> Summary {
+        Summary {
+            bytes: self.bytes + other.bytes,
+            graphemes: self.graphemes + other.graphemes,
+            lines: self.lines + other.lines,
+            width: self.width.max(other.width),
+            chunks: self.chunks + other.chunks,
+        }
+    }
+}
+
+impl Chunk {
+    fn push(&mut self, line: Line) {
+        let summary = Summary {
+            bytes: line.bytes,
+            graphemes: line.graphemes,
+            lines: 1,
+            width: line.width,
+            chunks: 0,
+        };
+        self.summary = Summary { chunks: 1, ..self.summary.add(summary) };
+        self.lines.push(line);
+    }
+}
+
+impl Node {
+    fn summary(&self) -> Summary {
+        match self {
+            Node::Leaf(chunk) => chunk.summary,
+            Node::Branch { summary, .. } => *summary,
+        }
+    }
+
+    fn height(&self) -> u8 {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Branch { height, .. } => *height,
+        }
+    }
+}
+
+fn branch(left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+    let summary = left.summary().add(right.summary());
+    let height = left.height().max(right.height()) + 1;
+    Arc::new(Node::Branch { summary, height, left, right })
+}
+
+/// Creates node from subtrees which heights differ by at most two, rotating them if needed.
+fn balance(left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+    if left.height() > right.height() + 1 {
+        let Node::Branch { left: outer, right: inner, .. } = &*left else {
+            unreachable!("Leaf is the lowest node")
+        };
+        if outer.height() >= inner.height() {
+            return branch(outer.clone(), branch(inner.clone(), right));
+        }
+        let Node::Branch { left: inner_left, right: inner_right, .. } = &**inner else {
+            unreachable!("Inner node is higher than outer one")
+        };
+        branch(branch(outer.clone(), inner_left.clone()), branch(inner_right.clone(), right))
+    } else if right.height() > left.height() + 1 {
+        let Node::Branch { left: inner, right: outer, .. } = &*right else {
+            unreachable!("Leaf is the lowest node")
+        };
+        if outer.height() >= inner.height() {
+            return branch(branch(left, inner.clone()), outer.clone());
+        }
+        let Node::Branch { left: inner_left, right: inner_right, .. } = &**inner else {
+            unreachable!("Inner node is higher than outer one")
+        };
+        branch(branch(left, inner_left.clone()), branch(inner_right.clone(), outer.clone()))
+    } else {
+        branch(left, right)
+    }
+}
+
+/// Joins trees of any heights. The lower tree is joined with a subtree of the same height, so it
+/// takes time proportional to the difference of heights.
+fn join(left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+    if left.height() > right.height() + 1 {
+        let Node::Branch { left: outer, right: inner, .. } = &*left else {
+            unreachable!("Leaf is the lowest node")
+        };
+        balance(outer.clone(), join(inner.clone(), right))
+    } else if right.height() > left.height() + 1 {
+        let Node::Branch { left: inner, right: outer, .. } = &*right else {
+            unreachable!("Leaf is the lowest node")
+        };
+        balance(join(left, inner.clone()), outer.clone())
+    } else {
+        branch(left, right)
+    }
+}
+
+fn concat(left: Option<Arc<Node>>, right: Option<Arc<Node>>) -> Option<Arc<Node>> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(join(left, right)),
+        (left, right) => left.or(right),
+    }
+}
+
+/// Splits tree into the first `at` chunks and the rest.
+fn split(node: &Arc<Node>, at: usize) -> (Option<Arc<Node>>, Option<Arc<Node>>) {
+    if at == 0 {
+        return (None, Some(node.clone()));
+    }
+    if at >= node.summary().chunks {
+        return (Some(node.clone()), None);
+    }
+
+    let Node::Branch { left, right, .. } = &**node else { unreachable!("Leaf has one chunk") };
+    let left_chunks = left.summary().chunks;
+    if at <= left_chunks {
+        let (before, after) = split(left, at);
+        (before, concat(after, Some(right.clone())))
+    } else {
+        let (before, after) = split(right, at - left_chunks);
+        (concat(Some(left.clone()), before), after)
+    }
+}
+
+/// Builds perfectly balanced tree from `chunks`.
+fn build(chunks: Vec<Chunk>) -> Option<Arc<Node>> {
+    fn build_from(chunks: &mut impl Iterator<Item = Chunk>, len: usize) -> Arc<Node> {
+        if len == 1 {
+            return Arc::new(Node::Leaf(chunks.next().expect("Iterator has `len` chunks")));
+        }
+        let left = build_from(chunks, len / 2);
+        let right = build_from(chunks, len - len / 2);
+        branch(left, right)
+    }
+
+    let len = chunks.len();
+    (len > 0).then(|| build_from(&mut chunks.into_iter(), len))
+}
+
+/// Splits `s` into lines and measures them.
+fn measure(s: &str) -> Vec<Line> {
+    let line = |line: &str| Line {
+        bytes: line.len(),
+        graphemes: line.graphemes(true).count(),
+        width: width(line),
+    };
+    s.split_inclusive('\n').map(line).collect()
+}
+
+/// Groups `lines` into chunks.
+fn group(lines: Vec<Line>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut chunk = Chunk::default();
+    for line in lines {
+        chunk.push(line);
+        if chunk.summary.bytes >= CHUNK_BYTES {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+    }
+    if !chunk.lines.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    fn document() -> String {
+        (0..700).map(|i| format!("line {i} す\r\n")).collect()
+    }
+
+    fn lines(chunks: &Chunks) -> Vec<Line> {
+        fn collect(node: &Node, lines: &mut Vec<Line>) {
+            match node {
+                Node::Leaf(chunk) => lines.extend_from_slice(&chunk.lines),
+                Node::Branch { left, right, .. } => {
+                    collect(left, lines);
+                    collect(right, lines);
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        if let Some(root) = &chunks.root {
+            collect(root, &mut lines);
+        }
+        lines
+    }
+
+    /// Checks that heights of children differ by at most one and summaries are up to date.
+    fn assert_balanced(node: &Node) {
+        if let Node::Branch { summary, height, left, right } = node {
+            assert!(left.height().abs_diff(right.height()) <= 1);
+            assert_eq!(*height, left.height().max(right.height()) + 1);
+            assert_eq!(*summary, left.summary().add(right.summary()));
+            assert_balanced(left);
+            assert_balanced(right);
+        }
+    }
+
+    fn assert_measured(chunks: &Chunks, s: &str) {
+        let expected = Chunks::new(s);
+        assert_eq!(lines(chunks), lines(&expected));
+        assert_eq!(chunks.graphemes(), s.graphemes(true).count());
+        assert_eq!(chunks.size(), Size::from(s));
+        if let Some(root) = &chunks.root {
+            assert_balanced(root);
+        }
+    }
+
+    #[rstest]
+    #[case::insert_char(100, 100, "x")]
+    #[case::insert_line(100, 100, "new\nlines\n")]
+    #[case::join_lines(5000, 5020, "")]
+    #[case::remove_delimiter(4094, 4097, "")]
+    #[case::wide_line(9000, 9001, &"すし".repeat(1000))]
+    #[case::combining_after_delimiter(4096, 4096, "\u{0301}")]
+    #[case::split_crlf(11, 12, "\n")]
+    #[case::append(usize::MAX, usize::MAX, "tail")]
+    #[case::remove_all(0, usize::MAX, "")]
+    fn replace_matches_measure(#[case] start: usize, #[case] end: usize, #[case] inserted: &str) {
+        let mut s = document();
+        let mut chunks = Chunks::new(&s);
+        assert!(chunks.summary().chunks > 1);
+
+        let (start, end) = (start.min(s.len()), end.min(s.len()));
+        let (start, end) = (s.floor_char_boundary(start), s.floor_char_boundary(end));
+        s.replace_range(start..end, inserted);
+        chunks.replace(&s, start..end, inserted.len());
+        assert_measured(&chunks, &s);
+    }
+
+    #[test]
+    fn edits_keep_tree_balanced() {
+        let mut s = document().repeat(4);
+        let mut chunks = Chunks::new(&s);
+        for i in 0..200 {
+            let start = s.floor_char_boundary(i * 7919 % s.len());
+            let end = s.floor_char_boundary((start + i * 31).min(s.len()));
+            let inserted = if i % 3 == 0 { "x" } else { "new\nlines\n" };
+            s.replace_range(start..end, inserted);
+            chunks.replace(&s, start..end, inserted.len());
+        }
+        assert_measured(&chunks, &s);
+        let root = chunks.root.as_ref().unwrap();
+        assert!(usize::from(root.height()) <= 2 * root.summary().chunks.ilog2() as usize + 2);
+    }
+
+    #[test]
+    fn clones_share_chunks() {
+        let old = document();
+        let mut chunks = Chunks::new(&old);
+        let clone = chunks.clone();
+        assert!(Arc::ptr_eq(chunks.root.as_ref().unwrap(), clone.root.as_ref().unwrap()));
+
+        let mut s = old.clone();
+        s.replace_range(0..0, "x\n");
+        chunks.replace(&s, 0..0, 2);
+        assert_measured(&chunks, &s);
+        assert_measured(&clone, &old);
+    }
+
+    #[test]
+    fn line_widths() {
+        let chunks = Chunks::new("ab\r\n\nすしc\n");
+        let widths: Vec<_> = (0..4).map(|n| chunks.line_width(n)).collect();
+        assert_eq!(widths, vec![Some(2), Some(0), Some(5), None]);
+    }
+
+    #[test]
+    fn grapheme_bytes() {
+        let s = document();
+        let chunks = Chunks::new(&s);
+        let expected: Vec<_> = s
+            .grapheme_indices(true)
+            .map(|(byte, _)| Some(byte))
+            .chain([Some(s.len()), None])
+            .collect();
+        // Every grapheme is slow in debug builds, check graphemes at different offsets in chunks
+        for idx in (0..expected.len()).step_by(97).chain([expected.len() - 2, expected.len() - 1]) {
+            assert_eq!(chunks.grapheme_byte(&s, idx), expected[idx], "grapheme {idx}");
+        }
+    }
+
+    #[rstest]
+    fn byte_graphemes(#[values(Snap::Left, Snap::Right, Snap::Error)] snap: Snap) {
+        let s = document().replace('す', "y\u{0301}");
+        let chunks = Chunks::new(&s);
+        let starts: Vec<_> = s.grapheme_indices(true).map(|(byte, _)| byte).collect();
+        // Bytes inside and around "\r\n" and "ý" at different offsets in chunks
+        let bytes =
+            (0..s.len()).step_by(89).flat_map(|byte| byte..byte + 4).filter(|b| *b < s.len());
+        for byte in bytes.chain([s.len() - 1, s.len(), s.len() + 1]) {
+            let next = starts.partition_point(|start| *start < byte);
+            let expected = match starts.get(next) {
+                _ if byte > s.len() => None,
+                Some(start) if *start == byte => Some(next),
+                None if byte == s.len() => Some(next),
+                _ => match snap {
+                    Snap::Left => Some(next - 1),
+                    Snap::Right => Some(next),
+                    Snap::Error => None,
+                },
+            };
+            assert_eq!(chunks.byte_grapheme(&s, byte, snap), expected, "byte {byte}");
+        }
+    }
+}
@@ -1,11 +1,28 @@
+mod color;
+mod parse;
+
 use bitflags::bitflags;
+pub(crate) use color::{ansi_index, named_color};
+pub use color::{ColorDepth, Hsl, Oklch, Palette};
+pub use parse::ParseError;
+pub(crate) use parse::{parse_hex, parse_style_with};
+use std::ops::{BitOr, BitOrAssign, Sub, SubAssign};
 
 /// `Style` of displayed graphemes in terminal way. In terminal you can set [`foreground`] and
 /// [`background`] [`colors`]. Also you can change [`modifiers`], e.g. use bold font, underlined,
 /// etc. Look at all modifiers in [`modifier`] struct.
 ///
+/// Underline can have its own [`color`] too.
+///
+/// `Style` is designed to be layered with [`merge`]: `None` colors are inherited from the lower
+/// layer, while [`Color::Reset`] explicitly resets color to the terminal default. In the same way
+/// [`modifier`](Self::modifier) adds modifiers and [`sub_modifier`] removes them.
+///
+/// [`merge`]: Self::merge
+/// [`sub_modifier`]: Self::sub_modifier
 /// [`foreground`]: Self::fg
 /// [`background`]: Self::bg
+/// [`color`]: Self::underline_color
 /// [`colors`]: Color
 /// [`modifiers`]: Self::modifier
 /// [`modifier`]: Modifier
@@ -13,7 +30,11 @@ use bitflags::bitflags;
 pub struct Style {
     pub fg: Option<Color>,
     pub bg: Option<Color>,
+    pub underline_color: Option<Color>,
+    /// Modifiers which are added by this style.
     pub modifier: Modifier,
+    /// Modifiers which are removed by this style.
+    pub sub_modifier: Modifier,
 }
 
 /// `Color` of displayed graphemes. Full support of
@@ -40,16 +61,94 @@ pub enum Color {
     BrightWhite,
     // TODO: Add 24bit colors, may be 8bit too
     Rgb(u8, u8, u8),
+    /// Default color of the terminal. Unlike `None` in [`Style`] it is not inherited on
+    /// [`merge`] but overrides color of the lower style.
+    ///
+    /// [`merge`]: Style::merge
+    Reset,
 }
 
 bitflags! {
     /// `Modifier` of displayed graphemes. Allows to display bold, underlined, etc. text. All
     /// modifiers can be composed with bit-or(|) operation.
     #[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Hash)]
+    ///
+    /// Some modifiers are mutually exclusive: only one kind of underline and only one kind of
+    /// blink can be displayed at once. Look at [`normalize`] for details.
+    ///
+    /// [`normalize`]: Modifier::normalize
     pub struct Modifier : u16 {
         const BOLD = 0x1;
         const ITALIC = 0x2;
-        // TODO: Add more modifiers
+        const DIM = 0x4;
+        const UNDERLINED = 0x8;
+        const DOUBLE_UNDERLINED = 0x10;
+        const CURLY_UNDERLINED = 0x20;
+        const DOTTED_UNDERLINED = 0x40;
+        const DASHED_UNDERLINED = 0x80;
+        const SLOW_BLINK = 0x100;
+        const RAPID_BLINK = 0x200;
+        const REVERSED = 0x400;
+        const HIDDEN = 0x800;
+        const CROSSED_OUT = 0x1000;
+        const OVERLINED = 0x2000;
+    }
+}
+
+impl Modifier {
+    /// All kinds of underline. Only one of them can be set at once.
+    pub const ANY_UNDERLINE: Modifier = Modifier::UNDERLINED
+        .union(Modifier::DOUBLE_UNDERLINED)
+        .union(Modifier::CURLY_UNDERLINED)
+        .union(Modifier::DOTTED_UNDERLINED)
+        .union(Modifier::DASHED_UNDERLINED);
+
+    /// All kinds of blink. Only one of them can be set at once.
+    pub const ANY_BLINK: Modifier = Modifier::SLOW_BLINK.union(Modifier::RAPID_BLINK);
+
+    /// Groups of mutually exclusive modifiers.
+    const EXCLUSIVE: [Modifier; 2] = [Modifier::ANY_UNDERLINE, Modifier::ANY_BLINK];
+
+    /// Leaves only one modifier from every group of mutually exclusive modifiers (e.g.
+    /// [`ANY_UNDERLINE`]). The modifier with the highest bit wins, so more specific kinds are
+    /// preferred: `CURLY_UNDERLINED` over `UNDERLINED`, `RAPID_BLINK` over `SLOW_BLINK`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Modifier;
+    /// let modifier = Modifier::BOLD | Modifier::UNDERLINED | Modifier::CURLY_UNDERLINED;
+    /// assert_eq!(modifier.normalize(), Modifier::BOLD | Modifier::CURLY_UNDERLINED);
+    /// ```
+    ///
+    /// [`ANY_UNDERLINE`]: Modifier::ANY_UNDERLINE
+    #[must_use]
+    pub const fn normalize(self) -> Self {
+        let mut bits = self.bits();
+        let mut i = 0;
+        while i < Self::EXCLUSIVE.len() {
+            let group = bits & Self::EXCLUSIVE[i].bits();
+            if group != 0 {
+                let highest = 1 << (u16::BITS - 1 - group.leading_zeros());
+                bits = (bits & !group) | highest;
+            }
+            i += 1;
+        }
+        Self::from_bits_retain(bits)
+    }
+
+    /// Modifiers which can't be displayed together with `self`: other members of every group of
+    /// mutually exclusive modifiers `self` intersects.
+    const fn exclusive_with(self) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < Self::EXCLUSIVE.len() {
+            if self.intersects(Self::EXCLUSIVE[i]) {
+                bits |= Self::EXCLUSIVE[i].bits();
+            }
+            i += 1;
+        }
+        Self::from_bits_retain(bits & !self.bits())
     }
 }
 
@@ -64,10 +163,18 @@ impl Style {
     /// let style = Style::new();
     /// assert_eq!(style.fg, None);
     /// assert_eq!(style.bg, None);
+    /// assert_eq!(style.underline_color, None);
     /// assert_eq!(style.modifier, Modifier::empty());
+    /// assert_eq!(style.sub_modifier, Modifier::empty());
     /// ```
     pub const fn new() -> Self {
-        Self { bg: None, fg: None, modifier: Modifier::empty() }
+        Self {
+            bg: None,
+            fg: None,
+            underline_color: None,
+            modifier: Modifier::empty(),
+            sub_modifier: Modifier::empty(),
+        }
     }
 
     /// Set foreground color.
@@ -100,6 +207,23 @@ impl Style {
         self
     }
 
+    /// Set underline color. It is visible only with one of underline [`modifiers`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style = Style::new().underline_color(Color::Red).modifier(Modifier::CURLY_UNDERLINED);
+    /// assert_eq!(style.underline_color, Some(Color::Red));
+    /// ```
+    ///
+    /// [`modifiers`]: Modifier::ANY_UNDERLINE
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn underline_color(mut self, color: Color) -> Self {
+        self.underline_color = Some(color);
+        self
+    }
+
     /// Set modifiers.
     ///
     /// # Examples
@@ -115,8 +239,54 @@ impl Style {
         self
     }
 
+    /// Add modifiers. Unlike [`modifier`] it keeps already added modifiers and cancels removing
+    /// of `modifier`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style = Style::new().remove_modifier(Modifier::BOLD).add_modifier(Modifier::BOLD);
+    /// assert_eq!(style.modifier, Modifier::BOLD);
+    /// assert_eq!(style.sub_modifier, Modifier::empty());
+    /// ```
+    ///
+    /// [`modifier`]: Self::modifier
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn add_modifier(mut self, modifier: Modifier) -> Self {
+        self.sub_modifier.remove(modifier);
+        self.modifier = (self.modifier | modifier).normalize();
+        self
+    }
+
+    /// Remove modifiers. Removed modifiers are saved in [`sub_modifier`], so they're removed from
+    /// the lower style on [`merge`] too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let base = Style::new().modifier(Modifier::BOLD | Modifier::ITALIC);
+    /// let selection = Style::new().remove_modifier(Modifier::BOLD);
+    /// assert_eq!(base.merge(selection).modifier, Modifier::ITALIC);
+    /// ```
+    ///
+    /// [`sub_modifier`]: Self::sub_modifier
+    /// [`merge`]: Self::merge
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn remove_modifier(mut self, modifier: Modifier) -> Self {
+        self.modifier.remove(modifier);
+        self.sub_modifier.insert(modifier);
+        self
+    }
+
     /// Merge styles from `rhs` to `self`. All styles from `rhs` are applied to `self` if they're
-    /// not None. Modifiers are also merge.
+    /// not None. Modifiers are also merge: `rhs.sub_modifier` are removed from `self` and then
+    /// `rhs.modifier` are added. Mutually exclusive modifiers from `rhs` replace the modifiers of
+    /// the same group in `self` (e.g. `rhs` underline kind wins), the replaced ones are saved in
+    /// [`sub_modifier`](Self::sub_modifier) of the result.
+    ///
+    /// The same operation is available with `|` operator.
     ///
     /// # Examples
     ///
@@ -139,6 +309,22 @@ impl Style {
     /// let style1 = Style::new().modifier(Modifier::BOLD);
     /// let style2 = Style::new().modifier(Modifier::ITALIC);
     /// assert_eq!(style1.merge(style2), Style::new().modifier(Modifier::BOLD | Modifier::ITALIC));
+    /// ```
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style1 = Style::new().modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    /// let style2 = Style::new().modifier(Modifier::CURLY_UNDERLINED);
+    /// assert_eq!(style1.merge(style2).modifier, Modifier::BOLD | Modifier::CURLY_UNDERLINED);
+    /// ```
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let base = Style::new().fg(Color::Green).modifier(Modifier::BOLD);
+    /// let layer = Style::new().fg(Color::Reset) - Modifier::BOLD;
+    /// assert_eq!((base | layer).fg, Some(Color::Reset));
+    /// assert_eq!((base | layer).modifier, Modifier::empty());
+    /// ```
     #[must_use = "`Style` object is immutable. All mutation methods return new object"]
     pub fn merge(mut self, rhs: Style) -> Self {
         if let Some(fg) = rhs.fg {
@@ -149,18 +335,184 @@ impl Style {
             self.bg = Some(bg);
         }
 
-        self.modifier |= rhs.modifier;
+        if let Some(underline_color) = rhs.underline_color {
+            self.underline_color = Some(underline_color);
+        }
+
+        // Setting one modifier of an exclusive group removes the others, so they're recorded in
+        // `sub_modifier` too. This way the result removes them from lower styles as well and
+        // merge stays associative.
+        let lhs_modifier = self.modifier.normalize();
+        let rhs_modifier = rhs.modifier.normalize();
+        let rhs_sub_modifier = (rhs.sub_modifier | rhs_modifier.exclusive_with()) - rhs_modifier;
+
+        self.modifier = (lhs_modifier - rhs_sub_modifier) | rhs_modifier;
+        self.sub_modifier =
+            (self.sub_modifier | lhs_modifier.exclusive_with() | rhs_sub_modifier) - self.modifier;
         self
     }
 }
 
+impl BitOr for Style {
+    type Output = Style;
+
+    /// Merge styles, look at [`Style::merge`].
+    fn bitor(self, rhs: Style) -> Self::Output {
+        self.merge(rhs)
+    }
+}
+
+impl BitOrAssign for Style {
+    fn bitor_assign(&mut self, rhs: Style) {
+        *self = self.merge(rhs);
+    }
+}
+
+impl Sub<Modifier> for Style {
+    type Output = Style;
+
+    /// Remove modifiers, look at [`Style::remove_modifier`].
+    fn sub(self, rhs: Modifier) -> Self::Output {
+        self.remove_modifier(rhs)
+    }
+}
+
+impl SubAssign<Modifier> for Style {
+    fn sub_assign(&mut self, rhs: Modifier) {
+        *self = self.remove_modifier(rhs);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
 
     #[test]
     fn check_style_sizeof() {
         assert_eq!(std::mem::size_of::<Color>(), 4);
-        assert_eq!(std::mem::size_of::<Style>(), 10);
+        assert_eq!(std::mem::size_of::<Style>(), 16);
+    }
+
+    #[rstest]
+    #[case::plain(Modifier::BOLD | Modifier::ITALIC, Modifier::BOLD | Modifier::ITALIC)]
+    #[case::two_underlines(
+        Modifier::UNDERLINED | Modifier::DOTTED_UNDERLINED,
+        Modifier::DOTTED_UNDERLINED
+    )]
+    #[case::all_underlines(Modifier::ANY_UNDERLINE, Modifier::DASHED_UNDERLINED)]
+    #[case::both_groups(
+        Modifier::ANY_BLINK | Modifier::ANY_UNDERLINE | Modifier::DIM,
+        Modifier::RAPID_BLINK | Modifier::DASHED_UNDERLINED | Modifier::DIM
+    )]
+    fn normalize_modifier(#[case] modifier: Modifier, #[case] expected: Modifier) {
+        assert_eq!(modifier.normalize(), expected);
+    }
+
+    #[rstest]
+    #[case::add(Modifier::BOLD, Modifier::DIM, Modifier::BOLD | Modifier::DIM)]
+    #[case::replace_underline(
+        Modifier::UNDERLINED | Modifier::OVERLINED,
+        Modifier::DOUBLE_UNDERLINED,
+        Modifier::DOUBLE_UNDERLINED | Modifier::OVERLINED
+    )]
+    #[case::keep_underline(
+        Modifier::CURLY_UNDERLINED,
+        Modifier::SLOW_BLINK,
+        Modifier::CURLY_UNDERLINED | Modifier::SLOW_BLINK
+    )]
+    #[case::replace_blink(Modifier::RAPID_BLINK, Modifier::SLOW_BLINK, Modifier::SLOW_BLINK)]
+    fn merge_exclusive_modifiers(
+        #[case] lhs: Modifier,
+        #[case] rhs: Modifier,
+        #[case] expected: Modifier,
+    ) {
+        let style = Style::new().modifier(lhs).merge(Style::new().modifier(rhs));
+        assert_eq!(style.modifier, expected);
+    }
+
+    #[rstest]
+    #[case::inherit(Style::new().fg(Color::Red), Style::new(), Style::new().fg(Color::Red))]
+    #[case::reset(
+        Style::new().fg(Color::Red).bg(Color::Blue),
+        Style::new().bg(Color::Reset),
+        Style::new().fg(Color::Red).bg(Color::Reset)
+    )]
+    #[case::remove_modifier(
+        Style::new().modifier(Modifier::BOLD | Modifier::DIM),
+        Style::new() - Modifier::BOLD,
+        Style::new().modifier(Modifier::DIM) - Modifier::BOLD
+    )]
+    #[case::remove_underline_group(
+        Style::new().modifier(Modifier::CURLY_UNDERLINED),
+        Style::new() - Modifier::ANY_UNDERLINE,
+        Style::new() - Modifier::ANY_UNDERLINE
+    )]
+    #[case::add_cancels_remove(
+        Style::new() - Modifier::BOLD,
+        Style::new().modifier(Modifier::BOLD),
+        Style::new().modifier(Modifier::BOLD)
+    )]
+    #[case::remove_after_add(
+        Style::new().modifier(Modifier::BOLD),
+        Style::new() - Modifier::BOLD,
+        Style::new() - Modifier::BOLD
+    )]
+    fn merge_layers(#[case] lhs: Style, #[case] rhs: Style, #[case] expected: Style) {
+        assert_eq!(lhs | rhs, expected);
+    }
+
+    #[test]
+    fn merge_is_associative() {
+        let a = Style::new().fg(Color::Red).modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        let b = Style::new().bg(Color::Reset) - Modifier::BOLD;
+        let c = Style::new().modifier(Modifier::BOLD | Modifier::DOTTED_UNDERLINED);
+
+        assert_eq!((a | b) | c, a | (b | c));
+    }
+
+    #[test]
+    fn merge_is_associative_for_all_triples() {
+        let modifiers = [
+            Modifier::empty(),
+            Modifier::BOLD,
+            Modifier::UNDERLINED,
+            Modifier::CURLY_UNDERLINED,
+            Modifier::UNDERLINED | Modifier::CURLY_UNDERLINED,
+            Modifier::BOLD | Modifier::SLOW_BLINK,
+            Modifier::RAPID_BLINK | Modifier::DOTTED_UNDERLINED,
+        ];
+        let colors = [None, Some(Color::Red), Some(Color::Reset)];
+
+        let mut styles = Vec::new();
+        for (i, &modifier) in modifiers.iter().enumerate() {
+            for (j, &sub_modifier) in modifiers.iter().enumerate() {
+                styles.push(Style {
+                    fg: colors[(i + j) % colors.len()],
+                    bg: colors[i % colors.len()],
+                    underline_color: colors[j % colors.len()],
+                    modifier,
+                    sub_modifier,
+                });
+            }
+        }
+
+        for &a in &styles {
+            for &b in &styles {
+                for &c in &styles {
+                    assert_eq!((a | b) | c, a | (b | c), "{a:?} | {b:?} | {c:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merge_removes_replaced_underline() {
+        let a = Style::new().modifier(Modifier::UNDERLINED);
+        let b = Style::new().modifier(Modifier::CURLY_UNDERLINED);
+        let c = Style::new() - Modifier::CURLY_UNDERLINED;
+
+        assert_eq!(((a | b) | c).modifier, Modifier::empty());
+        assert_eq!((a | (b | c)).modifier, Modifier::empty());
     }
 }
@@ -0,0 +1,707 @@
+use crate::{Color, Style};
+
+/// `Palette` maps named [ANSI](https://en.wikipedia.org/wiki/ANSI_escape_code#Colors) colors to
+/// their real rgb values. Terminals are free to display named colors as they want, so all color
+/// math resolves named colors through a palette first.
+///
+/// Default palette is the xterm one.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{Color, Palette};
+/// let mut palette = Palette::default();
+/// palette.set(Color::Red, (200, 0, 0));
+/// assert_eq!(Color::Red.resolve(&palette), Color::Rgb(200, 0, 0));
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Palette {
+    colors: [(u8, u8, u8); 16],
+}
+
+/// Color in [HSL](https://en.wikipedia.org/wiki/HSL_and_HSV) representation.
+///
+/// `h` is hue in degrees `[0; 360)`, `s` is saturation and `l` is lightness in `[0; 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+/// Color in [OKLCH](https://bottosson.github.io/posts/oklab/) representation.
+///
+/// `l` is perceptual lightness in `[0; 1]`, `c` is chroma (usually in `[0; 0.4]`) and `h` is hue
+/// in degrees `[0; 360)`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Oklch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+/// Count of colors which can be displayed by a terminal.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub enum ColorDepth {
+    /// No colors at all.
+    Monochrome,
+    /// 16 named ANSI colors.
+    Ansi16,
+    /// 24-bit rgb colors.
+    #[default]
+    TrueColor,
+}
+
+impl Palette {
+    /// Default xterm palette.
+    pub const XTERM: Palette = Palette {
+        colors: [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ],
+    };
+
+    /// Creates palette from rgb values of 16 named colors in ANSI order: `Black`, `Red`, ...,
+    /// `BrightCyan`, `BrightWhite`.
+    pub const fn new(colors: [(u8, u8, u8); 16]) -> Self {
+        Self { colors }
+    }
+
+    /// Set rgb value for named `color`. Does nothing for [`Color::Rgb`] and [`Color::Reset`].
+    pub fn set(&mut self, color: Color, rgb: (u8, u8, u8)) {
+        if let Some(idx) = ansi_index(color) {
+            self.colors[idx] = rgb;
+        }
+    }
+
+    /// Get rgb value for `color`. Returns `None` for [`Color::Reset`] because the terminal
+    /// default color is unknown.
+    pub const fn get(&self, color: Color) -> Option<(u8, u8, u8)> {
+        match color {
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+            named => match ansi_index(named) {
+                Some(idx) => Some(self.colors[idx]),
+                None => None,
+            },
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::XTERM
+    }
+}
+
+pub(crate) const fn named_color(idx: usize) -> Option<Color> {
+    Some(match idx {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::White,
+        8 => Color::BrightBlack,
+        9 => Color::BrightRed,
+        10 => Color::BrightGreen,
+        11 => Color::BrightYellow,
+        12 => Color::BrightBlue,
+        13 => Color::BrightMagenta,
+        14 => Color::BrightCyan,
+        15 => Color::BrightWhite,
+        _ => return None,
+    })
+}
+
+pub(crate) const fn ansi_index(color: Color) -> Option<usize> {
+    Some(match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White => 7,
+        Color::BrightBlack => 8,
+        Color::BrightRed => 9,
+        Color::BrightGreen => 10,
+        Color::BrightYellow => 11,
+        Color::BrightBlue => 12,
+        Color::BrightMagenta => 13,
+        Color::BrightCyan => 14,
+        Color::BrightWhite => 15,
+        Color::Rgb(..) | Color::Reset => return None,
+    })
+}
+
+/// Color math. Methods without `_with` suffix resolve named colors through [`Palette::XTERM`],
+/// `_with` variants take the palette of the terminal.
+///
+/// [`Color::Reset`] is the default color of the terminal, so its rgb value is unknown.
+/// Conversions, luminance and contrast ratio return `None` for it, [`lighten`] and [`darken`]
+/// return it unchanged and [`blend`] returns the color with the bigger weight.
+///
+/// [`lighten`]: Color::lighten
+/// [`darken`]: Color::darken
+/// [`blend`]: Color::blend
+impl Color {
+    /// Converts `self` into [`Color::Rgb`] with specified `palette`. [`Color::Reset`] is
+    /// returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, Palette};
+    /// assert_eq!(Color::BrightWhite.resolve(&Palette::XTERM), Color::Rgb(255, 255, 255));
+    /// assert_eq!(Color::Reset.resolve(&Palette::XTERM), Color::Reset);
+    /// ```
+    #[must_use]
+    pub const fn resolve(self, palette: &Palette) -> Color {
+        match palette.get(self) {
+            Some((r, g, b)) => Color::Rgb(r, g, b),
+            None => self,
+        }
+    }
+
+    /// Mix `self` with `other`. `alpha` is a weight of `other` in `[0; 1]`, so `0.0` returns
+    /// `self` and `1.0` returns `other`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// let black = Color::Rgb(0, 0, 0);
+    /// let white = Color::Rgb(255, 255, 255);
+    /// assert_eq!(black.blend(white, 0.5), Color::Rgb(128, 128, 128));
+    /// assert_eq!(black.blend(Color::Reset, 0.7), Color::Reset);
+    /// ```
+    #[must_use]
+    pub fn blend(self, other: Color, alpha: f32) -> Color {
+        self.blend_with(other, alpha, &Palette::XTERM)
+    }
+
+    /// Same as [`blend`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`blend`]: Self::blend
+    #[must_use]
+    pub fn blend_with(self, other: Color, alpha: f32, palette: &Palette) -> Color {
+        let alpha = alpha.clamp(0.0, 1.0);
+        match (palette.get(self), palette.get(other)) {
+            (Some(a), Some(b)) => rgb_color(mix(a, b, alpha)),
+            _ if alpha < 0.5 => self,
+            _ => other,
+        }
+    }
+
+    /// Increase HSL lightness of `self` by `amount` in `[0; 1]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// assert_eq!(Color::Rgb(0, 0, 0).lighten(1.0), Color::Rgb(255, 255, 255));
+    /// ```
+    #[must_use]
+    pub fn lighten(self, amount: f32) -> Color {
+        self.lighten_with(amount, &Palette::XTERM)
+    }
+
+    /// Same as [`lighten`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`lighten`]: Self::lighten
+    #[must_use]
+    pub fn lighten_with(self, amount: f32, palette: &Palette) -> Color {
+        let Some(mut hsl) = self.to_hsl_with(palette) else {
+            return self;
+        };
+        hsl.l = (hsl.l + amount).clamp(0.0, 1.0);
+        Color::from_hsl(hsl)
+    }
+
+    /// Decrease HSL lightness of `self` by `amount` in `[0; 1]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// assert_eq!(Color::Rgb(255, 255, 255).darken(1.0), Color::Rgb(0, 0, 0));
+    /// ```
+    #[must_use]
+    pub fn darken(self, amount: f32) -> Color {
+        self.lighten(-amount)
+    }
+
+    /// Same as [`darken`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`darken`]: Self::darken
+    #[must_use]
+    pub fn darken_with(self, amount: f32, palette: &Palette) -> Color {
+        self.lighten_with(-amount, palette)
+    }
+
+    /// Converts `self` into [`Hsl`]. Returns `None` for [`Color::Reset`].
+    pub fn to_hsl(self) -> Option<Hsl> {
+        self.to_hsl_with(&Palette::XTERM)
+    }
+
+    /// Same as [`to_hsl`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`to_hsl`]: Self::to_hsl
+    pub fn to_hsl_with(self, palette: &Palette) -> Option<Hsl> {
+        let (r, g, b) = palette.get(self)?;
+        let (r, g, b) = (f32::from(r) / 255.0, f32::from(g) / 255.0, f32::from(b) / 255.0);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+
+        if d == 0.0 {
+            return Some(Hsl { h: 0.0, s: 0.0, l });
+        }
+
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+
+        Some(Hsl { h, s, l })
+    }
+
+    /// Creates [`Color::Rgb`] from [`Hsl`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, style::Hsl};
+    /// assert_eq!(Color::from_hsl(Hsl { h: 120.0, s: 1.0, l: 0.5 }), Color::Rgb(0, 255, 0));
+    /// ```
+    pub fn from_hsl(hsl: Hsl) -> Color {
+        let Hsl { h, s, l } = hsl;
+        let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        let h = h.rem_euclid(360.0);
+
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = l - c / 2.0;
+
+        let (r, g, b) = match h {
+            h if h < 60.0 => (c, x, 0.0),
+            h if h < 120.0 => (x, c, 0.0),
+            h if h < 180.0 => (0.0, c, x),
+            h if h < 240.0 => (0.0, x, c),
+            h if h < 300.0 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        Color::Rgb(to_u8((r + m) * 255.0), to_u8((g + m) * 255.0), to_u8((b + m) * 255.0))
+    }
+
+    /// Converts `self` into [`Oklch`]. Returns `None` for [`Color::Reset`].
+    pub fn to_oklch(self) -> Option<Oklch> {
+        self.to_oklch_with(&Palette::XTERM)
+    }
+
+    /// Same as [`to_oklch`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`to_oklch`]: Self::to_oklch
+    pub fn to_oklch_with(self, palette: &Palette) -> Option<Oklch> {
+        let (r, g, b) = palette.get(self)?;
+        let (r, g, b) = (to_linear(r), to_linear(g), to_linear(b));
+
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+        let ok_l = 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s;
+        let ok_a = 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s;
+        let ok_b = 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s;
+
+        let c = (ok_a * ok_a + ok_b * ok_b).sqrt();
+        let h = if c < 1e-4 { 0.0 } else { ok_b.atan2(ok_a).to_degrees().rem_euclid(360.0) };
+
+        Some(Oklch { l: ok_l, c, h })
+    }
+
+    /// Creates [`Color::Rgb`] from [`Oklch`]. Colors out of sRGB gamut are clamped.
+    pub fn from_oklch(oklch: Oklch) -> Color {
+        let Oklch { l, c, h } = oklch;
+        let (ok_a, ok_b) = (c * h.to_radians().cos(), c * h.to_radians().sin());
+
+        let l_ = l + 0.396_337_78 * ok_a + 0.215_803_76 * ok_b;
+        let m_ = l - 0.105_561_346 * ok_a - 0.063_854_17 * ok_b;
+        let s_ = l - 0.089_484_18 * ok_a - 1.291_485_5 * ok_b;
+
+        let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+
+        let r = 4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s;
+        let g = -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s;
+        let b = -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s;
+
+        Color::Rgb(from_linear(r), from_linear(g), from_linear(b))
+    }
+
+    /// Converts `self` into a color which can be displayed with specified `depth`. Rgb colors are
+    /// replaced with the nearest named color for [`ColorDepth::Ansi16`], and all colors are
+    /// removed for [`ColorDepth::Monochrome`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, style::ColorDepth};
+    /// assert_eq!(Color::Rgb(250, 5, 5).downsample(ColorDepth::Ansi16), Some(Color::BrightRed));
+    /// assert_eq!(Color::Red.downsample(ColorDepth::TrueColor), Some(Color::Red));
+    /// assert_eq!(Color::Red.downsample(ColorDepth::Monochrome), None);
+    /// ```
+    pub fn downsample(self, depth: ColorDepth) -> Option<Color> {
+        match (depth, self) {
+            (ColorDepth::Monochrome, _) => None,
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => {
+                let distance = |(r2, g2, b2): (u8, u8, u8)| {
+                    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+                    d(r, r2) + d(g, g2) + d(b, b2)
+                };
+                Palette::XTERM
+                    .colors
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, rgb)| distance(**rgb))
+                    .and_then(|(idx, _)| named_color(idx))
+            }
+            (ColorDepth::Ansi16 | ColorDepth::TrueColor, color) => Some(color),
+        }
+    }
+
+    /// Returns relative luminance of `self` as defined by
+    /// [WCAG](https://www.w3.org/TR/WCAG21/#dfn-relative-luminance) in `[0; 1]`. Returns `None`
+    /// for [`Color::Reset`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// assert_eq!(Color::Rgb(0, 0, 0).luminance(), Some(0.0));
+    /// assert_eq!(Color::Rgb(255, 255, 255).luminance(), Some(1.0));
+    /// assert_eq!(Color::Reset.luminance(), None);
+    /// ```
+    pub fn luminance(self) -> Option<f32> {
+        self.luminance_with(&Palette::XTERM)
+    }
+
+    /// Same as [`luminance`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`luminance`]: Self::luminance
+    pub fn luminance_with(self, palette: &Palette) -> Option<f32> {
+        palette.get(self).map(luminance)
+    }
+
+    /// Returns contrast ratio between `self` and `other` as defined by
+    /// [WCAG](https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio) in `[1; 21]`. Returns `None` if
+    /// any of colors is [`Color::Reset`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// let ratio = Color::Rgb(0, 0, 0).contrast_ratio(Color::Rgb(255, 255, 255)).unwrap();
+    /// assert!((ratio - 21.0).abs() < 1e-4);
+    /// ```
+    pub fn contrast_ratio(self, other: Color) -> Option<f32> {
+        self.contrast_ratio_with(other, &Palette::XTERM)
+    }
+
+    /// Same as [`contrast_ratio`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`contrast_ratio`]: Self::contrast_ratio
+    pub fn contrast_ratio_with(self, other: Color, palette: &Palette) -> Option<f32> {
+        Some(contrast_ratio(palette.get(self)?, palette.get(other)?))
+    }
+}
+
+impl Style {
+    /// Converts all colors of `self` with [`Color::downsample`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{style::ColorDepth, *};
+    /// let style = Style::new().fg(Color::Rgb(0, 0, 0)).underline_color(Color::Rgb(0, 250, 0));
+    /// assert_eq!(
+    ///     style.downsample(ColorDepth::Ansi16),
+    ///     Style::new().fg(Color::Black).underline_color(Color::BrightGreen)
+    /// );
+    /// ```
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn downsample(mut self, depth: ColorDepth) -> Self {
+        self.fg = self.fg.and_then(|c| c.downsample(depth));
+        self.bg = self.bg.and_then(|c| c.downsample(depth));
+        self.underline_color = self.underline_color.and_then(|c| c.downsample(depth));
+        self
+    }
+
+    /// Adjust `fg` color to have at least `min_ratio` contrast with `bg` color. `fg` is moved
+    /// toward white or black, whichever needs smaller change. Named colors are resolved with
+    /// [`Palette::XTERM`], look at [`ensure_contrast_with`] for another palette.
+    ///
+    /// Style is returned unchanged if `fg` or `bg` is not set or is [`Color::Reset`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style = Style::new().fg(Color::Rgb(90, 90, 90)).bg(Color::Rgb(70, 70, 70));
+    /// let style = style.ensure_contrast(4.5);
+    /// assert!(style.fg.unwrap().contrast_ratio(style.bg.unwrap()) >= Some(4.5));
+    /// ```
+    ///
+    /// [`ensure_contrast_with`]: Self::ensure_contrast_with
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn ensure_contrast(self, min_ratio: f32) -> Self {
+        self.ensure_contrast_with(min_ratio, &Palette::XTERM)
+    }
+
+    /// Same as [`ensure_contrast`] but named colors are resolved with specified `palette`.
+    ///
+    /// [`ensure_contrast`]: Self::ensure_contrast
+    #[must_use = "`Style` object is immutable. All mutation methods return new object"]
+    pub fn ensure_contrast_with(mut self, min_ratio: f32, palette: &Palette) -> Self {
+        let (Some(fg), Some(bg)) = (self.fg, self.bg) else {
+            return self;
+        };
+        // Rgb value of `Color::Reset` is unknown
+        let (Some(fg), Some(bg)) = (palette.get(fg), palette.get(bg)) else {
+            return self;
+        };
+        if contrast_ratio(fg, bg) >= min_ratio {
+            return self;
+        }
+
+        let white = (255, 255, 255);
+        let black = (0, 0, 0);
+
+        let candidates = [white, black].map(|target| {
+            if contrast_ratio(target, bg) < min_ratio {
+                return None;
+            }
+
+            // Contrast changes monotonically while moving toward target, so search the
+            // smallest suitable step.
+            let (mut lo, mut hi) = (0.0f32, 1.0f32);
+            for _ in 0..16 {
+                let mid = (lo + hi) / 2.0;
+                if contrast_ratio(mix(fg, target, mid), bg) >= min_ratio {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            Some((hi, mix(fg, target, hi)))
+        });
+
+        self.fg = Some(rgb_color(match candidates {
+            [Some((a, c1)), Some((b, c2))] => {
+                if a <= b {
+                    c1
+                } else {
+                    c2
+                }
+            }
+            [Some((_, c)), None] | [None, Some((_, c))] => c,
+            // Unreachable ratio, so choose the best one
+            [None, None] => {
+                if contrast_ratio(white, bg) >= contrast_ratio(black, bg) {
+                    white
+                } else {
+                    black
+                }
+            }
+        }));
+
+        self
+    }
+}
+
+fn rgb_color((r, g, b): (u8, u8, u8)) -> Color {
+    Color::Rgb(r, g, b)
+}
+
+/// Mixes rgb values `a` and `b`, `alpha` is a weight of `b`.
+fn mix(a: (u8, u8, u8), b: (u8, u8, u8), alpha: f32) -> (u8, u8, u8) {
+    let mix = |a: u8, b: u8| to_u8(f32::from(a) + (f32::from(b) - f32::from(a)) * alpha);
+    (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    0.2126 * to_linear(r) + 0.7152 * to_linear(g) + 0.0722 * to_linear(b)
+}
+
+fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    let (l1, l2) = (luminance(a), luminance(b));
+    (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+}
+
+fn to_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+fn to_linear(c: u8) -> f32 {
+    let c = f32::from(c) / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    to_u8(c * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::named(Color::Red, Color::Rgb(205, 0, 0))]
+    #[case::bright(Color::BrightBlue, Color::Rgb(92, 92, 255))]
+    #[case::rgb(Color::Rgb(1, 2, 3), Color::Rgb(1, 2, 3))]
+    #[case::reset(Color::Reset, Color::Reset)]
+    fn resolve_with_xterm_palette(#[case] color: Color, #[case] expected: Color) {
+        assert_eq!(color.resolve(&Palette::XTERM), expected);
+    }
+
+    #[test]
+    fn resolve_with_custom_palette() {
+        let mut palette = Palette::default();
+        palette.set(Color::Black, (10, 10, 10));
+        palette.set(Color::Rgb(0, 0, 0), (20, 20, 20));
+
+        assert_eq!(Color::Black.resolve(&palette), Color::Rgb(10, 10, 10));
+        assert_eq!(Color::Rgb(0, 0, 0).resolve(&palette), Color::Rgb(0, 0, 0));
+    }
+
+    #[rstest]
+    #[case::start(0.0, Color::Rgb(10, 20, 30))]
+    #[case::end(1.0, Color::Rgb(110, 220, 130))]
+    #[case::quarter(0.25, Color::Rgb(35, 70, 55))]
+    #[case::clamped(2.0, Color::Rgb(110, 220, 130))]
+    fn blend_colors(#[case] alpha: f32, #[case] expected: Color) {
+        assert_eq!(Color::Rgb(10, 20, 30).blend(Color::Rgb(110, 220, 130), alpha), expected);
+    }
+
+    #[rstest]
+    #[case::black(Color::Rgb(0, 0, 0))]
+    #[case::white(Color::Rgb(255, 255, 255))]
+    #[case::red(Color::Rgb(255, 0, 0))]
+    #[case::teal(Color::Rgb(0, 128, 128))]
+    #[case::random(Color::Rgb(17, 200, 93))]
+    fn hsl_roundtrip(#[case] color: Color) {
+        assert_eq!(color.to_hsl().map(Color::from_hsl), Some(color));
+    }
+
+    #[rstest]
+    #[case::black(Color::Rgb(0, 0, 0))]
+    #[case::white(Color::Rgb(255, 255, 255))]
+    #[case::red(Color::Rgb(255, 0, 0))]
+    #[case::teal(Color::Rgb(0, 128, 128))]
+    #[case::random(Color::Rgb(17, 200, 93))]
+    fn oklch_roundtrip(#[case] color: Color) {
+        assert_eq!(color.to_oklch().map(Color::from_oklch), Some(color));
+    }
+
+    #[rstest]
+    #[case::true_color(ColorDepth::TrueColor, Color::Rgb(1, 2, 3), Some(Color::Rgb(1, 2, 3)))]
+    #[case::ansi_named(ColorDepth::Ansi16, Color::Cyan, Some(Color::Cyan))]
+    #[case::ansi_black(ColorDepth::Ansi16, Color::Rgb(20, 10, 10), Some(Color::Black))]
+    #[case::ansi_gray(ColorDepth::Ansi16, Color::Rgb(120, 130, 125), Some(Color::BrightBlack))]
+    #[case::ansi_blue(ColorDepth::Ansi16, Color::Rgb(10, 0, 220), Some(Color::Blue))]
+    #[case::ansi_reset(ColorDepth::Ansi16, Color::Reset, Some(Color::Reset))]
+    #[case::monochrome(ColorDepth::Monochrome, Color::Rgb(1, 2, 3), None)]
+    fn downsample_color(
+        #[case] depth: ColorDepth,
+        #[case] color: Color,
+        #[case] expected: Option<Color>,
+    ) {
+        assert_eq!(color.downsample(depth), expected);
+    }
+
+    #[test]
+    fn lighten_and_darken() {
+        let color = Color::from_hsl(Hsl { h: 200.0, s: 0.5, l: 0.5 });
+        assert!((color.lighten(0.2).to_hsl().unwrap().l - 0.7).abs() < 0.01);
+        assert!((color.darken(0.2).to_hsl().unwrap().l - 0.3).abs() < 0.01);
+        assert_eq!(Color::Reset.lighten(0.2), Color::Reset);
+    }
+
+    #[rstest]
+    #[case::start(0.0, Color::Rgb(10, 20, 30))]
+    #[case::middle(0.5, Color::Reset)]
+    #[case::end(1.0, Color::Reset)]
+    fn blend_with_reset(#[case] alpha: f32, #[case] expected: Color) {
+        assert_eq!(Color::Rgb(10, 20, 30).blend(Color::Reset, alpha), expected);
+    }
+
+    #[test]
+    fn color_math_with_custom_palette() {
+        let palette = Palette::new([(255, 255, 255); 16]);
+        assert_eq!(Color::Black.blend_with(Color::Red, 0.5, &palette), Color::Rgb(255, 255, 255));
+        assert_eq!(Color::Black.darken_with(1.0, &palette), Color::Rgb(0, 0, 0));
+        assert_eq!(Color::Black.luminance_with(&palette), Some(1.0));
+        assert_eq!(Color::Black.to_hsl_with(&palette).map(|hsl| hsl.l), Some(1.0));
+        assert_eq!(Color::Black.contrast_ratio_with(Color::White, &palette), Some(1.0));
+        assert_eq!(Color::Black.contrast_ratio_with(Color::Reset, &palette), None);
+    }
+
+    #[test]
+    fn contrast_ratio_is_symmetric() {
+        let (a, b) = (Color::Rgb(30, 60, 90), Color::Yellow);
+        assert_eq!(a.contrast_ratio(b), b.contrast_ratio(a));
+        assert_eq!(a.contrast_ratio(a), Some(1.0));
+    }
+
+    #[rstest]
+    #[case::dark_bg(Color::Rgb(40, 40, 40), Color::Rgb(60, 60, 60))]
+    #[case::light_bg(Color::Rgb(230, 230, 230), Color::Rgb(200, 200, 200))]
+    #[case::named(Color::Blue, Color::Black)]
+    fn ensure_contrast_reaches_ratio(#[case] bg: Color, #[case] fg: Color) {
+        let style = Style::new().fg(fg).bg(bg).ensure_contrast(4.5);
+        assert!(style.fg.unwrap().contrast_ratio(bg) >= Some(4.5));
+        assert_eq!(style.bg, Some(bg));
+    }
+
+    #[test]
+    fn ensure_contrast_keeps_good_style() {
+        let style = Style::new().fg(Color::White).bg(Color::Black);
+        assert_eq!(style.ensure_contrast(4.5), style);
+        assert_eq!(
+            Style::new().fg(Color::Black).ensure_contrast(4.5),
+            Style::new().fg(Color::Black)
+        );
+        let style = Style::new().fg(Color::Black).bg(Color::Reset);
+        assert_eq!(style.ensure_contrast(4.5), style);
+    }
+}
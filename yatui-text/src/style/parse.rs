@@ -0,0 +1,250 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::{style::ansi_index, Color, Modifier, Style};
+
+/// Names of named colors in ANSI order.
+const COLOR_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// An error which can be returned when parsing [`Color`], [`Modifier`] or [`Style`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl FromStr for Color {
+    type Err = ParseError;
+
+    /// Parses color from lowercase name (`red`, `bright_red`, `reset`) or hex rgb value
+    /// (`#ff0000`, `#f00`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Color;
+    /// assert_eq!("bright_red".parse(), Ok(Color::BrightRed));
+    /// assert_eq!("#ff8000".parse(), Ok(Color::Rgb(255, 128, 0)));
+    /// assert_eq!("#f80".parse(), Ok(Color::Rgb(255, 136, 0)));
+    /// assert!("orange".parse::<Color>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix('#') {
+            return parse_hex(hex).ok_or_else(|| ParseError::new(format!("invalid color `{s}`")));
+        }
+
+        if s == "reset" {
+            return Ok(Color::Reset);
+        }
+
+        COLOR_NAMES
+            .iter()
+            .position(|name| *name == s)
+            .and_then(crate::style::named_color)
+            .ok_or_else(|| ParseError::new(format!("unknown color `{s}`")))
+    }
+}
+
+impl Display for Color {
+    /// Writes color in the format accepted by [`FromStr`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self, ansi_index(*self)) {
+            (Color::Rgb(r, g, b), _) => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+            (Color::Reset, _) => f.write_str("reset"),
+            (_, Some(idx)) => f.write_str(COLOR_NAMES[idx]),
+            (_, None) => unreachable!("All named colors have ANSI index"),
+        }
+    }
+}
+
+/// Parses `rrggbb` or `rgb` hex color.
+pub(crate) fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..(i + 1) * len], 16);
+    match hex.len() {
+        6 => Some(Color::Rgb(channel(0, 2).ok()?, channel(1, 2).ok()?, channel(2, 2).ok()?)),
+        3 => {
+            let short = |i| channel(i, 1).map(|c| c * 17);
+            Some(Color::Rgb(short(0).ok()?, short(1).ok()?, short(2).ok()?))
+        }
+        _ => None,
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = ParseError;
+
+    /// Parses whitespace separated lowercase modifier names, the same as [`Display`] writes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Modifier;
+    /// assert_eq!("bold  italic".parse(), Ok(Modifier::BOLD | Modifier::ITALIC));
+    /// assert_eq!("".parse(), Ok(Modifier::empty()));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_whitespace().try_fold(Modifier::empty(), |modifier, name| {
+            parse_modifier(name).map(|m| modifier | m)
+        })
+    }
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier, ParseError> {
+    if name.bytes().any(|b| b.is_ascii_uppercase()) {
+        return Err(ParseError::new(format!("unknown modifier `{name}`")));
+    }
+
+    Modifier::from_name(&name.to_ascii_uppercase())
+        .ok_or_else(|| ParseError::new(format!("unknown modifier `{name}`")))
+}
+
+impl FromStr for Style {
+    type Err = ParseError;
+
+    /// Parses style from whitespace separated tokens:
+    ///
+    /// * `fg=<color>`, `bg=<color>`, `underline=<color>` set colors, look at [`Color`] for the
+    ///   format of colors.
+    /// * `<modifier>` adds modifier, e.g. `bold`.
+    /// * `-<modifier>` removes modifier, e.g. `-bold`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::*;
+    /// let style: Style = "fg=red bg=#000000 bold -italic".parse().unwrap();
+    /// assert_eq!(
+    ///     style,
+    ///     Style::new().fg(Color::Red).bg(Color::Rgb(0, 0, 0)).modifier(Modifier::BOLD)
+    ///         - Modifier::ITALIC
+    /// );
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_style_with(s, str::parse)
+    }
+}
+
+/// Parses [`Style`] like its [`FromStr`] implementation but colors are parsed with `color`.
+pub(crate) fn parse_style_with(
+    s: &str,
+    mut color: impl FnMut(&str) -> Result<Color, ParseError>,
+) -> Result<Style, ParseError> {
+    s.split_whitespace().try_fold(Style::new(), |style, token| {
+        Ok(match token.split_once('=') {
+            Some(("fg", value)) => style.fg(color(value)?),
+            Some(("bg", value)) => style.bg(color(value)?),
+            Some(("underline", value)) => style.underline_color(color(value)?),
+            Some((key, _)) => return Err(ParseError::new(format!("unknown attribute `{key}`"))),
+            None => match token.strip_prefix('-') {
+                Some(name) => style.remove_modifier(parse_modifier(name)?),
+                None => style.add_modifier(parse_modifier(token)?),
+            },
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::named("black", Color::Black)]
+    #[case::bright("bright_white", Color::BrightWhite)]
+    #[case::reset("reset", Color::Reset)]
+    #[case::hex("#0a0B0c", Color::Rgb(10, 11, 12))]
+    #[case::short_hex("#fff", Color::Rgb(255, 255, 255))]
+    fn parse_color(#[case] s: &str, #[case] expected: Color) {
+        assert_eq!(s.parse(), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::unknown("orange")]
+    #[case::uppercase("Red")]
+    #[case::empty("")]
+    #[case::long_hex("#1234567")]
+    #[case::not_hex("#gggggg")]
+    #[case::multibyte_hex("#ффф")]
+    fn parse_invalid_color(#[case] s: &str) {
+        assert!(s.parse::<Color>().is_err());
+    }
+
+    #[rstest]
+    #[case::named(Color::BrightMagenta)]
+    #[case::rgb(Color::Rgb(1, 22, 255))]
+    #[case::reset(Color::Reset)]
+    fn color_roundtrip(#[case] color: Color) {
+        assert_eq!(color.to_string().parse(), Ok(color));
+    }
+
+    #[test]
+    fn modifier_roundtrip() {
+        let modifier = Modifier::all().normalize();
+        assert_eq!(modifier.to_string().parse(), Ok(modifier));
+    }
+
+    #[rstest]
+    #[case::empty("", Style::new())]
+    #[case::colors(
+        "fg=red bg=reset underline=#010203",
+        Style::new().fg(Color::Red).bg(Color::Reset).underline_color(Color::Rgb(1, 2, 3))
+    )]
+    #[case::modifiers(
+        "bold  dim\t-italic",
+        Style::new().modifier(Modifier::BOLD | Modifier::DIM) - Modifier::ITALIC
+    )]
+    #[case::add_after_remove("-bold bold", Style::new().modifier(Modifier::BOLD))]
+    #[case::exclusive(
+        "underlined curly_underlined",
+        Style::new().modifier(Modifier::CURLY_UNDERLINED)
+    )]
+    fn parse_style(#[case] s: &str, #[case] expected: Style) {
+        assert_eq!(s.parse(), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::unknown_modifier("bold shiny")]
+    #[case::unknown_attribute("color=red")]
+    #[case::invalid_color("fg=#12")]
+    #[case::uppercase_modifier("BOLD")]
+    fn parse_invalid_style(#[case] s: &str) {
+        assert!(s.parse::<Style>().is_err());
+    }
+}
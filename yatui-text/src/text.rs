@@ -1,4 +1,23 @@
-use crate::{raw::Raw, Mask};
+use std::{
+    any::Any,
+    fmt::Debug,
+    iter::FusedIterator,
+    ops::{Bound, Range, RangeBounds},
+    str::SplitInclusive,
+    sync::Arc,
+    vec,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    link::Links,
+    mask::{Attr, Bounded, BoundsError, Mask, RangeAttrs},
+    raw::{self, Raw},
+    unit::{ByteIdx, Col, GraphemeIdx, Snap, TextUnit, Unit},
+    Error, IdxRange, Style,
+};
 
 /// [`Text`] is common structure for representing strings in terminal.
 ///
@@ -6,53 +25,1233 @@ use crate::{raw::Raw, Mask};
 /// with `grapheme` instead of `character`.
 ///
 /// Also `Text` contains [`Mask`]. This mask apply specified styles for all graphemes in this
-/// `Text`. By default, applied mask have [`default`] empty styles. All methods for modifying
-/// graphemes in this `Text` do not touch [`Mask`]. It is user responsibility to update mask for
-/// their purposes.
+/// `Text`. By default, applied mask have [`default`] empty styles. [`modify`] does not touch
+/// [`Mask`], it is user responsibility to update mask for their purposes. [`splice`] and
+/// [`insert`] move styles of graphemes after the edit together with graphemes.
+///
+/// Graphemes can be attached to [`hyperlinks`] with [`Links`] and to any other attributes with
+/// [`layers`] in the same way.
+///
+/// `Text<'a>` can borrow any `&'a str`, e.g. a part of a buffer which is styled for one frame,
+/// without copying it. The string is copied on the first modification or by [`into_owned`].
+///
+/// `Text` is `Send` and `Sync`, so it can be shared with render or search threads. Attributes are
+/// shared between clones and copied on the first modification. The string is shared too if it is
+/// `&'static str` or after [`share`], so cloning a large `Text` is cheap.
 ///
 /// [`default`]: crate::style::Style::default
-#[derive(Debug, Default)]
-pub struct Text {
-    raw: Raw,
+/// [`hyperlinks`]: crate::link::Hyperlink
+/// [`modify`]: Self::modify
+/// [`splice`]: Self::splice
+/// [`insert`]: Self::insert
+/// [`layers`]: Self::layer_mut
+/// [`share`]: Self::share
+/// [`into_owned`]: Self::into_owned
+#[derive(Debug, Default, Clone)]
+pub struct Text<'a> {
+    raw: Raw<'a>,
     mask: Mask,
+    links: Links,
+    layers: Vec<Box<dyn Layer>>,
+}
+
+/// An iterator over ranges of graphemes which should be repainted after [`Text`] was changed.
+///
+/// The iterator element type is ([`IdxRange`], Option<&'a [`Style`]>, &'a [`Style`]).
+///
+/// This struct is created by the [`diff`] method on [`Text`]. See its documentation for more.
+///
+/// [`diff`]: Text::diff
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Diff<'a> {
+    inner: vec::IntoIter<(IdxRange, Option<&'a Style>, &'a Style)>,
+}
+
+/// An iterator over lines of [`Text`].
+///
+/// The iterator element type is ([`IdxRange`], &'a [`str`]).
+///
+/// This struct is created by the [`lines`] method on [`Text`]. See its documentation for more.
+///
+/// [`lines`]: Text::lines
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Lines<'a> {
+    inner: SplitInclusive<'a, char>,
+    /// Index of the first grapheme of the next line.
+    start: usize,
+}
+
+/// Extra attribute layer of [`Text`] with erased type of attributes.
+trait Layer: Debug + Send + Sync {
+    fn splice_at(&mut self, start: usize, removed: usize, inserted: usize);
+
+    fn clone_box(&self) -> Box<dyn Layer>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Attr + Debug + Send + Sync + 'static> Layer for RangeAttrs<T> {
+    fn splice_at(&mut self, start: usize, removed: usize, inserted: usize) {
+        RangeAttrs::splice_at(self, start, removed, inserted);
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
 
-impl Text {
+impl Clone for Box<dyn Layer> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl<'a> Text<'a> {
     /// Create empty [`Text`] with empty [`Mask`].
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Returns string slice of this [`Text`].
+    pub fn as_str(&self) -> &str {
+        self.raw.as_ref()
+    }
+
+    /// Returns [`Mask`] applied to this [`Text`].
+    pub fn mask(&self) -> &Mask {
+        &self.mask
+    }
+
+    /// Returns mutable reference to [`Mask`] applied to this [`Text`].
+    pub fn mask_mut(&mut self) -> &mut Mask {
+        &mut self.mask
+    }
+
+    /// Returns [`Links`] attached to this [`Text`].
+    pub fn links(&self) -> &Links {
+        &self.links
+    }
+
+    /// Returns count of graphemes in this [`Text`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Text;
+    /// assert_eq!(Text::from("y\u{0301}es").grapheme_count(), 3);
+    /// ```
+    pub fn grapheme_count(&self) -> usize {
+        self.raw.graphemes()
+    }
+
+    /// Returns width of the widest line of this [`Text`] in terminal columns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Text;
+    /// assert_eq!(Text::from("ab\nすしc").width(), 5);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.raw.width()
+    }
+
+    /// Returns count of lines of this [`Text`]. Line delimiter after the last line does not
+    /// create an empty line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Text;
+    /// assert_eq!(Text::from("a\nb\r\nc\n").height(), 3);
+    /// ```
+    pub fn height(&self) -> usize {
+        self.raw.height()
+    }
+
+    /// Returns width of line `n` (starting from 0) in terminal columns or `None` if there is no
+    /// such line. Widths of lines are cached and edits with [`splice`], [`insert`] or
+    /// [`replace_range`] measure again only edited lines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Text;
+    /// let mut text = Text::from("ab\nすしc");
+    /// assert_eq!(text.line_width(1), Some(5));
+    ///
+    /// text.insert(1, "cd");
+    /// assert_eq!(text.line_width(0), Some(4));
+    /// assert_eq!(text.line_width(2), None);
+    /// ```
+    ///
+    /// [`splice`]: Self::splice
+    /// [`insert`]: Self::insert
+    /// [`replace_range`]: Self::replace_range
+    pub fn line_width(&self, n: usize) -> Option<usize> {
+        self.raw.line_width(n)
+    }
+
+    /// Gets an iterator over lines of this [`Text`]. Lines are split in the same way as in
+    /// [`height`].
+    ///
+    /// The iterator element type is ([`IdxRange`], &[`str`]): graphemes of the line and its
+    /// content without line delimiter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{IdxRange, Text};
+    /// let text = Text::from("ab\r\n\nc");
+    /// let lines: Vec<_> = text.lines().collect();
+    /// assert_eq!(
+    ///     lines,
+    ///     vec![(IdxRange::new(0, 1), "ab"), (IdxRange::new(3, 2), ""), (IdxRange::new(4, 4), "c")]
+    /// );
+    /// ```
+    ///
+    /// [`height`]: Self::height
+    pub fn lines(&self) -> Lines<'_> {
+        Lines { inner: self.as_str().split_inclusive('\n'), start: 0 }
+    }
+
+    /// Converts this [`Text`] into [`Text`] which owns its string. Borrowed string is copied,
+    /// attributes are moved. Strings up to 23 bytes are copied inline without heap allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, Style, Text};
+    /// fn highlight(buffer: &str) -> Text<'static> {
+    ///     let mut text = Text::from(buffer);
+    ///     text.mask_mut().add(0..2, Style::new().fg(Color::Blue));
+    ///     text.into_owned()
+    /// }
+    ///
+    /// let text = highlight(&String::from("fn main() {}"));
+    /// assert_eq!(text.as_str(), "fn main() {}");
+    /// ```
+    pub fn into_owned(self) -> Text<'static> {
+        Text { raw: self.raw.into_owned(), mask: self.mask, links: self.links, layers: self.layers }
+    }
+
+    /// Converts index `idx` into another [unit](crate::unit). Index equal to count of units is
+    /// the end of the text.
+    ///
+    /// Returns `None` if `idx` is out of bounds or there is no unit `T` which starts at the same
+    /// position, e.g. byte in the middle of grapheme can not be converted into grapheme index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{ByteIdx, GraphemeIdx, Utf16Idx}, Text};
+    /// let text = Text::from("y\u{0301}es 𝄞!");
+    /// assert_eq!(text.convert(GraphemeIdx(4)), Some(ByteIdx(6)));
+    /// assert_eq!(text.convert(ByteIdx(10)), Some(GraphemeIdx(5)));
+    /// assert_eq!(text.convert::<_, Utf16Idx>(GraphemeIdx(5)), Some(Utf16Idx(7)));
+    ///
+    /// // Combining acute accent is not a start of grapheme
+    /// assert_eq!(text.convert::<_, GraphemeIdx>(ByteIdx(1)), None);
+    /// ```
+    pub fn convert<F: TextUnit, T: TextUnit>(&self, idx: F) -> Option<T> {
+        self.try_convert(idx).ok()
+    }
+
+    /// Checked version of [`convert`] which returns [`Error::IndexOutOfBounds`] if `idx` is out
+    /// of bounds and [`Error::NotGraphemeBoundary`] if there is no unit `T` at `idx`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{ByteIdx, GraphemeIdx}, Error, Text};
+    /// let text = Text::from("y\u{0301}es");
+    /// assert_eq!(text.try_convert(ByteIdx(3)), Ok(GraphemeIdx(1)));
+    /// assert_eq!(
+    ///     text.try_convert::<_, GraphemeIdx>(ByteIdx(1)),
+    ///     Err(Error::NotGraphemeBoundary { offset: 1 })
+    /// );
+    /// assert_eq!(
+    ///     text.try_convert::<_, ByteIdx>(GraphemeIdx(4)),
+    ///     Err(Error::IndexOutOfBounds { idx: 4, len: 3 })
+    /// );
+    /// ```
+    ///
+    /// [`convert`]: Self::convert
+    pub fn try_convert<F: TextUnit, T: TextUnit>(&self, idx: F) -> Result<T, Error> {
+        let idx = idx.into();
+        let byte = Snap::Error.apply(idx, self.units::<F>(), |idx| self.unit_byte::<F>(idx))?;
+        let to = self.byte_unit::<T>(byte, Snap::Error);
+        to.map(T::from).map_err(|_| Error::NotGraphemeBoundary { offset: idx })
+    }
+
+    /// Converts range of any [unit](crate::unit) into range of graphemes, e.g. to apply styles
+    /// from regex matches or LSP highlights to the [`Mask`].
+    ///
+    /// Offsets which land inside a grapheme cluster (or inside a char) are moved according to
+    /// `snap`. Policy applies to both ends of the range, so [`Snap::Left`] includes partially
+    /// covered grapheme at the start and excludes it at the end, and [`Snap::Right`] does
+    /// the opposite. Unbounded end means the end of the text.
+    ///
+    /// Returns `None` if range is out of bounds or snapping fails with [`Snap::Error`], see
+    /// [`try_to_graphemes`] for the reason of failure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{ByteIdx, Snap, Utf16Idx}, IdxRange, Text};
+    /// let text = Text::from("y\u{0301}es 𝄞!");
+    /// // "es" found by regex
+    /// let found = ByteIdx(3)..ByteIdx(5);
+    /// assert_eq!(text.to_graphemes(found, Snap::Error), Some(IdxRange::new(1, 2)));
+    /// // "!" highlighted by LSP
+    /// let highlight = Utf16Idx(7)..=Utf16Idx(7);
+    /// assert_eq!(text.to_graphemes(highlight, Snap::Error), Some(IdxRange::new(5, 5)));
+    ///
+    /// // Combining accent only
+    /// let accent = ByteIdx(1)..ByteIdx(3);
+    /// assert_eq!(text.to_graphemes(accent.clone(), Snap::Error), None);
+    /// assert_eq!(text.to_graphemes(accent.clone(), Snap::Left), Some(IdxRange::new(0, 0)));
+    /// assert!(text.to_graphemes(accent, Snap::Right).is_some_and(|range| range.is_empty()));
+    /// ```
+    ///
+    /// [`Mask`]: crate::Mask
+    /// [`try_to_graphemes`]: Self::try_to_graphemes
+    pub fn to_graphemes<U: TextUnit>(
+        &self,
+        range: impl Into<IdxRange<U>>,
+        snap: Snap,
+    ) -> Option<IdxRange> {
+        self.try_to_graphemes(range, snap).ok()
+    }
+
+    /// Checked version of [`to_graphemes`]. Returns [`Error::IndexOutOfBounds`] if range is out
+    /// of bounds, e.g. offsets are stale, and [`Error::NotGraphemeBoundary`] if snapping fails
+    /// with [`Snap::Error`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{Snap, Utf16Idx}, Error, Text};
+    /// let text = Text::from("𝄞!");
+    /// assert_eq!(
+    ///     text.try_to_graphemes(Utf16Idx(1)..Utf16Idx(3), Snap::Error),
+    ///     Err(Error::NotGraphemeBoundary { offset: 1 })
+    /// );
+    /// assert_eq!(
+    ///     text.try_to_graphemes(Utf16Idx(2)..Utf16Idx(5), Snap::Left),
+    ///     Err(Error::IndexOutOfBounds { idx: 5, len: 3 })
+    /// );
+    /// ```
+    ///
+    /// [`to_graphemes`]: Self::to_graphemes
+    pub fn try_to_graphemes<U: TextUnit>(
+        &self,
+        range: impl Into<IdxRange<U>>,
+        snap: Snap,
+    ) -> Result<IdxRange, Error> {
+        let range = range.into();
+        let units = self.units::<U>();
+
+        let grapheme = |idx| {
+            let byte = snap.apply(idx, units, |idx| self.unit_byte::<U>(idx))?;
+            let grapheme = self.byte_unit::<GraphemeIdx>(byte, snap);
+            grapheme.map_err(|_| Error::NotGraphemeBoundary { offset: idx })
+        };
+
+        let start = grapheme(range.start)?;
+        let end = match range.end {
+            usize::MAX => self.grapheme_count(),
+            end => grapheme(end + 1)?,
+        };
+
+        Ok(exclusive(start, end))
+    }
+
+    /// Converts range of graphemes into range of any other [unit](crate::unit), e.g. to report
+    /// position of styled graphemes to LSP server. It is reverse of [`to_graphemes`]. Unbounded
+    /// end means the end of the text.
+    ///
+    /// Returns `None` if range is out of bounds. See [`try_from_graphemes`] for checked version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{ByteIdx, CharIdx, Utf16Idx}, IdxRange, Text};
+    /// let text = Text::from("y\u{0301}es 𝄞!");
+    /// assert_eq!(text.from_graphemes(0..3), Some(IdxRange::from(ByteIdx(0)..ByteIdx(5))));
+    /// assert_eq!(text.from_graphemes(4..), Some(IdxRange::from(Utf16Idx(5)..Utf16Idx(8))));
+    /// assert_eq!(text.from_graphemes(4..), Some(IdxRange::from(CharIdx(5)..CharIdx(7))));
+    /// assert_eq!(text.from_graphemes::<ByteIdx>(4..10), None);
+    /// ```
+    ///
+    /// [`to_graphemes`]: Self::to_graphemes
+    /// [`try_from_graphemes`]: Self::try_from_graphemes
+    pub fn from_graphemes<U: TextUnit>(&self, range: impl Into<IdxRange>) -> Option<IdxRange<U>> {
+        self.try_from_graphemes(range).ok()
+    }
+
+    /// Checked version of [`from_graphemes`]. Returns [`Error::IndexOutOfBounds`] if range is
+    /// out of bounds.
+    ///
+    /// [`from_graphemes`]: Self::from_graphemes
+    pub fn try_from_graphemes<U: TextUnit>(
+        &self,
+        range: impl Into<IdxRange>,
+    ) -> Result<IdxRange<U>, Error> {
+        let range = range.into();
+        let start = self.try_convert::<_, U>(GraphemeIdx(range.start))?.into();
+        let end = match range.end {
+            usize::MAX => self.units::<U>(),
+            end => self.try_convert::<_, U>(GraphemeIdx(end + 1))?.into(),
+        };
+
+        Ok(exclusive(start, end))
+    }
+
+    /// Returns terminal column where grapheme `idx` starts in its line. Wide graphemes occupy
+    /// two columns and control characters do not occupy any columns. Index equal to grapheme
+    /// count gives column after the last grapheme.
+    ///
+    /// Returns `None` if `idx` is out of bounds. See [`try_col`] for checked version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{Col, GraphemeIdx}, Text};
+    /// let text = Text::from("すし\nab");
+    /// assert_eq!(text.col(GraphemeIdx(1)), Some(Col(2)));
+    /// assert_eq!(text.col(GraphemeIdx(4)), Some(Col(1)));
+    /// assert_eq!(text.col(GraphemeIdx(6)), None);
+    /// ```
+    ///
+    /// [`try_col`]: Self::try_col
+    pub fn col(&self, idx: GraphemeIdx) -> Option<Col> {
+        self.try_col(idx).ok()
+    }
+
+    /// Checked version of [`col`]. Returns [`Error::IndexOutOfBounds`] if `idx` is out of
+    /// bounds.
+    ///
+    /// [`col`]: Self::col
+    pub fn try_col(&self, idx: GraphemeIdx) -> Result<Col, Error> {
+        let s = self.as_str();
+        let byte = self.try_convert::<_, ByteIdx>(idx)?.0;
+        let line_start = s[..byte].rfind('\n').map_or(0, |i| i + 1);
+        Ok(Col(raw::width(&s[line_start..byte])))
+    }
+
+    /// Returns grapheme which occupies column `col` of line `line`. It is reverse of [`col`].
+    ///
+    /// Returns `None` if there is no such line or `col` is after the end of the line. See
+    /// [`try_grapheme_at`] for checked version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{unit::{Col, GraphemeIdx}, Text};
+    /// let text = Text::from("すし\nab");
+    /// assert_eq!(text.grapheme_at(0, Col(3)), Some(GraphemeIdx(1)));
+    /// assert_eq!(text.grapheme_at(1, Col(1)), Some(GraphemeIdx(4)));
+    /// assert_eq!(text.grapheme_at(1, Col(2)), None);
+    /// ```
+    ///
+    /// [`col`]: Self::col
+    /// [`try_grapheme_at`]: Self::try_grapheme_at
+    pub fn grapheme_at(&self, line: usize, col: Col) -> Option<GraphemeIdx> {
+        self.try_grapheme_at(line, col).ok()
+    }
+
+    /// Checked version of [`grapheme_at`]. Returns [`Error::IndexOutOfBounds`] with `line` and
+    /// count of lines if there is no such line and with `col` and width of the line if `col` is
+    /// after the end of the line.
+    ///
+    /// [`grapheme_at`]: Self::grapheme_at
+    pub fn try_grapheme_at(&self, line: usize, col: Col) -> Result<GraphemeIdx, Error> {
+        let mut lines = self.as_str().split_inclusive('\n');
+        let mut before = 0;
+        for skipped in lines.by_ref().take(line) {
+            before += skipped.graphemes(true).count();
+        }
+        let Some(current) = lines.next() else {
+            let len = self.as_str().split_inclusive('\n').count();
+            return Err(Error::IndexOutOfBounds { idx: line, len });
+        };
+
+        let mut start = 0;
+        for (i, g) in current.graphemes(true).enumerate() {
+            let end = start + raw::width(g);
+            if (start..end).contains(&col.0) {
+                return Ok(GraphemeIdx(before + i));
+            }
+            start = end;
+        }
+        Err(Error::IndexOutOfBounds { idx: col.0, len: start })
+    }
+
+    /// Gets an iterator over styled runs of graphemes of this [`Text`]. It is the same as
+    /// [`Mask::iter_bounded`] with [`grapheme count`], so the last run ends at the last grapheme.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Text, Style, Color, IdxRange};
+    /// let mut text = Text::from("hello");
+    /// text.mask_mut().add(0..2, Style::new().fg(Color::Red));
+    ///
+    /// let runs: Vec<_> = text.mask_runs().map(|(range, _)| range).collect();
+    /// assert_eq!(runs, vec![IdxRange::new(0, 1), IdxRange::new(2, 4)]);
+    /// ```
+    ///
+    /// [`grapheme count`]: Self::grapheme_count
+    pub fn mask_runs(&self) -> Bounded<'_> {
+        self.mask.iter_bounded(self.grapheme_count())
+    }
+
+    /// Gets an iterator over ranges of graphemes of `new` text which look different than in `old`
+    /// text, so a renderer can repaint only them.
+    ///
+    /// Graphemes of unchanged text are compared like in [`Mask::diff`]. Changed graphemes are
+    /// returned with `None` old style. Graphemes after the change move to other cells, so the
+    /// rest of the line with the change is returned as changed too, or the rest of the text when
+    /// the change adds or removes lines. Graphemes after that are compared with graphemes of
+    /// `old` text which have the same offset from the end.
+    ///
+    /// Only graphemes of `new` text are returned: when `new` text is shorter, cells after its
+    /// end should be cleared by the renderer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Text, Style, Color, IdxRange};
+    /// let red = Style::new().fg(Color::Red);
+    /// let mut old = Text::from("let x = 1;\nlet y = 2;");
+    /// old.mask_mut().add(4..5, red);
+    ///
+    /// let mut new = Text::from("let xs = 1;\nlet y = 2;");
+    /// new.mask_mut().add(4..6, red);
+    /// new.mask_mut().add(16..17, red);
+    ///
+    /// let changed: Vec<_> = Text::diff(&old, &new).map(|(range, old, _)| (range, old)).collect();
+    /// assert_eq!(
+    ///     changed,
+    ///     vec![
+    ///         (IdxRange::new(5, 5), None),
+    ///         (IdxRange::new(6, 11), None),
+    ///         (IdxRange::new(16, 16), Some(&Style::default())),
+    ///     ]
+    /// );
+    /// ```
+    pub fn diff<'d>(old: &'d Text<'_>, new: &'d Text<'_>) -> Diff<'d> {
+        let old_graphemes: Vec<_> = old.as_str().graphemes(true).collect();
+        let new_graphemes: Vec<_> = new.as_str().graphemes(true).collect();
+        let (old_len, new_len) = (old_graphemes.len(), new_graphemes.len());
+
+        let prefix = old_graphemes.iter().zip(&new_graphemes).take_while(|(a, b)| a == b).count();
+        let suffix = old_graphemes[prefix..]
+            .iter()
+            .rev()
+            .zip(new_graphemes[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut items: Vec<_> = Mask::diff(&old.mask, &new.mask)
+            .take_while(|(range, ..)| range.start < prefix)
+            .map(|(range, old, new)| {
+                (IdxRange::new(range.start, range.end.min(prefix - 1)), old, new)
+            })
+            .collect();
+        if prefix == new_len && prefix == old_len {
+            return Diff { inner: items.into_iter() };
+        }
+
+        let is_newline = |grapheme: &&str| grapheme.ends_with('\n');
+        let old_lines =
+            old_graphemes[prefix..old_len - suffix].iter().filter(|g| is_newline(g)).count();
+        let new_lines =
+            new_graphemes[prefix..new_len - suffix].iter().filter(|g| is_newline(g)).count();
+        let changed_end = match new_graphemes[new_len - suffix..].iter().position(is_newline) {
+            Some(pos) if old_lines == new_lines => new_len - suffix + pos + 1,
+            _ => new_len,
+        };
+        if changed_end > prefix {
+            let changed = IdxRange::new(prefix, changed_end - 1);
+            items.extend(new.mask.range(changed).map(|(range, style)| (range, None, style)));
+        }
+
+        if changed_end < new_len {
+            // Styles of `old` text moved to indexes of `new` text
+            let offset = new_len as isize - old_len as isize;
+            let moved: Vec<_> = old
+                .mask
+                .range(changed_end + old_len - new_len..old_len)
+                .map(|(range, style)| {
+                    (
+                        range.checked_shift(offset).expect("Unchanged suffix is inside new text"),
+                        style,
+                    )
+                })
+                .collect();
+            let unchanged =
+                Mask::diff_runs(moved.into_iter(), new.mask.range(changed_end..new_len));
+            items.extend(unchanged);
+        }
+
+        Diff { inner: items.into_iter() }
+    }
+
+    /// Returns mutable reference to [`Links`] attached to this [`Text`].
+    pub fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+
+    /// Returns layer of attributes of type `T` if it was created by [`layer_mut`].
+    ///
+    /// [`layer_mut`]: Self::layer_mut
+    pub fn layer<T: Attr + Debug + Send + Sync + 'static>(&self) -> Option<&RangeAttrs<T>> {
+        self.layers.iter().find_map(|layer| layer.as_any().downcast_ref())
+    }
+
+    /// Returns mutable reference to layer of attributes of type `T`. Empty layer is created if
+    /// it does not exist yet. Every type has its own layer, so wrap attributes in newtypes to
+    /// keep several layers of the same type.
+    ///
+    /// Like [`Mask`] layers follow [`splice`] and [`insert`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Text, IdxRange};
+    /// #[derive(Debug, Clone, Copy, PartialEq)]
+    /// struct DiagnosticId(u32);
+    ///
+    /// let mut text = Text::from("let x = y;");
+    /// text.layer_mut::<Option<DiagnosticId>>().replace(8..9, Some(DiagnosticId(1)));
+    /// text.splice(4..5, "value");
+    ///
+    /// let diagnostics = text.layer::<Option<DiagnosticId>>().unwrap();
+    /// assert_eq!(diagnostics[12], Some(DiagnosticId(1)));
+    /// ```
+    ///
+    /// [`splice`]: Self::splice
+    /// [`insert`]: Self::insert
+    pub fn layer_mut<T: Attr + Debug + Send + Sync + 'static>(&mut self) -> &mut RangeAttrs<T> {
+        let pos = match self.layers.iter().position(|layer| layer.as_any().is::<RangeAttrs<T>>()) {
+            Some(pos) => pos,
+            None => {
+                self.layers.push(Box::new(RangeAttrs::<T>::new()));
+                self.layers.len() - 1
+            }
+        };
+        self.layers[pos].as_any_mut().downcast_mut().expect("Layer has type of attributes")
+    }
+
+    /// Removes layer of attributes of type `T` and returns it.
+    pub fn remove_layer<T: Attr + Debug + Send + Sync + 'static>(
+        &mut self,
+    ) -> Option<RangeAttrs<T>> {
+        let pos = self.layers.iter().position(|layer| layer.as_any().is::<RangeAttrs<T>>())?;
+        self.layers.remove(pos).into_any().downcast().ok().map(|layer| *layer)
+    }
+
     /// Modify text in place with a given closure. Closure can return any value.
+    ///
+    /// Cached metrics ([`width`], [`height`] and [`grapheme count`]) are invalidated and the
+    /// whole text is measured again on the next query. [`splice`] and [`insert`] measure again
+    /// only lines around the edit, so prefer them for large texts.
+    ///
+    /// [`width`]: Self::width
+    /// [`height`]: Self::height
+    /// [`grapheme count`]: Self::grapheme_count
+    /// [`splice`]: Self::splice
+    /// [`insert`]: Self::insert
     pub fn modify<F, R>(&mut self, f: F) -> R
     where
         F: FnOnce(&mut String) -> R,
     {
         self.raw.modify(f)
     }
+
+    /// Moves the string to shared storage, so clones of this [`Text`] do not copy it. The string
+    /// is copied once by this call and on the first modification of a shared string. Short inline
+    /// strings are not moved, because they are copied without allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::Text;
+    /// let mut text = Text::from("large document\n".repeat(100));
+    /// text.share();
+    ///
+    /// let clone = text.clone();
+    /// assert_eq!(clone.as_str().as_ptr(), text.as_str().as_ptr());
+    /// ```
+    pub fn share(&mut self) {
+        self.raw.share();
+    }
+
+    /// Replaces graphemes in `range` with `with`. Styles, links and attributes of all [`layers`]
+    /// of graphemes after the `range` are moved together with graphemes. Graphemes from `with`
+    /// get [`default`] attributes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` starts after the end of the text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Text, Style, Color};
+    /// let mut text = Text::from("let x = 1;");
+    /// text.mask_mut().add(8..9, Style::new().fg(Color::Red));
+    ///
+    /// text.splice(4..5, "value");
+    /// assert_eq!(text.as_str(), "let value = 1;");
+    /// assert_eq!(text.mask()[12], Style::new().fg(Color::Red));
+    /// ```
+    ///
+    /// [`layers`]: Self::layer_mut
+    /// [`default`]: Default::default
+    pub fn splice(&mut self, range: impl Into<IdxRange>, with: &str) {
+        let range = range.into();
+        self.edit(range.start, range.len(), with);
+    }
+
+    /// Checked version of [`splice`]. Returns the same errors as [`check_range`], so unlike
+    /// [`splice`] it fails if `range` ends after the end of the text. Text is not changed on
+    /// error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Error, Text};
+    /// let mut text = Text::from("let x = 1;");
+    /// assert_eq!(text.try_splice(4..5, "value"), Ok(()));
+    /// assert_eq!(text.try_splice(20.., ""), Err(Error::IndexOutOfBounds { idx: 20, len: 14 }));
+    /// assert_eq!(text.as_str(), "let value = 1;");
+    /// ```
+    ///
+    /// [`splice`]: Self::splice
+    /// [`check_range`]: Self::check_range
+    pub fn try_splice(&mut self, range: impl RangeBounds<usize>, with: &str) -> Result<(), Error> {
+        let (start, end) = self.check_bounds(range)?;
+        self.edit(start, end - start, with);
+        Ok(())
+    }
+
+    /// Inserts `s` before grapheme with index `idx`. See [`splice`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is greater than [`grapheme count`].
+    ///
+    /// [`splice`]: Self::splice
+    /// [`grapheme count`]: Self::grapheme_count
+    pub fn insert(&mut self, idx: usize, s: &str) {
+        self.edit(idx, 0, s);
+    }
+
+    /// Checked version of [`insert`]. Returns [`Error::IndexOutOfBounds`] if `idx` is greater
+    /// than [`grapheme count`]. Text is not changed on error.
+    ///
+    /// [`insert`]: Self::insert
+    /// [`grapheme count`]: Self::grapheme_count
+    pub fn try_insert(&mut self, idx: usize, s: &str) -> Result<(), Error> {
+        self.check_idx(idx)?;
+        self.edit(idx, 0, s);
+        Ok(())
+    }
+
+    /// Replaces bytes in `range` with `with` like [`String::replace_range`], but attributes of
+    /// graphemes are moved like in [`splice`]. It is useful for edits which come as byte offsets,
+    /// e.g. from regex replacements.
+    ///
+    /// Returns [`Error::InvalidRange`] if `range` starts after its end,
+    /// [`Error::IndexOutOfBounds`] if `range` is out of bounds and [`Error::NotGraphemeBoundary`]
+    /// if `range` does not start or end at a grapheme boundary. Text is not changed on error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, Error, Style, Text};
+    /// let mut text = Text::from("let x = 1;");
+    /// text.mask_mut().add(8..9, Style::new().fg(Color::Red));
+    ///
+    /// text.replace_range(4..5, "value")?;
+    /// assert_eq!(text.as_str(), "let value = 1;");
+    /// assert_eq!(text.mask()[12], Style::new().fg(Color::Red));
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// [`splice`]: Self::splice
+    pub fn replace_range(&mut self, range: Range<usize>, with: &str) -> Result<(), Error> {
+        if range.start > range.end {
+            return Err(Error::InvalidRange(BoundsError::StartAfterEnd));
+        }
+        let start = self.try_convert::<_, GraphemeIdx>(ByteIdx(range.start))?.0;
+        let end = self.try_convert::<_, GraphemeIdx>(ByteIdx(range.end))?.0;
+        self.edit(start, end - start, with);
+        Ok(())
+    }
+
+    /// Checks that `range` is a valid range of graphemes of this text, so it can be passed to
+    /// methods of [`Mask`], [`Links`] or [`layers`] which do not know length of the text.
+    ///
+    /// Returns [`Error::InvalidRange`] if `range` can not be represented by [`IdxRange`] or starts
+    /// after its end and [`Error::IndexOutOfBounds`] if `range` starts after the end of the text
+    /// or ends after the last grapheme. Unbounded end is clamped to the last grapheme. Empty ranges
+    /// like `3..3` are valid, though `0..0` is returned as `IdxRange::new(1, 0)` because inclusive
+    /// range can't end before `0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{Color, Error, IdxRange, Style, Text};
+    /// let mut text = Text::from("hello");
+    /// assert_eq!(text.check_range(1..), Ok(IdxRange::new(1, 4)));
+    /// assert_eq!(text.check_range(3..9), Err(Error::IndexOutOfBounds { idx: 8, len: 5 }));
+    ///
+    /// let range = text.check_range(1..3)?;
+    /// text.mask_mut().add(range, Style::new().fg(Color::Red));
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// [`layers`]: Self::layer_mut
+    pub fn check_range(&self, range: impl RangeBounds<usize>) -> Result<IdxRange, Error> {
+        let (start, end) = self.check_bounds(range)?;
+        Ok(exclusive(start, end))
+    }
+
+    /// Checks `range` like [`check_range`](Self::check_range), but returns exclusive bounds.
+    /// Unlike [`IdxRange`] they can describe an empty range at the start of the text, e.g. `0..0`.
+    fn check_bounds(&self, range: impl RangeBounds<usize>) -> Result<(usize, usize), Error> {
+        let len = self.grapheme_count();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).ok_or(BoundsError::StartOverflow)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&usize::MAX) | Bound::Unbounded => len.max(start),
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+        };
+
+        if start > end {
+            return Err(BoundsError::StartAfterEnd.into());
+        }
+        self.check_idx(start)?;
+        if end > len {
+            return Err(Error::IndexOutOfBounds { idx: end - 1, len });
+        }
+        Ok((start, end))
+    }
+
+    /// Returns count of units `U` in the text.
+    fn units<U: TextUnit>(&self) -> usize {
+        if U::GRAPHEME {
+            return self.grapheme_count();
+        }
+        let s = self.as_str();
+        U::from_byte(s, s.len()).expect("End of string is a boundary of any unit")
+    }
+
+    /// Returns byte offset where unit `U` with index `idx` starts. Graphemes are located through
+    /// cached line metrics, so only their line is scanned.
+    fn unit_byte<U: TextUnit>(&self, idx: usize) -> Option<usize> {
+        if U::GRAPHEME {
+            return self.raw.grapheme_byte(idx);
+        }
+        U::to_byte(self.as_str(), idx)
+    }
+
+    /// Returns index of unit `U` which starts at byte offset `byte`. Offset inside of a unit is
+    /// moved according to `snap`.
+    fn byte_unit<U: TextUnit>(&self, byte: usize, snap: Snap) -> Result<usize, Error> {
+        let s = self.as_str();
+        if !U::GRAPHEME {
+            return snap.apply(byte, s.len(), |byte| U::from_byte(s, byte));
+        }
+        if byte > s.len() {
+            return Err(Error::IndexOutOfBounds { idx: byte, len: s.len() });
+        }
+        self.raw.byte_grapheme(byte, snap).ok_or(Error::NotGraphemeBoundary { offset: byte })
+    }
+
+    /// Returns [`Error::IndexOutOfBounds`] if `idx` is greater than grapheme count.
+    fn check_idx(&self, idx: usize) -> Result<(), Error> {
+        let len = self.grapheme_count();
+        if idx > len {
+            return Err(Error::IndexOutOfBounds { idx, len });
+        }
+        Ok(())
+    }
+
+    fn edit(&mut self, start: usize, removed: usize, with: &str) {
+        let count = self.grapheme_count();
+        assert!(start <= count, "Grapheme index {start} is out of bounds of {count} graphemes");
+        let removed = removed.min(count - start);
+
+        let from = self.raw.grapheme_byte(start).expect("Start is not after the end of text");
+        let to = self.raw.grapheme_byte(start + removed).expect("Removed graphemes are in text");
+        self.raw.replace_range(from..to, with);
+
+        // Graphemes of `with` can be joined with neighbours, so count them in the whole text
+        let inserted = (self.grapheme_count() + removed).saturating_sub(count);
+        self.mask.splice_at(start, removed, inserted);
+        self.links.splice_at(start, removed, inserted);
+        for layer in &mut self.layers {
+            layer.splice_at(start, removed, inserted);
+        }
+    }
+}
+
+/// Creates inclusive range from `start` and exclusive `end`. Range is empty if `end` is not
+/// greater than `start`.
+fn exclusive<U: Unit>(start: usize, end: usize) -> IdxRange<U> {
+    match end.checked_sub(1) {
+        Some(last) if start < end => IdxRange::from_units(start.into(), last.into()),
+        _ => {
+            let start = start.max(1);
+            IdxRange::from_units(start.into(), (start - 1).into())
+        }
+    }
 }
 
-impl From<&'static str> for Text {
-    /// Converts a `&'static str` into [`Text`].
+impl<'a> From<&'a str> for Text<'a> {
+    /// Converts a `&str` into [`Text`] which borrows it.
     ///
     /// No heap allocation is performed, and the string is not copied.
-    fn from(s: &'static str) -> Self {
+    fn from(s: &'a str) -> Self {
         Self { raw: s.into(), ..Default::default() }
     }
 }
 
-impl From<String> for Text {
+impl From<String> for Text<'_> {
     /// Converts the given [`String`] into [`Text`].
     ///
-    /// No heap allocation is performed, and the string is not copied.
+    /// No heap allocation is performed. Strings up to 23 bytes are copied inline and the buffer
+    /// of `s` is freed, longer strings are not copied.
     fn from(s: String) -> Self {
         Self { raw: s.into(), ..Default::default() }
     }
 }
 
-impl From<char> for Text {
-    /// Converts [`char`] into [`Text`] with dynamic allocation an owned [`String`].
+impl From<Arc<str>> for Text<'_> {
+    /// Converts the given shared string into [`Text`]. Clones of the text share the string, see
+    /// [`Text::share`].
+    ///
+    /// No heap allocation is performed, and the string is not copied.
+    fn from(s: Arc<str>) -> Self {
+        Self { raw: s.into(), ..Default::default() }
+    }
+}
+
+impl From<char> for Text<'_> {
+    /// Converts [`char`] into [`Text`] without dynamic allocation.
     fn from(c: char) -> Self {
-        String::from(c).into()
+        Self { raw: c.into(), ..Default::default() }
+    }
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = (IdxRange, Option<&'a Style>, &'a Style);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> FusedIterator for Diff<'a> {}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = (IdxRange, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.inner.next()?;
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+
+        let start = self.start;
+        self.start += line.graphemes(true).count();
+        Some((exclusive(start, start + content.graphemes(true).count()), content))
+    }
+}
+
+impl<'a> FusedIterator for Lines<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{link::Hyperlink, Color, Style};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Fold(bool);
+
+    #[rstest]
+    #[case::replace("hello world", IdxRange::new(6, 10), "there", "hello there")]
+    #[case::insert_at_end("hello", IdxRange::new(5, 4), " world", "hello world")]
+    #[case::remove_combining("y\u{0301}es", IdxRange::new(0, 0), "", "es")]
+    #[case::remove_after_end("abc", IdxRange::from(1..), "", "a")]
+    fn splice_string(
+        #[case] text: &'static str,
+        #[case] range: IdxRange,
+        #[case] with: &str,
+        #[case] expected: &str,
+    ) {
+        let mut text = Text::from(text);
+        text.splice(range, with);
+        assert_eq!(text.as_str(), expected);
+    }
+
+    #[test]
+    fn attributes_follow_edits() {
+        let red = Style::new().fg(Color::Red);
+        let link = Hyperlink::new("https://example.com");
+
+        let mut text = Text::from("fn main() {}");
+        text.mask_mut().add(3..7, red);
+        text.links_mut().add(3..7, link.clone());
+        text.layer_mut::<Option<Fold>>().replace(10..12, Some(Fold(true)));
+
+        text.insert(0, "pub ");
+        text.splice(7..=10, "run");
+
+        assert_eq!(text.as_str(), "pub fn run() {}");
+        assert_eq!(text.mask().first_where(|s| *s == red), None);
+        assert_eq!(text.links().iter().next(), None);
+        let folds = text.layer::<Option<Fold>>().unwrap();
+        assert_eq!(folds.first_where(Option::is_some).unwrap().0, IdxRange::new(13, 14));
+
+        text.insert(3, "\u{0301}");
+        assert_eq!(text.grapheme_count(), 15);
+        assert_eq!(text.layer::<Option<Fold>>().unwrap()[13], Some(Fold(true)));
+    }
+
+    #[rstest]
+    #[case::equal("ab\ncd", "ab\ncd", vec![((1, 1), true)])]
+    #[case::append("ab", "abc", vec![((1, 1), true), ((2, 2), false)])]
+    #[case::remove_at_end("abc", "ab", vec![((1, 1), true)])]
+    #[case::same_line("ab\ncd\nef", "ab\nxd\nef", vec![((1, 1), true), ((3, 5), false)])]
+    #[case::new_line("ab\ncd\nef", "ab\nc\nd\nef", vec![((1, 1), true), ((4, 8), false)])]
+    #[case::removed_line("a\nb\nc", "a\nc", vec![((1, 1), true), ((2, 2), false)])]
+    #[case::clear("ab\ncd", "", vec![])]
+    fn diff_text(
+        #[case] old: &'static str,
+        #[case] new: &'static str,
+        #[case] expected: Vec<((usize, usize), bool)>,
+    ) {
+        let red = Style::new().fg(Color::Red);
+        let (mut old, mut new) = (Text::from(old), Text::from(new));
+        old.mask_mut().add(0..1, red);
+        new.mask_mut().add(0..2, red);
+
+        let diff: Vec<_> = Text::diff(&old, &new)
+            .map(|(range, old, _)| (range.into_tuple(), old.is_some()))
+            .collect();
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn diff_moved_styles() {
+        let red = Style::new().fg(Color::Red);
+        let (mut old, mut new) = (Text::from("a\nbc"), Text::from("xa\nbc"));
+        old.mask_mut().add(3..4, red);
+        new.mask_mut().add(3..5, red);
+
+        let diff: Vec<_> = Text::diff(&old, &new).map(|(range, ..)| range.into_tuple()).collect();
+        assert_eq!(diff, vec![(0, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn layers_by_type() {
+        let mut text = Text::from("hello");
+        text.layer_mut::<Option<Fold>>().replace(0..1, Some(Fold(false)));
+        text.layer_mut::<Option<u32>>().replace(1..2, Some(7));
+
+        assert_eq!(text.layer::<Option<Fold>>().unwrap()[0], Some(Fold(false)));
+        assert_eq!(text.layer::<Option<u32>>().unwrap()[1], Some(7));
+        assert!(text.remove_layer::<Option<u32>>().is_some());
+        assert!(text.layer::<Option<u32>>().is_none());
+        assert!(text.layer::<Option<Fold>>().is_some());
+    }
+
+    #[rstest]
+    #[case::ascii("abc", 2, Some(2))]
+    #[case::wide("すしa", 2, Some(4))]
+    #[case::second_line("ab\ncd", 4, Some(1))]
+    #[case::crlf("ab\r\ncd", 3, Some(0))]
+    #[case::end("ab", 2, Some(2))]
+    #[case::out_of_bounds("ab", 3, None)]
+    fn columns(#[case] s: &'static str, #[case] idx: usize, #[case] expected: Option<usize>) {
+        let text = Text::from(s);
+        let col = text.col(GraphemeIdx(idx));
+        assert_eq!(col, expected.map(Col));
+
+        // Column of grapheme which occupies columns maps back to the same grapheme
+        if let Some(col) = col.filter(|_| idx < text.grapheme_count()) {
+            let line = text.as_str().graphemes(true).take(idx).filter(|g| g.contains('\n')).count();
+            assert_eq!(text.grapheme_at(line, col), Some(GraphemeIdx(idx)));
+        }
+    }
+
+    #[rstest]
+    #[case::exact(ByteIdx(3)..ByteIdx(11), Snap::Error, Some(IdxRange::new(1, 1)))]
+    #[case::whole(ByteIdx(0)..ByteIdx(12), Snap::Error, Some(IdxRange::new(0, 2)))]
+    #[case::inside_char(ByteIdx(2)..ByteIdx(11), Snap::Error, None)]
+    #[case::inside_char_left(ByteIdx(2)..ByteIdx(11), Snap::Left, Some(IdxRange::new(0, 1)))]
+    #[case::inside_grapheme_left(ByteIdx(0)..ByteIdx(7), Snap::Left, Some(IdxRange::new(0, 0)))]
+    #[case::inside_grapheme_right(ByteIdx(0)..ByteIdx(7), Snap::Right, Some(IdxRange::new(0, 1)))]
+    #[case::unbounded(ByteIdx(3).., Snap::Error, Some(IdxRange::new(1, 2)))]
+    #[case::out_of_bounds(ByteIdx(0)..ByteIdx(13), Snap::Right, None)]
+    #[case::empty_left(ByteIdx(4)..ByteIdx(6), Snap::Left, Some(IdxRange::new(1, 0)))]
+    #[case::empty_right(ByteIdx(4)..ByteIdx(6), Snap::Right, Some(IdxRange::new(2, 1)))]
+    fn bytes_to_graphemes(
+        #[case] range: impl Into<IdxRange<ByteIdx>>,
+        #[case] snap: Snap,
+        #[case] expected: Option<IdxRange>,
+    ) {
+        // "ä" is one byte and two-byte combining mark, "👍🏽" is two chars of four bytes
+        let text = Text::from("a\u{0308}\u{1F44D}\u{1F3FD}.");
+        assert_eq!(text.as_str().len(), 12);
+        assert_eq!(text.to_graphemes(range, snap), expected);
+
+        if let Some(graphemes) = expected.filter(|range| !range.is_empty()) {
+            let bytes = text.from_graphemes::<ByteIdx>(graphemes).unwrap();
+            assert_eq!(text.to_graphemes(bytes, Snap::Error), Some(graphemes));
+        }
+    }
+
+    #[rstest]
+    #[case::valid(2..4, Ok(IdxRange::new(2, 3)))]
+    #[case::unbounded(2.., Ok(IdxRange::new(2, 4)))]
+    #[case::empty_at_end(5..5, Ok(IdxRange::new(5, 4)))]
+    #[case::unbounded_at_end(5.., Ok(IdxRange::new(5, 4)))]
+    #[case::after_end(2..6, Err(Error::IndexOutOfBounds { idx: 5, len: 5 }))]
+    #[case::start_after_end(6.., Err(Error::IndexOutOfBounds { idx: 6, len: 5 }))]
+    #[case::empty_at_start(0..0, Ok(IdxRange::new(1, 0)))]
+    #[case::empty_to_start(..0, Ok(IdxRange::new(1, 0)))]
+    #[case::empty_in_middle(3..3, Ok(IdxRange::new(3, 2)))]
+    #[case::start_overflow(
+        (Bound::Excluded(usize::MAX), Bound::Unbounded),
+        Err(Error::InvalidRange(BoundsError::StartOverflow))
+    )]
+    #[case::reversed(3..1, Err(Error::InvalidRange(BoundsError::StartAfterEnd)))]
+    #[case::reversed_inclusive(3..=1, Err(Error::InvalidRange(BoundsError::StartAfterEnd)))]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn checked_ranges(
+        #[case] range: impl RangeBounds<usize> + Clone,
+        #[case] expected: Result<IdxRange, Error>,
+    ) {
+        let mut text = Text::from("hello");
+        assert_eq!(text.check_range(range.clone()), expected);
+
+        let expected = expected.map(|range| {
+            let mut s = String::from("hello");
+            s.replace_range(range.to_range().unwrap(), "");
+            s
+        });
+        let result = text.try_splice(range, "").map(|_| text.as_str().to_owned());
+        assert_eq!(result, expected);
+        assert_eq!(text.as_str(), expected.as_deref().unwrap_or("hello"));
+    }
+
+    #[rstest]
+    #[case::start(0..0, "!hello")]
+    #[case::middle(2..2, "he!llo")]
+    #[case::end(5..5, "hello!")]
+    #[case::unbounded(5.., "hello!")]
+    fn checked_insert_with_splice(#[case] range: impl RangeBounds<usize>, #[case] expected: &str) {
+        let mut text = Text::from("hello");
+        assert_eq!(text.try_splice(range, "!"), Ok(()));
+        assert_eq!(text.as_str(), expected);
+    }
+
+    #[test]
+    fn checked_positions() {
+        let mut text = Text::from("すし\nab");
+
+        assert_eq!(text.try_insert(6, "!"), Err(Error::IndexOutOfBounds { idx: 6, len: 5 }));
+        assert_eq!(text.try_col(GraphemeIdx(6)), Err(Error::IndexOutOfBounds { idx: 6, len: 5 }));
+        assert_eq!(
+            text.try_grapheme_at(2, Col(0)),
+            Err(Error::IndexOutOfBounds { idx: 2, len: 2 })
+        );
+        assert_eq!(
+            text.try_grapheme_at(0, Col(4)),
+            Err(Error::IndexOutOfBounds { idx: 4, len: 4 })
+        );
+        assert_eq!(text.try_grapheme_at(1, Col(1)), Ok(GraphemeIdx(4)));
+        assert_eq!(text.try_insert(5, "!"), Ok(()));
+        assert_eq!(text.as_str(), "すし\nab!");
+        assert_eq!(text.replace_range(1..3, ""), Err(Error::NotGraphemeBoundary { offset: 1 }));
+        assert_eq!(
+            text.replace_range(7..12, ""),
+            Err(Error::IndexOutOfBounds { idx: 12, len: 10 })
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 3..0;
+        assert_eq!(
+            text.replace_range(reversed, ""),
+            Err(Error::InvalidRange(BoundsError::StartAfterEnd))
+        );
+        assert_eq!(text.replace_range(0..3, "寿"), Ok(()));
+        assert_eq!((text.as_str(), text.line_width(0)), ("寿し\nab!", Some(4)));
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        fn assert_thread_safe<T: Send + Sync>(_: &T) {}
+
+        let red = Style::new().fg(Color::Red);
+        let mut text = Text::from(Arc::<str>::from("hello"));
+        text.mask_mut().add(0..2, red);
+        text.layer_mut::<Option<u32>>().add(1..3, Some(1));
+        assert_thread_safe(&text);
+
+        let mut clone = text.clone();
+        clone.insert(0, ">");
+        clone.layer_mut::<Option<u32>>().remove(..);
+
+        assert_eq!(text.as_str(), "hello");
+        assert_eq!(text.mask(), &Mask::from([(0..2, red)]));
+        assert_eq!(text.layer::<Option<u32>>().unwrap().get(1), Some(&Some(1)));
+        assert_eq!(clone.as_str(), ">hello");
+        assert_eq!(clone.mask(), &Mask::from([(1..3, red)]));
+        assert_eq!(clone.layer::<Option<u32>>().unwrap().get(2), Some(&None));
+    }
+
+    #[test]
+    fn borrowed_text() {
+        let buffer = String::from("let x = 1;\nlet y = 2;");
+        let mut text = Text::from(&buffer[11..]);
+        text.mask_mut().add(4..5, Style::new().fg(Color::Red));
+        assert_eq!(text.as_str().as_ptr(), buffer[11..].as_ptr());
+
+        let owned = text.clone().into_owned();
+        drop(buffer);
+        assert_eq!(owned.as_str(), "let y = 2;");
+        assert_eq!(owned.mask()[4], Style::new().fg(Color::Red));
     }
 }
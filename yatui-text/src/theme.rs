@@ -0,0 +1,402 @@
+//! Named style classes resolved against a [`Theme`].
+//!
+//! Highlighters assign [`classes`] (e.g. "keyword", "error", "selection") to graphemes with
+//! [`ClassMask`] instead of concrete styles. Classes are resolved to [`Style`] only when mask is
+//! rendered, so switching theme does not require to highlight text again.
+//!
+//! Themes can be [`parsed`] from plain-text files or imported from [`base16`] schemes.
+//!
+//! [`classes`]: ClassId
+//! [`parsed`]: Theme::parse
+//! [`base16`]: Base16Scheme
+
+mod base16;
+mod file;
+
+pub use base16::Base16Scheme;
+pub use file::ThemeError;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    iter::FusedIterator,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use crate::{
+    mask::{self, RangeAttrs},
+    IdxRange, Mask, Style,
+};
+
+/// Interned name of style class. Interning is global, so the same name always has the same id and
+/// ids are cheap to copy and compare.
+///
+/// Class names are hierarchical: segments are separated by dots. A class without style in the
+/// theme falls back to its parent class, e.g. "keyword.control" falls back to "keyword".
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::theme::ClassId;
+/// let class = ClassId::new("keyword.control");
+/// assert_eq!(class, ClassId::new("keyword.control"));
+/// assert_eq!(class.name().as_ref(), "keyword.control");
+/// assert_eq!(class.parent(), Some(ClassId::new("keyword")));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClassId(u32);
+
+/// Interned class. Name and parent are computed once when the class is interned.
+struct Class {
+    name: Arc<str>,
+    parent: Option<ClassId>,
+}
+
+/// Global table of classes. Id of a class is its index in `classes`.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<Arc<str>, ClassId>,
+    classes: Vec<Class>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Default::default)
+}
+
+impl ClassId {
+    /// Returns id for class `name`.
+    pub fn new(name: &str) -> Self {
+        if let Some(id) = interner().read().expect("Interner is never poisoned").ids.get(name) {
+            return *id;
+        }
+
+        // Parent is interned first, so the lock is not held while interning it
+        let parent = name.rsplit_once('.').map(|(parent, _)| ClassId::new(parent));
+        let mut interner = interner().write().expect("Interner is never poisoned");
+        if let Some(id) = interner.ids.get(name) {
+            return *id;
+        }
+
+        let id = ClassId(u32::try_from(interner.classes.len()).expect("Too many classes"));
+        let name: Arc<str> = name.into();
+        interner.classes.push(Class { name: name.clone(), parent });
+        interner.ids.insert(name, id);
+        id
+    }
+
+    /// Returns name of the class.
+    pub fn name(&self) -> Arc<str> {
+        self.with_class(|class| class.name.clone())
+    }
+
+    /// Returns parent class: class name without the last dot separated segment.
+    pub fn parent(&self) -> Option<ClassId> {
+        self.with_class(|class| class.parent)
+    }
+
+    /// Calls `f` with the interned class of this id.
+    fn with_class<R>(&self, f: impl FnOnce(&Class) -> R) -> R {
+        let interner = interner().read().expect("Interner is never poisoned");
+        f(&interner.classes[self.0 as usize])
+    }
+}
+
+impl Debug for ClassId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ClassId").field(&self.name()).finish()
+    }
+}
+
+impl Display for ClassId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl From<&str> for ClassId {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+/// `Theme` maps [`classes`] to [`styles`].
+///
+/// Themes can extend another theme: missing classes are looked up in the parent theme. Missing
+/// class is resolved in order:
+///
+/// 1. The class itself in the theme and then in its parents.
+/// 2. Explicit [`fallback`] class of the class, if it is set.
+/// 3. Parent class in dot-separated hierarchy ("keyword.control" -> "keyword").
+/// 4. [`Style::default`] if nothing was found.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use yatui_text::{theme::Theme, Color, Style};
+/// let mut base = Theme::new();
+/// base.set("keyword", Style::new().fg(Color::Blue));
+/// base.set("error", Style::new().fg(Color::Red));
+///
+/// let mut dark = Theme::extend(Arc::new(base));
+/// dark.set("keyword", Style::new().fg(Color::BrightBlue));
+///
+/// assert_eq!(dark.resolve("keyword.control"), Style::new().fg(Color::BrightBlue));
+/// assert_eq!(dark.resolve("error"), Style::new().fg(Color::Red));
+/// assert_eq!(dark.resolve("unknown"), Style::default());
+/// ```
+///
+/// [`classes`]: ClassId
+/// [`styles`]: Style
+/// [`fallback`]: Theme::set_fallback
+#[derive(Debug, Default, Clone)]
+pub struct Theme {
+    parent: Option<Arc<Theme>>,
+    styles: HashMap<ClassId, Style>,
+    fallbacks: HashMap<ClassId, ClassId>,
+}
+
+impl Theme {
+    /// Creates empty theme.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates empty theme which inherits all styles and fallbacks of `parent`.
+    pub fn extend(parent: Arc<Theme>) -> Self {
+        Self { parent: Some(parent), ..Default::default() }
+    }
+
+    /// Returns parent theme.
+    pub fn parent(&self) -> Option<&Arc<Theme>> {
+        self.parent.as_ref()
+    }
+
+    /// Set `style` for `class`.
+    pub fn set(&mut self, class: impl Into<ClassId>, style: Style) {
+        self.styles.insert(class.into(), style);
+    }
+
+    /// Set `fallback` class which is used when `class` has no style.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{theme::Theme, Color, Style};
+    /// let mut theme = Theme::new();
+    /// theme.set("error", Style::new().fg(Color::Red));
+    /// theme.set_fallback("diagnostic.fatal", "error");
+    /// assert_eq!(theme.resolve("diagnostic.fatal"), Style::new().fg(Color::Red));
+    /// ```
+    pub fn set_fallback(&mut self, class: impl Into<ClassId>, fallback: impl Into<ClassId>) {
+        self.fallbacks.insert(class.into(), fallback.into());
+    }
+
+    /// Returns style of `class` from this theme or its parents without any fallbacks.
+    pub fn get(&self, class: impl Into<ClassId>) -> Option<Style> {
+        let class = class.into();
+        self.chain().find_map(|theme| theme.styles.get(&class).copied())
+    }
+
+    /// Returns style of `class` with respect to all fallbacks. Look at [`Theme`] for details.
+    pub fn resolve(&self, class: impl Into<ClassId>) -> Style {
+        let mut class = Some(class.into());
+        // Fallbacks can be cyclic, so limit count of steps
+        let mut steps = 0;
+
+        while let Some(current) = class {
+            if let Some(style) = self.get(current) {
+                return style;
+            }
+
+            steps += 1;
+            if steps > MAX_FALLBACK_STEPS {
+                break;
+            }
+
+            class = self.fallback(current).or_else(|| current.parent());
+        }
+
+        Style::default()
+    }
+
+    /// Returns explicit fallback of `class` from this theme or its parents.
+    fn fallback(&self, class: ClassId) -> Option<ClassId> {
+        self.chain().find_map(|theme| theme.fallbacks.get(&class).copied())
+    }
+
+    /// Iterator over `self` and all parent themes.
+    fn chain(&self) -> impl Iterator<Item = &Theme> {
+        std::iter::successors(Some(self), |theme| theme.parent.as_deref())
+    }
+}
+
+const MAX_FALLBACK_STEPS: usize = 64;
+
+/// [`ClassMask`] saves [`classes`] for specified ranges of graphemes. It is resolved to [`Mask`]
+/// with concrete styles against any [`Theme`].
+///
+/// Graphemes without class have [`default`] style.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{theme::{ClassMask, Theme}, Color, Style};
+/// let mut classes = ClassMask::new();
+/// classes.set(0..2, "keyword");
+///
+/// let mut theme = Theme::new();
+/// theme.set("keyword", Style::new().fg(Color::Blue));
+///
+/// let mask = classes.resolve(&theme);
+/// assert_eq!(mask[1], Style::new().fg(Color::Blue));
+/// assert_eq!(mask[2], Style::default());
+/// ```
+///
+/// [`classes`]: ClassId
+/// [`default`]: Style::default
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ClassMask {
+    attrs: RangeAttrs<Option<ClassId>>,
+}
+
+/// An iterator over styles of [`ClassMask`] resolved against [`Theme`].
+///
+/// The iterator element type is ([`IdxRange`], [`Style`]). Like [`Mask`] iterators it covers all
+/// indexes.
+///
+/// This struct is created by the [`iter`] method on [`ClassMask`].
+///
+/// [`iter`]: ClassMask::iter
+#[must_use = "Iterators are lazy and do nothing unless consumed"]
+pub struct Iter<'a> {
+    inner: mask::Iter<'a, Option<ClassId>>,
+    theme: &'a Theme,
+}
+
+impl ClassMask {
+    /// Create empty [`ClassMask`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `class` for specified `range`. Classes in the `range` are replaced.
+    pub fn set(&mut self, range: impl Into<IdxRange>, class: impl Into<ClassId>) {
+        self.attrs.replace(range, Some(class.into()));
+    }
+
+    /// Remove all classes for specified `range`.
+    pub fn remove(&mut self, range: impl Into<IdxRange>) {
+        self.attrs.remove(range);
+    }
+
+    /// Returns class of grapheme with index `idx`.
+    pub fn get(&self, idx: usize) -> Option<ClassId> {
+        self.attrs.get(idx).copied().flatten()
+    }
+
+    /// Gets an iterator over all ranges and their styles resolved against `theme`. Adjacent
+    /// ranges may have equal styles.
+    pub fn iter<'a>(&'a self, theme: &'a Theme) -> Iter<'a> {
+        Iter { inner: self.attrs.iter(), theme }
+    }
+
+    /// Resolves all classes against `theme` into [`Mask`].
+    pub fn resolve(&self, theme: &Theme) -> Mask {
+        self.iter(theme).collect()
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (IdxRange, Style);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (range, class) = self.inner.next()?;
+        Some((range, class.map(|class| self.theme.resolve(class)).unwrap_or_default()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> FusedIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Color, Modifier};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    fn base() -> Arc<Theme> {
+        let mut theme = Theme::new();
+        theme.set("keyword", Style::new().fg(Color::Blue));
+        theme.set("keyword.control", Style::new().fg(Color::Cyan));
+        theme.set("error", Style::new().fg(Color::Red));
+        theme.set_fallback("diagnostic.error", "error");
+        Arc::new(theme)
+    }
+
+    #[test]
+    fn intern_classes() {
+        let a = ClassId::new("theme.tests.a");
+        assert_eq!(a, ClassId::from("theme.tests.a"));
+        assert_ne!(a, ClassId::new("theme.tests.b"));
+        assert_eq!(a.to_string(), "theme.tests.a");
+        assert_eq!(ClassId::new("theme").parent(), None);
+    }
+
+    #[rstest]
+    #[case::own("keyword", Style::new().fg(Color::BrightBlue))]
+    #[case::inherited_specific("keyword.control", Style::new().fg(Color::Cyan))]
+    #[case::hierarchy("keyword.other.deep", Style::new().fg(Color::BrightBlue))]
+    #[case::inherited("error", Style::new().fg(Color::Red))]
+    #[case::inherited_fallback("diagnostic.error", Style::new().fg(Color::Red))]
+    #[case::own_fallback("selection.primary", Style::new().modifier(Modifier::REVERSED))]
+    #[case::missing("diagnostic", Style::default())]
+    fn resolve_classes(#[case] class: &str, #[case] expected: Style) {
+        let mut dark = Theme::extend(base());
+        dark.set("keyword", Style::new().fg(Color::BrightBlue));
+        dark.set("highlight", Style::new().modifier(Modifier::REVERSED));
+        dark.set_fallback("selection", "highlight");
+
+        assert_eq!(dark.resolve(class), expected);
+    }
+
+    #[test]
+    fn resolve_cyclic_fallbacks() {
+        let mut theme = Theme::new();
+        theme.set_fallback("a", "b");
+        theme.set_fallback("b", "a");
+        assert_eq!(theme.resolve("a"), Style::default());
+    }
+
+    #[test]
+    fn class_mask_follows_theme() {
+        let mut classes = ClassMask::new();
+        classes.set(2..4, "keyword");
+        classes.set(4..=4, "error");
+        classes.set(6..8, "keyword.control");
+
+        let items: Vec<_> = classes.iter(&base()).map(|(r, s)| (r.into_tuple(), s)).collect();
+        assert_eq!(
+            items,
+            vec![
+                ((0, 1), Style::default()),
+                ((2, 3), Style::new().fg(Color::Blue)),
+                ((4, 4), Style::new().fg(Color::Red)),
+                ((5, 5), Style::default()),
+                ((6, 7), Style::new().fg(Color::Cyan)),
+                ((8, usize::MAX), Style::default()),
+            ]
+        );
+
+        let mut light = Theme::extend(base());
+        light.set("keyword", Style::new().fg(Color::Black));
+        let mask = classes.resolve(&light);
+        assert_eq!(mask[2], Style::new().fg(Color::Black));
+        assert_eq!(mask[6], Style::new().fg(Color::Cyan));
+        assert_eq!(mask[5], Style::default());
+    }
+}
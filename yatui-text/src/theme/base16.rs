@@ -0,0 +1,272 @@
+use crate::{
+    style::parse_hex,
+    theme::{Theme, ThemeError},
+    Color, Modifier, Palette, Style,
+};
+
+/// Color scheme in [base16](https://github.com/tinted-theming/home) or
+/// [base24](https://github.com/tinted-theming/base24) format.
+///
+/// Both the classic format with `baseXX` keys at the top level and the newer one with `palette`
+/// section are supported. Only flat `key: value` YAML is accepted, which is enough for schemes.
+///
+/// # Examples
+///
+/// ```
+/// # use yatui_text::{theme::Base16Scheme, Color, Style};
+/// let yaml = r#"
+/// scheme: "Example"
+/// author: "Someone"
+/// base00: "000000"
+/// base01: "111111"
+/// base02: "222222"
+/// base03: "333333"
+/// base04: "444444"
+/// base05: "555555"
+/// base06: "666666"
+/// base07: "777777"
+/// base08: "880000"
+/// base09: "999900"
+/// base0A: "aaaa00"
+/// base0B: "00bb00"
+/// base0C: "00cccc"
+/// base0D: "0000dd"
+/// base0E: "ee00ee"
+/// base0F: "ff8800"
+/// "#;
+///
+/// let scheme = Base16Scheme::parse(yaml).unwrap();
+/// assert_eq!(scheme.name, "Example");
+/// assert_eq!(scheme.theme().resolve("keyword"), Style::new().fg(Color::Rgb(0xee, 0, 0xee)));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Base16Scheme {
+    pub name: String,
+    pub author: String,
+    /// Colors `base00`..`base0F` and `base10`..`base17` for base24 schemes.
+    pub colors: Vec<Color>,
+}
+
+/// Classes which are set by [`Base16Scheme::theme`] with their colors according to base16
+/// styling guidelines: (class, fg, bg).
+const CLASSES: [(&str, Option<usize>, Option<usize>); 19] = [
+    ("default", Some(0x05), Some(0x00)),
+    ("ui.statusline", Some(0x04), Some(0x01)),
+    ("ui.linenr", Some(0x03), Some(0x01)),
+    ("selection", None, Some(0x02)),
+    ("search", Some(0x01), Some(0x0A)),
+    ("comment", Some(0x03), None),
+    ("variable", Some(0x08), None),
+    ("constant", Some(0x09), None),
+    ("type", Some(0x0A), None),
+    ("string", Some(0x0B), None),
+    ("escape", Some(0x0C), None),
+    ("function", Some(0x0D), None),
+    ("keyword", Some(0x0E), None),
+    ("deprecated", Some(0x0F), None),
+    ("error", Some(0x08), None),
+    ("warning", Some(0x09), None),
+    ("diff.inserted", Some(0x0B), None),
+    ("diff.deleted", Some(0x08), None),
+    ("diff.changed", Some(0x0E), None),
+];
+
+impl Base16Scheme {
+    /// Parses scheme from YAML. Scheme is base24 if it has `base10`..`base17` colors.
+    pub fn parse(yaml: &str) -> Result<Self, ThemeError> {
+        let mut name = String::new();
+        let mut author = String::new();
+        let mut colors: [Option<Color>; 24] = [None; 24];
+
+        for (idx, line) in yaml.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line == "---" {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(ThemeError::parse(line_no, "expected `key: value`"));
+            };
+            let value = scalar(value.trim());
+
+            match key.trim() {
+                "scheme" | "name" => name = value.to_string(),
+                "author" => author = value.to_string(),
+                key => {
+                    let Some(idx) = key.strip_prefix("base").and_then(color_index) else {
+                        continue;
+                    };
+                    let hex = value.strip_prefix('#').unwrap_or(value);
+                    let color = parse_hex(hex).filter(|_| hex.len() == 6).ok_or_else(|| {
+                        ThemeError::parse(line_no, format!("invalid color `{value}`"))
+                    })?;
+                    colors[idx] = Some(color);
+                }
+            }
+        }
+
+        let count = if colors[16..].iter().any(Option::is_some) { 24 } else { 16 };
+        let colors = colors[..count]
+            .iter()
+            .enumerate()
+            .map(|(idx, color)| {
+                color
+                    .ok_or_else(|| ThemeError::Incomplete(format!("missing color `base{idx:02X}`")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { name, author, colors })
+    }
+
+    /// Returns `true` for base24 scheme.
+    pub fn is_base24(&self) -> bool {
+        self.colors.len() == 24
+    }
+
+    /// Creates [`Theme`] with common classes: `default`, `selection`, `search`, `comment`,
+    /// `keyword`, `string`, `error`, `diff.inserted`, etc. Colors are assigned according to the
+    /// base16 styling guidelines. Base24 schemes use their darker backgrounds for
+    /// `ui.background.dark` and `ui.background.darker` classes.
+    pub fn theme(&self) -> Theme {
+        let mut theme = Theme::new();
+
+        for (class, fg, bg) in CLASSES {
+            let mut style = Style::new();
+            style.fg = fg.map(|idx| self.colors[idx]);
+            style.bg = bg.map(|idx| self.colors[idx]);
+            theme.set(class, style);
+        }
+        theme.set("comment", theme.resolve("comment").add_modifier(Modifier::ITALIC));
+
+        if self.is_base24() {
+            theme.set("ui.background.dark", Style::new().bg(self.colors[0x10]));
+            theme.set("ui.background.darker", Style::new().bg(self.colors[0x11]));
+        }
+
+        theme
+    }
+
+    /// Creates terminal [`Palette`] from scheme in the same way as base16-shell does. Base24
+    /// schemes have their own bright colors.
+    pub fn palette(&self) -> Palette {
+        let rgb = |idx: usize| match self.colors[idx] {
+            Color::Rgb(r, g, b) => (r, g, b),
+            _ => unreachable!("Scheme colors are always rgb"),
+        };
+        let bright = |base16: usize, base24: usize| {
+            if self.is_base24() {
+                rgb(base24)
+            } else {
+                rgb(base16)
+            }
+        };
+
+        Palette::new([
+            rgb(0x00),
+            rgb(0x08),
+            rgb(0x0B),
+            rgb(0x0A),
+            rgb(0x0D),
+            rgb(0x0E),
+            rgb(0x0C),
+            rgb(0x05),
+            rgb(0x03),
+            bright(0x08, 0x12),
+            bright(0x0B, 0x14),
+            bright(0x0A, 0x13),
+            bright(0x0D, 0x16),
+            bright(0x0E, 0x17),
+            bright(0x0C, 0x15),
+            rgb(0x07),
+        ])
+    }
+}
+
+/// Converts hex suffix of `baseXX` key into color index.
+fn color_index(suffix: &str) -> Option<usize> {
+    if suffix.len() != 2 {
+        return None;
+    }
+    usize::from_str_radix(suffix, 16).ok().filter(|idx| *idx < 24)
+}
+
+/// Returns YAML scalar without quotes and trailing comment.
+fn scalar(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(quoted) = value.strip_prefix(quote) {
+            return quoted.split_once(quote).map_or(quoted, |(value, _)| value);
+        }
+    }
+    value.split_once(" #").map_or(value, |(value, _)| value).trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn yaml(count: usize, palette_section: bool) -> String {
+        let mut yaml = String::from("system: \"base24\"\nname: 'Test'\nauthor: Me\n");
+        if palette_section {
+            yaml.push_str("palette:\n");
+        }
+        for idx in 0..count {
+            let indent = if palette_section { "  " } else { "" };
+            yaml.push_str(&format!("{indent}base{idx:02X}: \"#{0:02x}{0:02x}{0:02x}\" # c\n", idx));
+        }
+        yaml
+    }
+
+    #[test]
+    fn parse_base16() {
+        let scheme = Base16Scheme::parse(&yaml(16, false)).unwrap();
+        assert_eq!(scheme.name, "Test");
+        assert_eq!(scheme.author, "Me");
+        assert!(!scheme.is_base24());
+        assert_eq!(scheme.colors[0x0F], Color::Rgb(15, 15, 15));
+
+        let theme = scheme.theme();
+        assert_eq!(
+            theme.resolve("default"),
+            Style::new().fg(Color::Rgb(5, 5, 5)).bg(Color::Rgb(0, 0, 0))
+        );
+        assert_eq!(theme.resolve("ui.background.dark"), Style::default());
+
+        let palette = scheme.palette();
+        assert_eq!(palette.get(Color::BrightRed), Some((8, 8, 8)));
+    }
+
+    #[test]
+    fn parse_base24() {
+        let scheme = Base16Scheme::parse(&yaml(24, true)).unwrap();
+        assert!(scheme.is_base24());
+
+        let theme = scheme.theme();
+        assert_eq!(theme.resolve("ui.background.darker"), Style::new().bg(Color::Rgb(17, 17, 17)));
+        assert_eq!(scheme.palette().get(Color::BrightRed), Some((18, 18, 18)));
+    }
+
+    #[test]
+    fn parse_missing_color() {
+        let mut yaml = yaml(16, false);
+        yaml = yaml.replace("base0A", "# base0A");
+        let err = Base16Scheme::parse(&yaml).unwrap_err();
+        assert_eq!(err.to_string(), "incomplete theme: missing color `base0A`");
+    }
+
+    #[test]
+    fn parse_unquoted_values() {
+        let yaml = yaml(16, false).replace("\"#0c0c0c\" # c", "0c0c0c # comment");
+        let scheme = Base16Scheme::parse(&yaml).unwrap();
+        assert_eq!(scheme.colors[0x0C], Color::Rgb(12, 12, 12));
+    }
+
+    #[test]
+    fn parse_invalid_color() {
+        let yaml = yaml(16, false).replace("#0b0b0b", "#0b0b0");
+        let err = Base16Scheme::parse(&yaml).unwrap_err();
+        assert_eq!(err.to_string(), "line 15: invalid color `#0b0b0`");
+    }
+}
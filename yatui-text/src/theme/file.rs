@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    style::{parse_style_with, ParseError},
+    theme::Theme,
+    Color,
+};
+
+/// An error which can be returned when loading [`Theme`].
+#[derive(Debug)]
+pub enum ThemeError {
+    /// Theme file can not be read.
+    Io(io::Error),
+    /// Theme has invalid content at `line` (starting from 1).
+    Parse { line: usize, message: String },
+    /// Theme misses required content.
+    Incomplete(String),
+}
+
+impl ThemeError {
+    pub(super) fn parse(line: usize, message: impl Into<String>) -> Self {
+        Self::Parse { line, message: message.into() }
+    }
+}
+
+impl Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeError::Io(e) => write!(f, "can not read theme: {e}"),
+            ThemeError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            ThemeError::Incomplete(message) => write!(f, "incomplete theme: {message}"),
+        }
+    }
+}
+
+impl Error for ThemeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ThemeError::Io(e) => Some(e),
+            ThemeError::Parse { .. } | ThemeError::Incomplete(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ThemeError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Theme {
+    /// Parses theme from plain-text format. Every non-empty line is one of:
+    ///
+    /// * `# comment`
+    /// * `$variable = <color>` defines palette variable. Variables can be used instead of colors
+    ///   in all following lines as `$variable`.
+    /// * `class = <style>` sets style for class, look at [`Style`] for the format of styles.
+    /// * `class -> fallback` sets [`fallback`] class.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use yatui_text::{theme::Theme, Color, Modifier, Style};
+    /// let theme = Theme::parse(
+    ///     "
+    ///     ## Palette
+    ///     $accent = #ff8800
+    ///
+    ///     keyword = bold fg=$accent
+    ///     diagnostic.error = fg=red underline=$accent curly_underlined
+    ///     error -> diagnostic.error
+    ///     ",
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(
+    ///     theme.resolve("keyword"),
+    ///     Style::new().fg(Color::Rgb(255, 136, 0)).modifier(Modifier::BOLD)
+    /// );
+    /// assert_eq!(theme.resolve("error"), theme.resolve("diagnostic.error"));
+    /// ```
+    ///
+    /// ```
+    /// # use yatui_text::theme::Theme;
+    /// let err = Theme::parse("keyword = bold\nerror = fg=$missing").unwrap_err();
+    /// assert_eq!(err.to_string(), "line 2: unknown variable `$missing`");
+    /// ```
+    ///
+    /// [`Style`]: crate::Style#impl-FromStr-for-Style
+    /// [`fallback`]: Theme::set_fallback
+    pub fn parse(src: &str) -> Result<Theme, ThemeError> {
+        let mut theme = Theme::new();
+        parse_into(&mut theme, src)?;
+        Ok(theme)
+    }
+
+    /// Same as [`parse`] but created theme extends `parent`.
+    ///
+    /// [`parse`]: Self::parse
+    pub fn parse_extending(src: &str, parent: Arc<Theme>) -> Result<Theme, ThemeError> {
+        let mut theme = Theme::extend(parent);
+        parse_into(&mut theme, src)?;
+        Ok(theme)
+    }
+
+    /// Reads and [`parses`] theme from file.
+    ///
+    /// [`parses`]: Self::parse
+    pub fn load(path: impl AsRef<Path>) -> Result<Theme, ThemeError> {
+        Theme::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ThemeError;
+
+    /// Look at [`Theme::parse`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::parse(s)
+    }
+}
+
+fn parse_into(theme: &mut Theme, src: &str) -> Result<(), ThemeError> {
+    let mut variables: HashMap<&str, Color> = HashMap::new();
+
+    for (idx, line) in src.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let color = |value: &str| match value.strip_prefix('$') {
+            Some(name) => variables
+                .get(name)
+                .copied()
+                .ok_or_else(|| ParseError::new(format!("unknown variable `{value}`"))),
+            None => value.parse(),
+        };
+
+        if let Some((class, fallback)) = line.split_once("->") {
+            let (class, fallback) = (class.trim(), fallback.trim());
+            check_class(class, line_no)?;
+            check_class(fallback, line_no)?;
+            theme.set_fallback(class, fallback);
+            continue;
+        }
+
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(ThemeError::parse(line_no, "expected `name = value` or `class -> class`"));
+        };
+        let (name, value) = (name.trim(), value.trim());
+
+        if let Some(variable) = name.strip_prefix('$') {
+            check_class(variable, line_no)?;
+            let value = color(value).map_err(|e| ThemeError::parse(line_no, e.to_string()))?;
+            variables.insert(variable, value);
+        } else {
+            check_class(name, line_no)?;
+            let style = parse_style_with(value, color)
+                .map_err(|e| ThemeError::parse(line_no, e.to_string()))?;
+            theme.set(name, style);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `name` is a valid name of class or variable.
+fn check_class(name: &str, line: usize) -> Result<(), ThemeError> {
+    let valid = !name.is_empty()
+        && name.split('.').all(|segment| {
+            !segment.is_empty()
+                && segment.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ThemeError::parse(line, format!("invalid name `{name}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Modifier, Style};
+    use rstest::rstest;
+
+    #[test]
+    fn parse_theme() {
+        let theme = Theme::parse(
+            "# comment\n\
+             $red = #ff0000\n\
+             $error = $red\n\
+             \n\
+             error = fg=$error underline=$red curly_underlined\n\
+             keyword = -bold italic\n\
+             diagnostic.fatal -> error\n",
+        )
+        .unwrap();
+
+        let error = Style::new()
+            .fg(Color::Rgb(255, 0, 0))
+            .underline_color(Color::Rgb(255, 0, 0))
+            .modifier(Modifier::CURLY_UNDERLINED);
+        assert_eq!(theme.resolve("error"), error);
+        assert_eq!(theme.resolve("diagnostic.fatal"), error);
+        assert_eq!(
+            theme.resolve("keyword"),
+            Style::new().modifier(Modifier::ITALIC) - Modifier::BOLD
+        );
+    }
+
+    #[test]
+    fn parse_extending_theme() {
+        let base = Arc::new(Theme::parse("keyword = bold\nerror = fg=red").unwrap());
+        let dark = Theme::parse_extending("keyword = italic", base).unwrap();
+
+        assert_eq!(dark.resolve("keyword"), Style::new().modifier(Modifier::ITALIC));
+        assert_eq!(dark.resolve("error"), Style::new().fg(Color::Red));
+    }
+
+    #[rstest]
+    #[case::no_separator("keyword bold", 1, "expected `name = value` or `class -> class`")]
+    #[case::unknown_variable("\n\nkeyword = fg=$a", 3, "unknown variable `$a`")]
+    #[case::variable_after_use("keyword = fg=$a\n$a = red", 1, "unknown variable `$a`")]
+    #[case::invalid_variable("$a = bold", 1, "unknown color `bold`")]
+    #[case::invalid_style("# ok\nkeyword = shiny", 2, "unknown modifier `shiny`")]
+    #[case::empty_name(" = bold", 1, "invalid name ``")]
+    #[case::invalid_name("key word = bold", 1, "invalid name `key word`")]
+    #[case::empty_segment("keyword..control = bold", 1, "invalid name `keyword..control`")]
+    #[case::invalid_fallback("a -> ", 1, "invalid name ``")]
+    fn parse_invalid_theme(#[case] src: &str, #[case] line: usize, #[case] message: &str) {
+        match Theme::parse(src) {
+            Err(ThemeError::Parse { line: l, message: m }) => {
+                assert_eq!((l, m.as_str()), (line, message));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn load_missing_file() {
+        assert!(matches!(Theme::load("/nonexistent/theme"), Err(ThemeError::Io(_))));
+    }
+}
@@ -0,0 +1,254 @@
+//! Units of indexes in text.
+//!
+//! The same position in a string can be described in many ways: [`Mask`] works with graphemes,
+//! `str` APIs and regex engines return bytes, LSP servers speak UTF-16 code units and terminal
+//! works with columns. Every unit has its own newtype, so [`IdxRange`] of bytes can not be used
+//! by accident where range of graphemes is expected.
+//!
+//! Plain `usize` indexes and std ranges of `usize` are always treated as graphemes.
+//!
+//! Indexes can be converted between units with [`Text::convert`] and [`Text::col`], ranges can
+//! be converted with [`Text::to_graphemes`] and [`Text::from_graphemes`].
+//!
+//! # Examples
+//!
+//! ```compile_fail
+//! # use yatui_text::{unit::ByteIdx, IdxRange, Mask, Style};
+//! let bytes = IdxRange::from(ByteIdx(0)..ByteIdx(4));
+//! Mask::new().add(bytes, Style::default()); // Mask expects range of graphemes
+//! ```
+//!
+//! Checked APIs take std ranges, which are also implemented only by ranges of graphemes:
+//!
+//! ```compile_fail
+//! # use yatui_text::{unit::ByteIdx, IdxRange, Text};
+//! let mut text = Text::from("text");
+//! text.try_splice(IdxRange::from(ByteIdx(0)..ByteIdx(4)), "X");
+//! ```
+//!
+//! [`Mask`]: crate::Mask
+//! [`IdxRange`]: crate::IdxRange
+//! [`Text::convert`]: crate::Text::convert
+//! [`Text::col`]: crate::Text::col
+//! [`Text::to_graphemes`]: crate::Text::to_graphemes
+//! [`Text::from_graphemes`]: crate::Text::from_graphemes
+
+use std::{
+    fmt::{self, Debug, Display},
+    hash::Hash,
+    iter,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Error;
+
+/// Unit of indexes. It is implemented by newtypes of `usize`.
+pub trait Unit: Copy + Ord + Hash + Debug + Default + From<usize> + Into<usize> {}
+
+/// Unit of indexes which can be located in a string without any other context, so it can be
+/// converted to any other such unit.
+pub trait TextUnit: Unit {
+    /// Returns byte offset where unit with index `idx` starts in `s`. Index equal to count of
+    /// units is the end of `s`. Returns `None` if `idx` is out of bounds or does not start at a
+    /// char boundary.
+    fn to_byte(s: &str, idx: usize) -> Option<usize>;
+
+    /// Returns index of unit which starts at byte offset `byte` in `s`. Byte offset equal to the
+    /// length of `s` is the end of `s`. Returns `None` if `byte` is out of bounds or no unit
+    /// starts at `byte`.
+    fn from_byte(s: &str, byte: usize) -> Option<usize>;
+
+    /// `true` only for [`GraphemeIdx`]. [`Text`] locates graphemes through its cache of line
+    /// metrics instead of scanning the string with methods above.
+    ///
+    /// [`Text`]: crate::Text
+    #[doc(hidden)]
+    const GRAPHEME: bool = false;
+}
+
+macro_rules! units {
+    ($($(#[$meta:meta])* $unit:ident,)*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+            pub struct $unit(pub usize);
+
+            impl Unit for $unit {}
+
+            impl From<usize> for $unit {
+                fn from(idx: usize) -> Self {
+                    Self(idx)
+                }
+            }
+
+            impl From<$unit> for usize {
+                fn from(idx: $unit) -> Self {
+                    idx.0
+                }
+            }
+
+            impl Display for $unit {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    Display::fmt(&self.0, f)
+                }
+            }
+        )*
+    };
+}
+
+units!(
+    /// Index of grapheme (user-perceived character). It is the unit of [`Mask`].
+    ///
+    /// [`Mask`]: crate::Mask
+    GraphemeIdx,
+    /// Offset in bytes of utf-8 string.
+    ByteIdx,
+    /// Index of unicode scalar value (`char`), e.g. index in Python strings.
+    CharIdx,
+    /// Offset in utf-16 code units, e.g. position in LSP.
+    Utf16Idx,
+    /// Terminal column counted from the start of a line. Wide graphemes occupy two columns.
+    Col,
+);
+
+/// Policy for offsets which do not land on a boundary of the target unit, e.g. byte offset in the
+/// middle of a grapheme cluster or utf-16 offset between two halves of a surrogate pair.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Snap {
+    /// Move offset to the start of the unit which contains it.
+    Left,
+    /// Move offset to the start of the next unit.
+    Right,
+    /// Do not move offset and fail conversion.
+    Error,
+}
+
+impl Snap {
+    /// Calls `f` with `idx` and, if it fails, with nearest indexes in direction of snapping up to
+    /// `max`. Returns an error if `idx` is greater than `max` or it can not be snapped.
+    pub(crate) fn apply(
+        self,
+        idx: usize,
+        max: usize,
+        f: impl Fn(usize) -> Option<usize>,
+    ) -> Result<usize, Error> {
+        if idx > max {
+            return Err(Error::IndexOutOfBounds { idx, len: max });
+        }
+
+        f(idx)
+            .or_else(|| match self {
+                Snap::Left => (0..idx).rev().find_map(&f),
+                Snap::Right => (idx + 1..=max).find_map(&f),
+                Snap::Error => None,
+            })
+            .ok_or(Error::NotGraphemeBoundary { offset: idx })
+    }
+}
+
+impl TextUnit for GraphemeIdx {
+    const GRAPHEME: bool = true;
+
+    fn to_byte(s: &str, idx: usize) -> Option<usize> {
+        s.grapheme_indices(true).map(|(byte, _)| byte).chain(iter::once(s.len())).nth(idx)
+    }
+
+    fn from_byte(s: &str, byte: usize) -> Option<usize> {
+        let mut starts = s.grapheme_indices(true).map(|(byte, _)| byte).chain(iter::once(s.len()));
+        let (idx, start) = starts.enumerate().find(|(_, start)| *start >= byte)?;
+        (start == byte).then_some(idx)
+    }
+}
+
+impl TextUnit for ByteIdx {
+    fn to_byte(s: &str, idx: usize) -> Option<usize> {
+        s.is_char_boundary(idx).then_some(idx)
+    }
+
+    fn from_byte(s: &str, byte: usize) -> Option<usize> {
+        s.is_char_boundary(byte).then_some(byte)
+    }
+}
+
+impl TextUnit for CharIdx {
+    fn to_byte(s: &str, idx: usize) -> Option<usize> {
+        s.char_indices().map(|(byte, _)| byte).chain(iter::once(s.len())).nth(idx)
+    }
+
+    fn from_byte(s: &str, byte: usize) -> Option<usize> {
+        s.is_char_boundary(byte).then(|| s[..byte].chars().count())
+    }
+}
+
+impl TextUnit for Utf16Idx {
+    fn to_byte(s: &str, idx: usize) -> Option<usize> {
+        let mut utf16 = 0;
+        for (byte, c) in s.char_indices().chain(iter::once((s.len(), '\0'))) {
+            if utf16 >= idx {
+                return (utf16 == idx).then_some(byte);
+            }
+            utf16 += c.len_utf16();
+        }
+        None
+    }
+
+    fn from_byte(s: &str, byte: usize) -> Option<usize> {
+        s.is_char_boundary(byte).then(|| s[..byte].encode_utf16().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    // "y̆" is two chars in one grapheme, "𝄞" is one char of four bytes and two utf-16 units
+    const S: &str = "ay\u{0306}𝄞b";
+
+    #[rstest]
+    #[case::start(0, Some(0), Some(0), Some(0))]
+    #[case::combining(1, Some(1), Some(1), Some(1))]
+    #[case::inside_grapheme(2, None, Some(2), Some(2))]
+    #[case::wide(4, Some(2), Some(3), Some(3))]
+    #[case::inside_char(5, None, None, None)]
+    #[case::last(8, Some(3), Some(4), Some(5))]
+    #[case::end(9, Some(4), Some(5), Some(6))]
+    #[case::out_of_bounds(10, None, None, None)]
+    fn from_byte(
+        #[case] byte: usize,
+        #[case] grapheme: Option<usize>,
+        #[case] char: Option<usize>,
+        #[case] utf16: Option<usize>,
+    ) {
+        assert_eq!(GraphemeIdx::from_byte(S, byte), grapheme);
+        assert_eq!(CharIdx::from_byte(S, byte), char);
+        assert_eq!(Utf16Idx::from_byte(S, byte), utf16);
+        assert_eq!(ByteIdx::from_byte(S, byte), char.map(|_| byte));
+    }
+
+    #[rstest]
+    #[case::grapheme(GraphemeIdx::to_byte, vec![Some(0), Some(1), Some(4), Some(8), Some(9), None])]
+    #[case::char(CharIdx::to_byte, vec![Some(0), Some(1), Some(2), Some(4), Some(8), Some(9)])]
+    #[case::utf16(Utf16Idx::to_byte, vec![Some(0), Some(1), Some(2), Some(4), None, Some(8)])]
+    #[case::byte(ByteIdx::to_byte, vec![Some(0), Some(1), Some(2), None, Some(4), None])]
+    fn to_byte(#[case] f: fn(&str, usize) -> Option<usize>, #[case] expected: Vec<Option<usize>>) {
+        let bytes: Vec<_> = (0..expected.len()).map(|idx| f(S, idx)).collect();
+        assert_eq!(bytes, expected);
+    }
+
+    #[rstest]
+    #[case::exact(Snap::Error, 4, Ok(2))]
+    #[case::error(Snap::Error, 6, Err(Error::NotGraphemeBoundary { offset: 6 }))]
+    #[case::left(Snap::Left, 6, Ok(2))]
+    #[case::right(Snap::Right, 6, Ok(3))]
+    #[case::out_of_bounds(Snap::Left, 10, Err(Error::IndexOutOfBounds { idx: 10, len: 9 }))]
+    fn snap_offsets(
+        #[case] snap: Snap,
+        #[case] byte: usize,
+        #[case] expected: Result<usize, Error>,
+    ) {
+        assert_eq!(snap.apply(byte, S.len(), |byte| GraphemeIdx::from_byte(S, byte)), expected);
+    }
+}